@@ -34,7 +34,7 @@ pub fn get_data(url: &str) -> Result<WebData, Box<dyn std::error::Error>> {
     let mut data = match parse_html(&response) {
         Ok(x) => x,
         Err(e) => {
-            println!("{} の値を取得できませんでした", e);
+            eprintln!("{} の値を取得できませんでした", e);
             return Err(Box::new(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 "ページを取得できませんでした",
@@ -109,9 +109,19 @@ fn parse_html(html: &str) -> Result<WebData, String> {
     };
     data.maker = maker;
 
+    // SKUとカテゴリは、スクリプト中の変数から取得する。取得できなくてもエラーにはしない。
+    data.sku = find_script_var(html, "sku").unwrap_or_default();
+    data.category = find_script_var(html, "categoryCode").unwrap_or_default();
+
     Ok(data)
 }
 
+/// HTML中のスクリプトで `var <name> = '<value>';` と定義されている変数の値を返す。
+fn find_script_var(html: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(r"var {} = '([^']*)';", regex::escape(name))).unwrap();
+    re.captures(html).map(|c| c[1].to_string())
+}
+
 /// 価格表記をデコードする。
 fn decode_price(string: &str) -> u64 {
     if string.starts_with('￥') {
@@ -655,5 +665,8 @@ mod tests {
         assert_eq!(486, data.point);
         assert_eq!(10, data.point_ratio);
         assert_eq!("OGK KABUTO", data.maker);
+        assert_eq!(StockStatus::Limited, data.status);
+        assert_eq!("100000001007414874", data.sku);
+        assert_eq!("500000000000175000", data.category);
     }
 }
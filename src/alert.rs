@@ -0,0 +1,375 @@
+//! 製品ごとの価格アラート。
+//!
+//! アラートの条件は価格の更新のたびに評価し、条件を満たさない状態から満たす状態に変わったときに通知する。
+//! 通知した状態は保存し、条件を満たさなくなるまでは同じアラートを繰り返し通知しない。
+
+use chrono::serde::ts_seconds_option;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, PaymentProfile};
+use crate::data::ProductHistory;
+use crate::rule::{evaluate_rules, CompiledRule};
+use crate::util::yen;
+
+/// アラートの種類として指定できる名称
+pub const RULE_KINDS: &[&str] = &[
+    "price",
+    "effective",
+    "below-high",
+    "new-low",
+    "back-in-stock",
+    "point-ratio",
+];
+
+/// アラートの条件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertRule {
+    /// 価格が指定した金額以下
+    Price { target: u64 },
+    /// 実質価格が指定した金額以下
+    Effective { target: u64 },
+    /// 価格が最高値より指定した割合（%）以上安い
+    BelowHigh { percent: f64 },
+    /// 最安値を更新した
+    NewLow,
+    /// 在庫がある（お取り寄せから在庫ありになったときに通知する）
+    BackInStock,
+    /// ポイント還元率が指定した値（%）以上
+    PointRatio { at_least: u64 },
+}
+
+impl AlertRule {
+    /// `price 30000` のような単語の並びからアラートの条件を生成する。
+    pub fn parse<S: AsRef<str>>(words: &[S]) -> Result<AlertRule, String> {
+        let words: Vec<&str> = words.iter().map(|v| v.as_ref()).collect();
+        let Some((&kind, rest)) = words.split_first() else {
+            return Err(format!(
+                "アラートの種類を指定してください（{} のいずれか）",
+                RULE_KINDS.join(", ")
+            ));
+        };
+        let number = || -> Result<f64, String> {
+            let [value] = rest else {
+                return Err(format!("`{}` には値を1つ指定してください", kind));
+            };
+            let value = value.trim_start_matches(['￥', '¥']).trim_end_matches('%');
+            value
+                .replace(',', "")
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v >= 0.0)
+                .ok_or_else(|| format!("`{}` は0以上の数値で指定してください", value))
+        };
+        let no_value = |rule: AlertRule| -> Result<AlertRule, String> {
+            if rest.is_empty() {
+                Ok(rule)
+            } else {
+                Err(format!("`{}` には値を指定できません", kind))
+            }
+        };
+        match kind {
+            "price" => Ok(AlertRule::Price {
+                target: number()? as u64,
+            }),
+            "effective" => Ok(AlertRule::Effective {
+                target: number()? as u64,
+            }),
+            "below-high" => Ok(AlertRule::BelowHigh { percent: number()? }),
+            "new-low" => no_value(AlertRule::NewLow),
+            "back-in-stock" => no_value(AlertRule::BackInStock),
+            "point-ratio" => Ok(AlertRule::PointRatio {
+                at_least: number()? as u64,
+            }),
+            _ => Err(format!(
+                "不明なアラートの種類 `{}` です（{} のいずれか）",
+                kind,
+                RULE_KINDS.join(", ")
+            )),
+        }
+    }
+
+    /// 製品の現在の状態が条件を満たすかどうかを返す。
+    pub fn holds(&self, product: &ProductHistory, profile: &PaymentProfile) -> bool {
+        let Some(last) = product.history.last() else {
+            return false;
+        };
+        match self {
+            AlertRule::Price { target } => last.price <= *target,
+            AlertRule::Effective { target } => last.effective_price(profile) <= *target,
+            AlertRule::BelowHigh { percent } => {
+                let (high, _, _) = product.high_low_now();
+                high > 0 && (last.price as f64) <= high as f64 * (1.0 - percent / 100.0)
+            }
+            AlertRule::NewLow => {
+                // 現在の価格になる前の最安値より安いかどうか
+                let start = product
+                    .history
+                    .windows(2)
+                    .rposition(|v| v[0].price != v[1].price)
+                    .map_or(0, |i| i + 1);
+                product.history[..start]
+                    .iter()
+                    .map(|v| v.price)
+                    .min()
+                    .is_some_and(|low| last.price < low)
+            }
+            AlertRule::BackInStock => last.status.is_in_stock(),
+            AlertRule::PointRatio { at_least } => last.point_ratio >= *at_least,
+        }
+    }
+}
+
+impl std::fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertRule::Price { target } => write!(f, "価格が{}以下", yen(*target)),
+            AlertRule::Effective { target } => write!(f, "実質価格が{}以下", yen(*target)),
+            AlertRule::BelowHigh { percent } => write!(f, "価格が最高値より{}%以上安い", percent),
+            AlertRule::NewLow => f.write_str("最安値を更新"),
+            AlertRule::BackInStock => f.write_str("在庫あり"),
+            AlertRule::PointRatio { at_least } => write!(f, "ポイント還元率が{}%以上", at_least),
+        }
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 製品に設定したアラートと、その通知状態
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub rule: AlertRule,
+    /// 通知済み（条件を満たしている間は再度通知しない）
+    #[serde(default)]
+    pub triggered: bool,
+    /// 最後に通知した日時
+    #[serde(default, with = "ts_seconds_option")]
+    pub triggered_at: Option<DateTime<Utc>>,
+    /// 最後に通知したときの価格
+    #[serde(default)]
+    pub triggered_price: Option<u64>,
+}
+
+impl Alert {
+    /// アラートを生成する。現在すでに条件を満たしているときは、通知済みの状態にする。
+    pub fn new(rule: AlertRule, product: &ProductHistory, profile: &PaymentProfile) -> Self {
+        let triggered = rule.holds(product, profile);
+        // 最安値の更新は、この価格より安くなったときに通知する。
+        let triggered_price = product
+            .history
+            .last()
+            .filter(|_| triggered)
+            .map(|v| v.price);
+        Self {
+            rule,
+            triggered,
+            triggered_at: None,
+            triggered_price,
+        }
+    }
+}
+
+/// 通知するアラート
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FiredAlert {
+    /// 条件の説明
+    pub rule: String,
+    /// 通知したときの価格（円）
+    pub price: u64,
+}
+
+impl std::fmt::Display for FiredAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}（現在 {}）", self.rule, yen(self.price))
+    }
+}
+
+/// 製品のアラートを評価し、新たに通知するアラートを返す。通知状態も更新する。
+pub fn evaluate(product: &mut ProductHistory, profile: &PaymentProfile) -> Vec<FiredAlert> {
+    let Some(last) = product.history.last() else {
+        return vec![];
+    };
+    let price = last.price;
+    // 価格履歴は変化したときにのみ追加されるため、通知した日時には最後に取得した日時を使う。
+    let datetime = product.last_checked.unwrap_or(last.datetime);
+    let holds: Vec<bool> = product
+        .alerts
+        .iter()
+        .map(|v| v.rule.holds(product, profile))
+        .collect();
+
+    let mut fired = vec![];
+    for (alert, holds) in product.alerts.iter_mut().zip(holds) {
+        if !holds {
+            alert.triggered = false;
+            continue;
+        }
+        // 最安値の更新は、通知済みでもさらに安くなったときは再度通知する。
+        let lower =
+            alert.rule == AlertRule::NewLow && alert.triggered_price.is_some_and(|v| price < v);
+        if alert.triggered && !lower {
+            continue;
+        }
+        alert.triggered = true;
+        alert.triggered_at = Some(datetime);
+        alert.triggered_price = Some(price);
+        fired.push(FiredAlert {
+            rule: alert.rule.to_string(),
+            price,
+        });
+    }
+    fired
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 価格の更新後に、アラートとルールを評価するための設定
+#[derive(Debug, Clone, Default)]
+pub struct AlertContext {
+    /// 実質価格の計算に使う支払い方法
+    pub profile: PaymentProfile,
+    pub rules: Vec<CompiledRule>,
+}
+
+impl AlertContext {
+    /// 設定から生成する。条件式が正しくないルールは除き、そのエラーメッセージを返す。
+    pub fn from_config(config: &Config) -> (Self, Vec<String>) {
+        let mut rules = vec![];
+        let mut errors = vec![];
+        for rule in config.rules.iter() {
+            match CompiledRule::new(rule.clone()) {
+                Ok(x) => rules.push(x),
+                Err(e) => errors.push(format!("ルール「{}」を使えません：{}", rule.name, e)),
+            }
+        }
+        let context = Self {
+            profile: config.active_profile(),
+            rules,
+        };
+        (context, errors)
+    }
+}
+
+/// 製品のアラートと、製品に適用するルールを評価し、新たに知らせるものを返す。
+pub fn evaluate_all(product: &mut ProductHistory, context: &AlertContext) -> Vec<FiredAlert> {
+    let mut fired = evaluate(product, &context.profile);
+    fired.extend(evaluate_rules(product, &context.rules, &context.profile));
+    fired
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::data::OnePrice;
+    use crate::fixtures::one_price_with;
+    use crate::types::StockStatus;
+
+    fn product(prices: &[(u64, StockStatus)]) -> ProductHistory {
+        ProductHistory {
+            history: prices
+                .iter()
+                .zip(1..)
+                .map(|(&(price, status), d)| one_price_with(price, 10, status, d))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn push(product: &mut ProductHistory, price: u64, status: StockStatus) {
+        let datetime = product.history.last().unwrap().datetime + Duration::days(1);
+        product.history.push(OnePrice {
+            price,
+            point: price / 10,
+            point_ratio: 10,
+            status,
+            datetime,
+        });
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(
+            Ok(AlertRule::Price { target: 30000 }),
+            AlertRule::parse(&["price", "￥30,000"])
+        );
+        assert_eq!(
+            Ok(AlertRule::BelowHigh { percent: 20.0 }),
+            AlertRule::parse(&["below-high", "20%"])
+        );
+        assert_eq!(Ok(AlertRule::NewLow), AlertRule::parse(&["new-low"]));
+        assert!(AlertRule::parse(&["price"]).is_err());
+        assert!(AlertRule::parse(&["new-low", "1"]).is_err());
+        assert!(AlertRule::parse(&["cheap"]).is_err());
+        assert!(AlertRule::parse::<&str>(&[]).is_err());
+    }
+
+    #[test]
+    fn holds_test() {
+        let profile = PaymentProfile::default();
+        let p = product(&[
+            (5000, StockStatus::Sufficient),
+            (4000, StockStatus::BackOrder),
+        ]);
+        assert!(AlertRule::Price { target: 4000 }.holds(&p, &profile));
+        assert!(!AlertRule::Price { target: 3999 }.holds(&p, &profile));
+        assert!(AlertRule::Effective { target: 3600 }.holds(&p, &profile));
+        assert!(AlertRule::BelowHigh { percent: 20.0 }.holds(&p, &profile));
+        assert!(!AlertRule::BelowHigh { percent: 21.0 }.holds(&p, &profile));
+        assert!(AlertRule::NewLow.holds(&p, &profile));
+        assert!(!AlertRule::BackInStock.holds(&p, &profile));
+        assert!(AlertRule::PointRatio { at_least: 10 }.holds(&p, &profile));
+
+        // 在庫状況のみが変化しても、最安値を更新した状態は続く。
+        let p = product(&[
+            (5000, StockStatus::Sufficient),
+            (4000, StockStatus::BackOrder),
+            (4000, StockStatus::Sufficient),
+        ]);
+        assert!(AlertRule::NewLow.holds(&p, &profile));
+        assert!(!AlertRule::NewLow.holds(&product(&[(5000, StockStatus::Sufficient)]), &profile));
+    }
+
+    #[test]
+    fn evaluate_test() {
+        let profile = PaymentProfile::default();
+        let mut p = product(&[(5000, StockStatus::BackOrder)]);
+        p.alerts = vec![
+            Alert::new(AlertRule::Price { target: 4500 }, &p, &profile),
+            Alert::new(AlertRule::BackInStock, &p, &profile),
+            Alert::new(AlertRule::NewLow, &p, &profile),
+        ];
+
+        // 条件を満たしたときに通知する。
+        push(&mut p, 4500, StockStatus::Sufficient);
+        let fired = evaluate(&mut p, &profile);
+        assert_eq!(3, fired.len());
+        assert_eq!("価格が￥4,500以下", fired[0].rule);
+
+        // 条件を満たしている間は、繰り返し通知しない。ただし最安値をさらに更新したときは通知する。
+        push(&mut p, 4400, StockStatus::Sufficient);
+        let fired = evaluate(&mut p, &profile);
+        assert_eq!(vec!["最安値を更新".to_string()], rule_names(&fired));
+
+        // 条件を満たさなくなると通知状態を戻し、再び満たしたときに通知する。
+        push(&mut p, 5000, StockStatus::BackOrder);
+        assert!(evaluate(&mut p, &profile).is_empty());
+        assert!(!p.alerts[0].triggered);
+        push(&mut p, 4500, StockStatus::Sufficient);
+        let checked = p.history.last().unwrap().datetime + Duration::hours(6);
+        p.last_checked = Some(checked);
+        let fired = evaluate(&mut p, &profile);
+        assert_eq!(
+            vec!["価格が￥4,500以下".to_string(), "在庫あり".to_string()],
+            rule_names(&fired)
+        );
+        // 通知した日時は、最後に取得した日時にする。
+        assert_eq!(Some(checked), p.alerts[0].triggered_at);
+    }
+
+    fn rule_names(fired: &[FiredAlert]) -> Vec<String> {
+        fired.iter().map(|v| v.rule.clone()).collect()
+    }
+}
@@ -0,0 +1,172 @@
+//! ルールのバックテスト。
+//!
+//! 記録済みの価格履歴を時刻順に再生してルールを評価し、いつ・何回知らせることになったかと、
+//! その後の価格の動きを求める。
+//! 価格履歴は変化した時点のみ記録されているため、評価は各価格データの時点で行う。
+//! クールダウンや知らせない時間帯で保留したときは、次の価格データより前に保留が解けるなら、その時点で再び評価する。
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::PaymentProfile;
+use crate::data::OnePrice;
+use crate::rule::{CompiledRule, Outcome, RuleState};
+
+/// 知らせた後の価格の動きを調べる期間（日数）
+pub const FOLLOW_DAYS: i64 = 30;
+
+/// 知らせた1回分の結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Firing {
+    pub datetime: DateTime<Utc>,
+    /// 知らせた時点の価格
+    pub price: u64,
+    /// 7日後の価格。その時点がまだ来ていないときは None
+    pub after_7d: Option<u64>,
+    /// 30日後の価格。その時点がまだ来ていないときは None
+    pub after_30d: Option<u64>,
+    /// 知らせてから30日間（または現在まで）の最安値
+    pub low: u64,
+}
+
+impl Firing {
+    /// 30日後の価格の変化率(%)を返す。
+    pub fn change_ratio_30d(&self) -> Option<f64> {
+        let after = self.after_30d?;
+        if self.price == 0 {
+            return None;
+        }
+        Some((after as f64 - self.price as f64) * 100.0 / self.price as f64)
+    }
+}
+
+/// 価格履歴を `end` の時点まで再生してルールを評価し、知らせることになった時点の一覧を返す。
+pub fn backtest(
+    history: &[OnePrice],
+    rule: &CompiledRule,
+    profile: &PaymentProfile,
+    end: DateTime<Utc>,
+) -> Vec<Firing> {
+    let mut state = RuleState::new(&rule.rule.name);
+    let mut firings = vec![];
+
+    for (i, p) in history.iter().enumerate() {
+        let prefix = &history[..=i];
+        let next = history.get(i + 1).map_or(end, |v| v.datetime);
+        let mut now = p.datetime;
+        loop {
+            let holds = rule.expr.holds(prefix, profile, now);
+            match state.step(&rule.rule, holds, now) {
+                Outcome::Fired => firings.push(follow(history, now, p.price, end)),
+                Outcome::Cooldown | Outcome::Quiet => {
+                    // 保留が次の価格データより前に解けるときは、その時点で再び評価する。
+                    let release = rule.rule.release_time(state.fired_at, now);
+                    if now < release && release < next {
+                        now = release;
+                        continue;
+                    }
+                }
+                Outcome::Idle | Outcome::Held => {}
+            }
+            break;
+        }
+    }
+    firings
+}
+
+/// 知らせた後の価格の動きを求める。
+fn follow(history: &[OnePrice], datetime: DateTime<Utc>, price: u64, end: DateTime<Utc>) -> Firing {
+    let after = |days: i64| {
+        let time = datetime + Duration::days(days);
+        (time <= end).then(|| price_at(history, time)).flatten()
+    };
+    let until = end.min(datetime + Duration::days(FOLLOW_DAYS));
+    let low = history
+        .iter()
+        .filter(|v| datetime < v.datetime && v.datetime <= until)
+        .map(|v| v.price)
+        .chain(std::iter::once(price))
+        .min()
+        .unwrap();
+    Firing {
+        datetime,
+        price,
+        after_7d: after(7),
+        after_30d: after(FOLLOW_DAYS),
+        low,
+    }
+}
+
+/// 指定した時点の価格（その時点までで最後の価格データの価格）を返す。
+fn price_at(history: &[OnePrice], time: DateTime<Utc>) -> Option<u64> {
+    history
+        .iter()
+        .take_while(|v| v.datetime <= time)
+        .last()
+        .map(|v| v.price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price};
+    use crate::rule::{QuietHours, Rule};
+
+    fn history() -> Vec<OnePrice> {
+        vec![
+            one_price(1000, 1),
+            one_price(900, 3),
+            one_price(1000, 5),
+            one_price(850, 10),
+            one_price(800, 20),
+        ]
+    }
+
+    #[test]
+    fn backtest_test() {
+        let profile = PaymentProfile::default();
+        let compiled = CompiledRule::new(Rule::ad_hoc("price < 950")).unwrap();
+        let firings = backtest(&history(), &compiled, &profile, day(31) + Duration::days(4));
+
+        // 条件を満たすたびに1回ずつ知らせる（満たし続けている間は知らせない）。
+        assert_eq!(2, firings.len());
+        assert_eq!(day(3), firings[0].datetime);
+        assert_eq!(900, firings[0].price);
+        assert_eq!(Some(850), firings[0].after_7d);
+        assert_eq!(Some(800), firings[0].after_30d);
+        assert_eq!(800, firings[0].low);
+
+        // 30日後がまだ来ていないときは None にする。
+        assert_eq!(day(10), firings[1].datetime);
+        assert_eq!(Some(850), firings[1].after_7d);
+        assert_eq!(None, firings[1].after_30d);
+        assert_eq!(None, firings[1].change_ratio_30d());
+        let ratio = firings[0].change_ratio_30d().unwrap();
+        assert!((ratio - -11.11).abs() < 0.01);
+    }
+
+    #[test]
+    fn backtest_suppression_test() {
+        let profile = PaymentProfile::default();
+
+        // クールダウンの期間中に条件を満たしたときは、期間が過ぎた時点で知らせる。
+        let mut r = Rule::ad_hoc("price < 950");
+        r.cooldown_hours = Some(24 * 10);
+        let compiled = CompiledRule::new(r).unwrap();
+        let firings = backtest(&history(), &compiled, &profile, day(31));
+        assert_eq!(vec![day(3), day(13)], datetimes(&firings));
+
+        // 知らせない時間帯（日本時間 9〜12時）に条件を満たしたときは、時間帯が終わった時点で知らせる。
+        let mut r = Rule::ad_hoc("price < 950");
+        r.quiet_hours = QuietHours::from_string("9-12");
+        let compiled = CompiledRule::new(r).unwrap();
+        let firings = backtest(&history(), &compiled, &profile, day(31));
+        assert_eq!(
+            vec![day(3) + Duration::hours(3), day(10) + Duration::hours(3)],
+            datetimes(&firings)
+        );
+    }
+
+    fn datetimes(firings: &[Firing]) -> Vec<DateTime<Utc>> {
+        firings.iter().map(|v| v.datetime).collect()
+    }
+}
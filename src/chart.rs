@@ -0,0 +1,232 @@
+//! 価格推移のグラフ。
+//!
+//! 価格履歴を階段関数として扱い、一定間隔の時刻での値を取り出して描画する。
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::data::OnePrice;
+use crate::types::StockStatus;
+use crate::util::{commafy, format_jst, pad_left};
+
+/// 1文字の中を8段階に分けて塗りつぶす文字（低い順）。スパークラインとグラフで共用する。
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// 端末の幅を返す。取得できないときは 80 を返す。
+pub fn terminal_width() -> usize {
+    match terminal_size::terminal_size() {
+        Some((terminal_size::Width(w), _)) => w as usize,
+        None => 80,
+    }
+}
+
+/// 指定された時刻における価格データを返す。時刻が最初のデータより前のときは None を返す。
+fn at(history: &[OnePrice], datetime: DateTime<Utc>) -> Option<&OnePrice> {
+    history.iter().rev().find(|v| v.datetime <= datetime)
+}
+
+/// 期間を `count` 個に等分し、各区間の中央の時刻での価格データを返す。
+pub fn sample(
+    history: &[OnePrice],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    count: usize,
+) -> Vec<Option<&OnePrice>> {
+    let span = (end - start).num_seconds() as f64;
+    (0..count)
+        .map(|i| {
+            let offset = span * (i as f64 + 0.5) / count as f64;
+            at(history, start + Duration::seconds(offset as i64))
+        })
+        .collect()
+}
+
+/// 値の列をスパークラインの文字列に変換する。値がないところは空白にする。
+pub fn sparkline(values: &[Option<u64>]) -> String {
+    let present: Vec<u64> = values.iter().flatten().copied().collect();
+    let (Some(&min), Some(&max)) = (present.iter().min(), present.iter().max()) else {
+        return " ".repeat(values.len());
+    };
+    values
+        .iter()
+        .map(|v| match v {
+            None => ' ',
+            Some(_) if max == min => BLOCKS[3],
+            Some(x) => BLOCKS[((x - min) * 7 / (max - min)) as usize],
+        })
+        .collect()
+}
+
+/// 直近 `days` 日間の価格のスパークラインを返す。
+pub fn price_sparkline(
+    history: &[OnePrice],
+    days: i64,
+    width: usize,
+    now: DateTime<Utc>,
+) -> String {
+    let values: Vec<_> = sample(history, now - Duration::days(days), now, width)
+        .iter()
+        .map(|v| v.map(|p| p.price))
+        .collect();
+    sparkline(&values)
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 価格と実質価格の推移を、ブロック文字の階段グラフとして描画する。
+///
+/// - `█`：実質価格まで
+/// - `░`：実質価格から価格まで（ポイント分）
+///
+/// グラフの下には、最高値(H)・最安値(L)の位置と、在庫状況の帯を表示する。
+/// 返り値は1行ずつの文字列。価格履歴が空のときは空の配列を返す。
+pub fn render_chart<F>(
+    history: &[OnePrice],
+    effective: F,
+    width: usize,
+    height: usize,
+    now: DateTime<Utc>,
+) -> Vec<String>
+where
+    F: Fn(&OnePrice) -> u64,
+{
+    let Some(first) = history.first() else {
+        return vec![];
+    };
+    let high = history.iter().map(|v| v.price).max().unwrap();
+    let low = history.iter().map(|v| v.price).min().unwrap();
+    let bottom = history.iter().map(&effective).min().unwrap();
+
+    // 縦軸の範囲。最小値の少し下から最高値までとする。
+    let margin = ((high - bottom) / 10).max(1);
+    let axis_min = bottom.saturating_sub(margin);
+    let axis_max = high.max(axis_min + 1);
+    let label_width = commafy(axis_max).len() + 2;
+    let cols = width.saturating_sub(label_width + 1).max(1);
+    let samples = sample(history, first.datetime, now.max(first.datetime), cols);
+
+    // 1行あたりの値の幅。各行は [row_low, row_low + step) の範囲を表す。
+    let step = (axis_max - axis_min) as f64 / height as f64;
+    let mut lines = vec![];
+    for row in (0..height).rev() {
+        let row_low = axis_min as f64 + step * row as f64;
+        let label = if row == height - 1 {
+            format!("￥{}", commafy(axis_max))
+        } else if row == 0 {
+            format!("￥{}", commafy(axis_min))
+        } else {
+            String::new()
+        };
+        let mut line = format!("{}┤", pad_left(&label, label_width));
+        for s in samples.iter() {
+            let c = match s {
+                None => ' ',
+                Some(p) => cell(row_low, step, effective(p) as f64, p.price as f64),
+            };
+            line.push(c);
+        }
+        lines.push(line);
+    }
+
+    // 横軸
+    lines.push(format!("{:>w$}└{}", "", "─".repeat(cols), w = label_width));
+
+    // 最高値・最安値の位置（同じ価格が続くときは最初の位置のみ）
+    let mut markers = String::new();
+    let mut prev = None;
+    for s in samples.iter() {
+        let price = s.map(|v| v.price);
+        let c = match price {
+            Some(x) if price != prev && x == high => 'H',
+            Some(x) if price != prev && x == low => 'L',
+            _ => ' ',
+        };
+        markers.push(c);
+        prev = price;
+    }
+    lines.push(format!("{:>w$} {}", "", markers, w = label_width));
+
+    // 在庫状況の帯
+    let band: String = samples
+        .iter()
+        .map(|s| match s.map(|v| v.status) {
+            Some(StockStatus::Sufficient) => '━',
+            Some(StockStatus::Limited) => '┅',
+            Some(StockStatus::BackOrder | StockStatus::Unknown) | None => ' ',
+        })
+        .collect();
+    lines.push(format!("{} {}", pad_left("在庫", label_width), band));
+
+    // 期間
+    let start_label = format_jst(&first.datetime, "%Y/%m/%d");
+    let end_label = format_jst(&now, "%Y/%m/%d");
+    let gap = cols.saturating_sub(start_label.len() + end_label.len());
+    lines.push(format!(
+        "{:>w$} {}{}{}",
+        "",
+        start_label,
+        " ".repeat(gap),
+        end_label,
+        w = label_width
+    ));
+    lines
+}
+
+/// グラフの1文字分を返す。
+fn cell(row_low: f64, step: f64, effective: f64, price: f64) -> char {
+    let row_high = row_low + step;
+    if effective >= row_high {
+        '█'
+    } else if effective > row_low {
+        // 実質価格がこの行の途中にあるときは、高さに応じた文字で描画する。
+        let ratio = (effective - row_low) / step;
+        let index = ((ratio * 8.0) as usize).clamp(1, 8) - 1;
+        BLOCKS[index]
+    } else if price > row_low {
+        '░'
+    } else {
+        ' '
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price_with};
+
+    #[test]
+    fn sparkline_test() {
+        assert_eq!("▁▄█ ", sparkline(&[Some(0), Some(50), Some(100), None]));
+        assert_eq!("▄▄", sparkline(&[Some(10), Some(10)]));
+        assert_eq!("  ", sparkline(&[None, None]));
+    }
+
+    #[test]
+    fn price_sparkline_test() {
+        let history = vec![
+            one_price_with(1000, 10, StockStatus::Sufficient, 1),
+            one_price_with(800, 10, StockStatus::Sufficient, 6),
+        ];
+        assert_eq!("██▁▁", price_sparkline(&history, 8, 4, day(9)));
+        // 登録前の期間は空白にする。
+        assert_eq!(" ██▁", price_sparkline(&history, 10, 4, day(9)));
+    }
+
+    #[test]
+    fn render_chart_test() {
+        let history = vec![
+            one_price_with(1000, 10, StockStatus::Sufficient, 1),
+            one_price_with(800, 10, StockStatus::BackOrder, 5),
+        ];
+        let lines = render_chart(&history, |v| v.price - v.point, 20, 4, day(9));
+        // グラフ4行、横軸、最高値・最安値、在庫、期間
+        assert_eq!(8, lines.len());
+        assert!(lines[0].trim_start().starts_with("￥1,000┤"));
+        // 全角の `￥` を含むラベルも、軸の位置が揃うこと。
+        let axis = |line: &str| crate::util::display_width(line.split(['┤', '└']).next().unwrap());
+        assert_eq!(axis(&lines[0]), axis(&lines[3]));
+        assert_eq!(axis(&lines[0]), axis(&lines[4]));
+        assert!(lines[5].contains("H") && lines[5].contains("L"));
+        assert!(lines[6].contains('━'));
+        assert!(lines[7].contains("2024/01/01"));
+    }
+}
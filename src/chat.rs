@@ -0,0 +1,268 @@
+//! チャットの Incoming Webhook 向けのメッセージ。
+//!
+//! 1回の更新で生じたイベントを、製品ごとに1行にまとめた1通のメッセージにする。
+//! 長さの上限を超えるときは、行の区切りで複数のメッセージに分ける。
+//! Slack は独自の書式（`<url|name>`, `*bold*`）、Discord と Mattermost は Markdown を使う。
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::notify::{Event, EventKind};
+use crate::util::{signed_yen, yen};
+
+/// 本文の形式の名称の一覧
+pub const WEBHOOK_FORMATS: &[&str] = &["json", "slack", "discord", "mattermost"];
+
+/// Webhook の本文の形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// イベントごとの JSON（またはテンプレート）
+    #[default]
+    Json,
+    Slack,
+    Discord,
+    /// Mattermost 等、Slack 互換の Webhook で Markdown を使うもの
+    Mattermost,
+}
+
+impl WebhookFormat {
+    pub fn from_string(string: &str) -> Option<WebhookFormat> {
+        match string {
+            "json" => Some(WebhookFormat::Json),
+            "slack" => Some(WebhookFormat::Slack),
+            "discord" => Some(WebhookFormat::Discord),
+            "mattermost" => Some(WebhookFormat::Mattermost),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            WebhookFormat::Json => "json",
+            WebhookFormat::Slack => "slack",
+            WebhookFormat::Discord => "discord",
+            WebhookFormat::Mattermost => "mattermost",
+        }
+    }
+
+    /// チャット向けの形式かどうかを返す。
+    pub fn is_chat(&self) -> bool {
+        *self != WebhookFormat::Json
+    }
+
+    /// 1通のメッセージの文字数の上限を返す。
+    fn limit(&self) -> usize {
+        match self {
+            WebhookFormat::Discord => 2000,
+            _ => 4000,
+        }
+    }
+
+    /// 太字にする。
+    fn bold(&self, text: &str) -> String {
+        match self {
+            WebhookFormat::Slack => format!("*{}*", text),
+            _ => format!("**{}**", text),
+        }
+    }
+
+    /// 名称を URL へのリンクにする。
+    fn link(&self, name: &str, url: &str) -> String {
+        match self {
+            WebhookFormat::Slack => {
+                let name = name
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;");
+                format!("<{}|{}>", url, name)
+            }
+            _ => {
+                let name = name.replace('[', "\\[").replace(']', "\\]");
+                format!("[{}]({})", name, url)
+            }
+        }
+    }
+
+    /// メッセージの本文を、形式に合った JSON にする。
+    fn payload(&self, text: &str) -> String {
+        match self {
+            WebhookFormat::Discord => json!({ "content": text }),
+            _ => json!({ "text": text }),
+        }
+        .to_string()
+    }
+}
+
+/// イベントの一覧を、チャット向けのメッセージ（JSON）の一覧にする。イベントがないときは空にする。
+pub fn chat_messages(format: WebhookFormat, events: &[Event]) -> Vec<String> {
+    if events.is_empty() {
+        return vec![];
+    }
+    let count = |kind| events.iter().filter(|v| v.event == kind).count();
+    let (alerts, errors) = (count(EventKind::Alert), count(EventKind::Error));
+    let mut summary = format!("価格の更新：変化 {}件", count(EventKind::Change));
+    if alerts > 0 {
+        summary += &format!("、アラート {}件", alerts);
+    }
+    if errors > 0 {
+        summary += &format!("、失敗 {}件", errors);
+    }
+
+    let mut lines = vec![];
+    for group in events.chunk_by(|a, b| a.product_id == b.product_id) {
+        lines.push(render_line(format, group));
+    }
+
+    let mut messages = vec![];
+    let mut text = format.bold(&summary);
+    for line in lines {
+        if text.chars().count() + 1 + line.chars().count() > format.limit() {
+            messages.push(format.payload(&text));
+            text = String::new();
+        }
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&line);
+    }
+    messages.push(format.payload(&text));
+    messages
+}
+
+/// 1つの製品のイベントを1行にする。
+fn render_line(format: WebhookFormat, events: &[Event]) -> String {
+    let first = &events[0];
+    if let Some(error) = &first.error {
+        return format!("× {}  {}", format.link(&first.name, &first.url), error);
+    }
+    let mark = match (first.old_price, first.price) {
+        (Some(old), Some(new)) if new < old => "↓",
+        (Some(old), Some(new)) if new > old => "↑",
+        _ => "・",
+    };
+
+    let mut parts = vec![];
+    match (first.old_price, first.price) {
+        (Some(old), Some(new)) if old != new => {
+            let change = new as i64 - old as i64;
+            parts.push(format!(
+                "{} → {} ({}, {:+.1}%)",
+                yen(old),
+                yen(new),
+                signed_yen(change),
+                change as f64 * 100.0 / old as f64
+            ));
+        }
+        (_, Some(price)) => parts.push(yen(price)),
+        _ => {}
+    }
+    if let Some(effective) = first.effective_price {
+        parts.push(format!("実質 {}", yen(effective)));
+    }
+    match (first.old_status, first.status) {
+        (Some(before), Some(after)) if before != after => {
+            parts.push(format!("在庫: {} → {}", before, after))
+        }
+        (_, Some(status)) => parts.push(status.to_string()),
+        _ => {}
+    }
+    if let (Some(before), Some(after)) = (first.old_point_ratio, first.point_ratio) {
+        if before != after {
+            parts.push(format!("還元率: {}% → {}%", before, after));
+        }
+    }
+    for rule in events.iter().filter_map(|v| v.rule.as_ref()) {
+        parts.push(format!("🔔 {}", format.bold(rule)));
+    }
+    format!(
+        "{} {}  {}",
+        mark,
+        format.link(&first.name, &first.url),
+        parts.join("  ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::StockStatus;
+
+    fn events() -> Vec<Event> {
+        let mut alert = Event::sample(EventKind::Alert);
+        alert.rule = Some("ルール「sale」".to_string());
+        let mut other = Event::sample(EventKind::Change);
+        other.product_id = "other".to_string();
+        other.name = "A & B <C> [黒]".to_string();
+        other.url = "https://example.com/other".to_string();
+        other.old_price = Some(1000);
+        other.price = Some(1200);
+        other.effective_price = Some(1080);
+        other.old_status = Some(StockStatus::Sufficient);
+        other.status = Some(StockStatus::Sufficient);
+        other.point_ratio = Some(13);
+        vec![
+            Event::sample(EventKind::Change),
+            alert,
+            other,
+            Event::sample(EventKind::Error),
+        ]
+    }
+
+    fn texts(format: WebhookFormat, events: &[Event]) -> Vec<String> {
+        let key = if format == WebhookFormat::Discord {
+            "content"
+        } else {
+            "text"
+        };
+        chat_messages(format, events)
+            .iter()
+            .map(|v| {
+                let value: serde_json::Value = serde_json::from_str(v).unwrap();
+                value[key].as_str().unwrap().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chat_messages_test() {
+        assert_eq!(
+            vec![[
+                "*価格の更新：変化 2件、アラート 1件、失敗 1件*",
+                "↓ <https://www.yodobashi.com/|テスト用の製品>  ￥30,000 → ￥27,800 (-￥2,200, -7.3%)  実質 ￥25,020  在庫: 在庫あり → 在庫僅少  🔔 *ルール「sale」*",
+                "↑ <https://example.com/other|A &amp; B &lt;C&gt; [黒]>  ￥1,000 → ￥1,200 (+￥200, +20.0%)  実質 ￥1,080  在庫あり  還元率: 10% → 13%",
+                "× <https://www.yodobashi.com/|テスト用の製品>  ページを取得できませんでした",
+            ]
+            .join("\n")],
+            texts(WebhookFormat::Slack, &events())
+        );
+
+        let discord = texts(WebhookFormat::Discord, &events());
+        assert_eq!(1, discord.len());
+        assert!(discord[0].starts_with("**価格の更新：変化 2件、アラート 1件、失敗 1件**\n"));
+        assert!(discord[0].contains("[A & B <C> \\[黒\\]](https://example.com/other)"));
+        assert!(discord[0].contains("🔔 **ルール「sale」**"));
+
+        let mattermost = texts(WebhookFormat::Mattermost, &events());
+        assert_eq!(discord, mattermost);
+
+        assert!(chat_messages(WebhookFormat::Slack, &[]).is_empty());
+    }
+
+    #[test]
+    fn chat_messages_split_test() {
+        // 上限を超えるときは、行の区切りで分ける。
+        let events = (0..40)
+            .map(|i| {
+                let mut event = Event::sample(EventKind::Change);
+                event.product_id = i.to_string();
+                event
+            })
+            .collect::<Vec<_>>();
+        let texts = texts(WebhookFormat::Discord, &events);
+        assert!(texts.len() > 1);
+        assert!(texts.iter().all(|v| v.chars().count() <= 2000));
+        let lines = texts.iter().map(|v| v.lines().count()).sum::<usize>();
+        assert_eq!(41, lines);
+    }
+}
@@ -0,0 +1,1756 @@
+//! コマンドの解析。
+//!
+//! 対話モードの入力と、起動時のコマンドライン引数の両方を同じ文法で解析する。
+//! 各コマンドの引数は `COMMANDS` に定義し、ヘルプの表示や入力の検証にも使う。
+
+use crate::chat::WEBHOOK_FORMATS;
+use crate::feed::FEED_FORMATS;
+use crate::mail::MAIL_ACTIONS;
+use crate::output::{OutputFormat, OUTPUT_FORMATS};
+use crate::query::{ListQuery, SORT_KEYS};
+use crate::stats::Window;
+
+/// 引数の値の種類
+#[derive(Debug, Clone, Copy)]
+pub enum ValueKind {
+    /// 任意の文字列
+    Text,
+    /// URL (`http://` または `https://` で始まる文字列)
+    Url,
+    /// 製品（一覧での番号またはID）
+    Product,
+    /// 統計の期間 (`30d`, `all` 等)
+    Window,
+    /// 正の整数
+    Number,
+    /// 選択肢のいずれか
+    Choice(&'static [&'static str]),
+}
+
+/// 引数の種類
+#[derive(Debug, Clone, Copy)]
+pub enum ArgKind {
+    /// 位置引数
+    Positional { required: bool, value: ValueKind },
+    /// 残りの全ての位置引数（最後の位置引数にのみ使う）
+    Rest { required: bool, value: ValueKind },
+    /// 値を取らないフラグ
+    Flag,
+    /// 値を取るオプション
+    Option { value: ValueKind },
+}
+
+impl ArgKind {
+    pub(crate) fn is_positional(&self) -> bool {
+        matches!(self, ArgKind::Positional { .. } | ArgKind::Rest { .. })
+    }
+}
+
+/// 引数の定義
+#[derive(Debug)]
+pub struct ArgSpec {
+    /// 引数の名称。オプションのときは `--` を除いた長い名称。
+    pub name: &'static str,
+    /// オプションの短い名称
+    pub short: Option<char>,
+    pub kind: ArgKind,
+    /// 説明
+    pub help: &'static str,
+}
+
+/// コマンドの定義
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// 別名（省略形）
+    pub aliases: &'static [&'static str],
+    /// 説明
+    pub summary: &'static str,
+    pub args: &'static [ArgSpec],
+}
+
+const PRODUCT_REQUIRED: ArgSpec = ArgSpec {
+    name: "product",
+    short: None,
+    kind: ArgKind::Positional {
+        required: true,
+        value: ValueKind::Product,
+    },
+    help: "製品（一覧での番号またはID）",
+};
+
+const PRODUCT_OPTIONAL: ArgSpec = ArgSpec {
+    name: "product",
+    short: None,
+    kind: ArgKind::Positional {
+        required: false,
+        value: ValueKind::Product,
+    },
+    help: "製品（一覧での番号またはID）。省略すると全製品が対象になります。",
+};
+
+/// `rules` コマンドの操作
+pub const RULE_ACTIONS: &[&str] = &["list", "add", "remove", "test"];
+
+/// `hooks` コマンドの操作
+pub const HOOK_ACTIONS: &[&str] = &["list", "add", "remove", "test"];
+
+/// `feeds` コマンドの操作
+pub const FEED_ACTIONS: &[&str] = &["list", "add", "remove", "print", "serve"];
+
+/// `webhooks` コマンドの操作
+pub const WEBHOOK_ACTIONS: &[&str] = &["list", "add", "remove", "test"];
+
+const OUTPUT: ArgSpec = ArgSpec {
+    name: "output",
+    short: Some('o'),
+    kind: ArgKind::Option {
+        value: ValueKind::Choice(OUTPUT_FORMATS),
+    },
+    help: "出力形式。省略すると人が読むための形式で表示します。",
+};
+
+/// 全コマンドの定義
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "help",
+        aliases: &["h"],
+        summary: "ヘルプを表示します。",
+        args: &[ArgSpec {
+            name: "command",
+            short: None,
+            kind: ArgKind::Positional {
+                required: false,
+                value: ValueKind::Text,
+            },
+            help: "使い方を表示するコマンド",
+        }],
+    },
+    CommandSpec {
+        name: "quit",
+        aliases: &["q"],
+        summary: "終了します。",
+        args: &[],
+    },
+    CommandSpec {
+        name: "add",
+        aliases: &["a"],
+        summary: "指定されたURLの製品を追加します。",
+        args: &[ArgSpec {
+            name: "url",
+            short: None,
+            kind: ArgKind::Positional {
+                required: true,
+                value: ValueKind::Url,
+            },
+            help: "製品ページのURL",
+        }],
+    },
+    CommandSpec {
+        name: "update",
+        aliases: &["u"],
+        summary: "登録されている製品の価格を取得・更新します。",
+        args: &[OUTPUT],
+    },
+    CommandSpec {
+        name: "remove",
+        aliases: &["rm"],
+        summary: "製品を削除します。価格の履歴も削除されます。",
+        args: &[
+            PRODUCT_REQUIRED,
+            ArgSpec {
+                name: "yes",
+                short: Some('y'),
+                kind: ArgKind::Flag,
+                help: "確認せずに削除します。",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "archive",
+        aliases: &[],
+        summary: "製品をアーカイブします。価格の更新は止まりますが、履歴は残ります。",
+        args: &[PRODUCT_REQUIRED],
+    },
+    CommandSpec {
+        name: "unarchive",
+        aliases: &[],
+        summary: "アーカイブした製品を元に戻し、価格の更新を再開します。",
+        args: &[PRODUCT_REQUIRED],
+    },
+    CommandSpec {
+        name: "rename",
+        aliases: &[],
+        summary: "製品に表示用の名称を設定します。名称を省略すると、設定を解除します。",
+        args: &[
+            PRODUCT_REQUIRED,
+            ArgSpec {
+                name: "name",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "表示用の名称",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "note",
+        aliases: &[],
+        summary: "製品にメモを設定します。メモを省略すると、メモを削除します。",
+        args: &[
+            PRODUCT_REQUIRED,
+            ArgSpec {
+                name: "text",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "メモ",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "tag",
+        aliases: &[],
+        summary: "製品にタグを追加します。",
+        args: &[
+            PRODUCT_REQUIRED,
+            ArgSpec {
+                name: "tag",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: true,
+                    value: ValueKind::Text,
+                },
+                help: "追加するタグ（複数指定可）",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "untag",
+        aliases: &[],
+        summary: "製品からタグを削除します。",
+        args: &[
+            PRODUCT_REQUIRED,
+            ArgSpec {
+                name: "tag",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: true,
+                    value: ValueKind::Text,
+                },
+                help: "削除するタグ（複数指定可）",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "alert",
+        aliases: &[],
+        summary: "製品のアラートを表示・追加・削除します。条件を満たすと、更新の結果で知らせます。",
+        args: &[
+            PRODUCT_OPTIONAL,
+            ArgSpec {
+                name: "remove",
+                short: Some('r'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Number,
+                },
+                help: "指定した番号のアラートを削除します。",
+            },
+            ArgSpec {
+                name: "reset",
+                short: None,
+                kind: ArgKind::Flag,
+                help: "通知済みの状態を戻し、次の更新で条件を満たしていれば再度知らせます。",
+            },
+            ArgSpec {
+                name: "rule",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "追加するアラートの条件（price 30000, effective 28000, below-high 20, new-low, back-in-stock, point-ratio 10）",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "rules",
+        aliases: &[],
+        summary: "アラートのルールを表示・追加・削除します。test で、現在条件を満たす製品を表示します。",
+        args: &[
+            ArgSpec {
+                name: "action",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Choice(RULE_ACTIONS),
+                },
+                help: "操作。省略するとルールの一覧を表示します。",
+            },
+            ArgSpec {
+                name: "name",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "ルールの名称",
+            },
+            ArgSpec {
+                name: "tag",
+                short: Some('t'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "指定したタグが付いた製品にのみ適用します。",
+            },
+            ArgSpec {
+                name: "product",
+                short: Some('p'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Product,
+                },
+                help: "指定した製品にのみ適用します。",
+            },
+            ArgSpec {
+                name: "cooldown",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "一度知らせてから、再び知らせるまでの最短の間隔 (12h, 3d 等)",
+            },
+            ArgSpec {
+                name: "quiet",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "知らせない時間帯（日本時間。22-7 等）。この間に条件を満たしたときは、時間帯が終わってから知らせます。",
+            },
+            ArgSpec {
+                name: "expr",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "条件式（例: effective < 0.85 * median(effective, 90d) and in_stock）。test では名称の代わりに条件式も指定できます。",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "backtest",
+        aliases: &[],
+        summary: "記録済みの価格履歴でルールを試し、いつ何回知らせることになったかと、その後の価格を表示します。",
+        args: &[
+            ArgSpec {
+                name: "tag",
+                short: Some('t'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "指定したタグが付いた製品のみを対象にします。",
+            },
+            ArgSpec {
+                name: "product",
+                short: Some('p'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Product,
+                },
+                help: "指定した製品のみを対象にします。",
+            },
+            ArgSpec {
+                name: "rule",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: true,
+                    value: ValueKind::Text,
+                },
+                help: "ルールの名称、または条件式",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "hooks",
+        aliases: &[],
+        summary: "価格の変化・アラート・取得の失敗のたびに実行する外部コマンドを表示・追加・削除します。test で、テスト用のイベントで実行します。",
+        args: &[
+            ArgSpec {
+                name: "action",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Choice(HOOK_ACTIONS),
+                },
+                help: "操作。省略するとフックの一覧を表示します。",
+            },
+            ArgSpec {
+                name: "name",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "フックの名称",
+            },
+            ArgSpec {
+                name: "events",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "実行するイベントの種類 (change, alert, error をカンマ区切りで。省略すると全て)",
+            },
+            ArgSpec {
+                name: "timeout",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Number,
+                },
+                help: "制限時間（秒。省略すると30秒）。過ぎても終わらないときは強制終了します。",
+            },
+            ArgSpec {
+                name: "command",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "実行するコマンドと引数。イベントの JSON を標準入力に、各項目を YODOPRICE_PRICE 等の環境変数に渡します。",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "mail",
+        aliases: &[],
+        summary: "メールの設定とまとめの状況を表示します。test でテスト用のアラートを、digest でまとめをすぐに送ります。",
+        args: &[ArgSpec {
+            name: "action",
+            short: None,
+            kind: ArgKind::Positional {
+                required: false,
+                value: ValueKind::Choice(MAIL_ACTIONS),
+            },
+            help: "操作。省略すると設定とまとめの状況を表示します。",
+        }],
+    },
+    CommandSpec {
+        name: "webhooks",
+        aliases: &[],
+        summary: "通知先の Webhook を表示・追加・削除します。test で、テスト用のイベントを送ります。",
+        args: &[
+            ArgSpec {
+                name: "action",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Choice(WEBHOOK_ACTIONS),
+                },
+                help: "操作。省略すると Webhook の一覧を表示します。",
+            },
+            ArgSpec {
+                name: "name",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "Webhook の名称",
+            },
+            ArgSpec {
+                name: "url",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Url,
+                },
+                help: "送信先の URL",
+            },
+            ArgSpec {
+                name: "format",
+                short: Some('f'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Choice(WEBHOOK_FORMATS),
+                },
+                help: "本文の形式 (json, slack, discord, mattermost)。チャット向けの形式では、1回の更新の結果を1通のメッセージにまとめて送ります。",
+            },
+            ArgSpec {
+                name: "secret",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "署名に使う秘密鍵。指定すると、本文の HMAC-SHA256 を X-Yodoprice-Signature ヘッダに付けます。",
+            },
+            ArgSpec {
+                name: "template",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "本文のテンプレート。{{name}} や {{price}} 等をイベントの値に、{{payload}} をイベントの JSON 全体に置き換えます。",
+            },
+            ArgSpec {
+                name: "events",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "送るイベントの種類 (change, alert, error をカンマ区切りで。省略すると全て)",
+            },
+            ArgSpec {
+                name: "retries",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Number,
+                },
+                help: "失敗したときに再送する回数（省略すると3回）",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "feeds",
+        aliases: &[],
+        summary: "価格の変化の Atom・RSS フィードを表示・追加・削除します。print で出力し、serve で HTTP で配信します。",
+        args: &[
+            ArgSpec {
+                name: "action",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Choice(FEED_ACTIONS),
+                },
+                help: "操作。省略すると、update のたびに書き出すフィードの一覧を表示します。",
+            },
+            ArgSpec {
+                name: "name",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "フィードの名称",
+            },
+            ArgSpec {
+                name: "path",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "書き出すファイルのパス",
+            },
+            ArgSpec {
+                name: "format",
+                short: Some('f'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Choice(FEED_FORMATS),
+                },
+                help: "フィードの形式 (atom, rss)。省略すると atom です。",
+            },
+            ArgSpec {
+                name: "tag",
+                short: Some('t'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "指定したタグが付いた製品の変化のみを含めます。",
+            },
+            ArgSpec {
+                name: "limit",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Number,
+                },
+                help: "エントリの最大の件数（省略すると50件）",
+            },
+            ArgSpec {
+                name: "listen",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "serve で待ち受けるアドレス（省略すると 127.0.0.1:8080）",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "list",
+        aliases: &["l"],
+        summary: "登録されている製品の一覧を表示します。",
+        args: &[
+            ArgSpec {
+                name: "compare",
+                short: Some('c'),
+                kind: ArgKind::Flag,
+                help: "全ての支払い方法での実質価格を並べて表示します。",
+            },
+            ArgSpec {
+                name: "explain",
+                short: Some('e'),
+                kind: ArgKind::Flag,
+                help: "買い時の評価の根拠も表示します。",
+            },
+            ArgSpec {
+                name: "sort",
+                short: Some('s'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Choice(SORT_KEYS),
+                },
+                help: "並べ替えの基準",
+            },
+            ArgSpec {
+                name: "reverse",
+                short: Some('r'),
+                kind: ArgKind::Flag,
+                help: "逆順に並べます。",
+            },
+            ArgSpec {
+                name: "tag",
+                short: Some('t'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "指定したタグが付いた製品のみを表示します。",
+            },
+            ArgSpec {
+                name: "archived",
+                short: Some('a'),
+                kind: ArgKind::Flag,
+                help: "アーカイブした製品を表示します。",
+            },
+            ArgSpec {
+                name: "view",
+                short: Some('v'),
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "保存した表示条件を使います。",
+            },
+            ArgSpec {
+                name: "save",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Text,
+                },
+                help: "表示条件を指定した名称で保存します。",
+            },
+            ArgSpec {
+                name: "json",
+                short: None,
+                kind: ArgKind::Flag,
+                help: "JSON形式で出力します (`--output json` と同じ)。",
+            },
+            OUTPUT,
+            ArgSpec {
+                name: "filter",
+                short: None,
+                kind: ArgKind::Rest {
+                    required: false,
+                    value: ValueKind::Text,
+                },
+                help: "絞り込みの条件（例: maker = Sony and price < 30000）",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "views",
+        aliases: &[],
+        summary: "保存した一覧の表示条件を表示します。",
+        args: &[ArgSpec {
+            name: "delete",
+            short: Some('d'),
+            kind: ArgKind::Option {
+                value: ValueKind::Text,
+            },
+            help: "指定した名称の表示条件を削除します。",
+        }],
+    },
+    CommandSpec {
+        name: "show",
+        aliases: &["s"],
+        summary: "製品の詳細と、全ての価格履歴を表示します。",
+        args: &[PRODUCT_REQUIRED, OUTPUT],
+    },
+    CommandSpec {
+        name: "chart",
+        aliases: &[],
+        summary: "製品の価格と実質価格の推移をグラフで表示します。",
+        args: &[
+            PRODUCT_REQUIRED,
+            ArgSpec {
+                name: "height",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Number,
+                },
+                help: "グラフの高さ（行数）。省略すると12行です。",
+            },
+            ArgSpec {
+                name: "width",
+                short: None,
+                kind: ArgKind::Option {
+                    value: ValueKind::Number,
+                },
+                help: "グラフの幅（文字数）。省略すると端末の幅に合わせます。",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "stats",
+        aliases: &[],
+        summary: "製品の価格の統計を表示します。",
+        args: &[
+            PRODUCT_REQUIRED,
+            ArgSpec {
+                name: "window",
+                short: None,
+                kind: ArgKind::Positional {
+                    required: false,
+                    value: ValueKind::Window,
+                },
+                help: "期間 (30d, 90d, all 等)。省略すると30日・90日・全期間を表示します。",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "events",
+        aliases: &[],
+        summary: "セール（一時的な値下がり）の履歴を表示します。",
+        args: &[PRODUCT_OPTIONAL],
+    },
+    CommandSpec {
+        name: "stock",
+        aliases: &[],
+        summary: "在庫状況の推移を表示します。",
+        args: &[PRODUCT_OPTIONAL],
+    },
+    CommandSpec {
+        name: "profile",
+        aliases: &["p"],
+        summary:
+            "支払い方法の一覧を表示します。名称を指定すると、使用する支払い方法を切り替えます。",
+        args: &[ArgSpec {
+            name: "name",
+            short: None,
+            kind: ArgKind::Positional {
+                required: false,
+                value: ValueKind::Text,
+            },
+            help: "支払い方法の名称",
+        }],
+    },
+    CommandSpec {
+        name: "tui",
+        aliases: &[],
+        summary: "全画面の一覧・詳細・グラフを表示し、キー操作で更新や編集を行います。",
+        args: &[],
+    },
+];
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 解析済みのコマンド
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help {
+        command: Option<String>,
+    },
+    Quit,
+    Add {
+        url: String,
+    },
+    Update {
+        output: OutputFormat,
+    },
+    Remove {
+        product: String,
+        yes: bool,
+    },
+    Archive {
+        product: String,
+    },
+    Unarchive {
+        product: String,
+    },
+    Rename {
+        product: String,
+        name: Option<String>,
+    },
+    Note {
+        product: String,
+        text: Option<String>,
+    },
+    Tag {
+        product: String,
+        tags: Vec<String>,
+    },
+    Untag {
+        product: String,
+        tags: Vec<String>,
+    },
+    Alert {
+        product: Option<String>,
+        rule: Vec<String>,
+        remove: Option<usize>,
+        reset: bool,
+    },
+    Rules {
+        action: Option<String>,
+        name: Option<String>,
+        expr: Option<String>,
+        tag: Option<String>,
+        product: Option<String>,
+        cooldown: Option<String>,
+        quiet: Option<String>,
+    },
+    Backtest {
+        rule: String,
+        tag: Option<String>,
+        product: Option<String>,
+    },
+    Hooks {
+        action: Option<String>,
+        name: Option<String>,
+        command: Vec<String>,
+        events: Option<String>,
+        timeout: Option<u64>,
+    },
+    Mail {
+        action: Option<String>,
+    },
+    Webhooks {
+        action: Option<String>,
+        name: Option<String>,
+        url: Option<String>,
+        format: Option<String>,
+        secret: Option<String>,
+        template: Option<String>,
+        events: Option<String>,
+        retries: Option<u32>,
+    },
+    Feeds {
+        action: Option<String>,
+        name: Option<String>,
+        path: Option<String>,
+        format: Option<String>,
+        tag: Option<String>,
+        limit: Option<usize>,
+        listen: Option<String>,
+    },
+    List {
+        compare: bool,
+        explain: bool,
+        query: ListQuery,
+        view: Option<String>,
+        save: Option<String>,
+        archived: bool,
+        output: OutputFormat,
+    },
+    Views {
+        delete: Option<String>,
+    },
+    Show {
+        product: String,
+        output: OutputFormat,
+    },
+    Chart {
+        product: String,
+        height: Option<usize>,
+        width: Option<usize>,
+    },
+    Stats {
+        product: String,
+        window: Option<Window>,
+    },
+    Events {
+        product: Option<String>,
+    },
+    Stock {
+        product: Option<String>,
+    },
+    Profile {
+        name: Option<String>,
+    },
+    Tui,
+}
+
+impl Command {
+    fn from_matches(m: &Matches) -> Command {
+        match m.spec.name {
+            "help" => Command::Help {
+                command: m.value("command"),
+            },
+            "quit" => Command::Quit,
+            "add" => Command::Add {
+                url: m.value("url").unwrap(),
+            },
+            "update" => Command::Update { output: m.output() },
+            "remove" => Command::Remove {
+                product: m.value("product").unwrap(),
+                yes: m.flag("yes"),
+            },
+            "archive" => Command::Archive {
+                product: m.value("product").unwrap(),
+            },
+            "unarchive" => Command::Unarchive {
+                product: m.value("product").unwrap(),
+            },
+            "rename" => Command::Rename {
+                product: m.value("product").unwrap(),
+                name: m.joined("name"),
+            },
+            "note" => Command::Note {
+                product: m.value("product").unwrap(),
+                text: m.joined("text"),
+            },
+            "tag" => Command::Tag {
+                product: m.value("product").unwrap(),
+                tags: m.values("tag"),
+            },
+            "untag" => Command::Untag {
+                product: m.value("product").unwrap(),
+                tags: m.values("tag"),
+            },
+            "alert" => Command::Alert {
+                product: m.value("product"),
+                rule: m.values("rule"),
+                remove: m.value("remove").and_then(|v| v.parse().ok()),
+                reset: m.flag("reset"),
+            },
+            "rules" => Command::Rules {
+                action: m.value("action"),
+                name: m.value("name"),
+                expr: m.joined("expr"),
+                tag: m.value("tag"),
+                product: m.value("product"),
+                cooldown: m.value("cooldown"),
+                quiet: m.value("quiet"),
+            },
+            "backtest" => Command::Backtest {
+                rule: m.joined("rule").unwrap(),
+                tag: m.value("tag"),
+                product: m.value("product"),
+            },
+            "hooks" => Command::Hooks {
+                action: m.value("action"),
+                name: m.value("name"),
+                command: m.values("command"),
+                events: m.value("events"),
+                timeout: m.value("timeout").and_then(|v| v.parse().ok()),
+            },
+            "mail" => Command::Mail {
+                action: m.value("action"),
+            },
+            "webhooks" => Command::Webhooks {
+                action: m.value("action"),
+                name: m.value("name"),
+                url: m.value("url"),
+                format: m.value("format"),
+                secret: m.value("secret"),
+                template: m.value("template"),
+                events: m.value("events"),
+                retries: m.value("retries").and_then(|v| v.parse().ok()),
+            },
+            "feeds" => Command::Feeds {
+                action: m.value("action"),
+                name: m.value("name"),
+                path: m.value("path"),
+                format: m.value("format"),
+                tag: m.value("tag"),
+                limit: m.value("limit").and_then(|v| v.parse().ok()),
+                listen: m.value("listen"),
+            },
+            "list" => Command::List {
+                compare: m.flag("compare"),
+                explain: m.flag("explain"),
+                query: ListQuery {
+                    filter: m.joined("filter"),
+                    sort: m.value("sort"),
+                    reverse: m.flag("reverse"),
+                    tag: m.value("tag"),
+                },
+                view: m.value("view"),
+                save: m.value("save"),
+                archived: m.flag("archived"),
+                output: if m.flag("json") {
+                    OutputFormat::Json
+                } else {
+                    m.output()
+                },
+            },
+            "views" => Command::Views {
+                delete: m.value("delete"),
+            },
+            "show" => Command::Show {
+                product: m.value("product").unwrap(),
+                output: m.output(),
+            },
+            "chart" => Command::Chart {
+                product: m.value("product").unwrap(),
+                height: m.value("height").and_then(|v| v.parse().ok()),
+                width: m.value("width").and_then(|v| v.parse().ok()),
+            },
+            "stats" => Command::Stats {
+                product: m.value("product").unwrap(),
+                window: m.value("window").and_then(|v| Window::from_string(&v)),
+            },
+            "events" => Command::Events {
+                product: m.value("product"),
+            },
+            "stock" => Command::Stock {
+                product: m.value("product"),
+            },
+            "profile" => Command::Profile {
+                name: m.value("name"),
+            },
+            "tui" => Command::Tui,
+            _ => unreachable!("`{}` に対応する Command がありません", m.spec.name),
+        }
+    }
+}
+
+/// 引数の解析結果
+struct Matches {
+    spec: &'static CommandSpec,
+    values: Vec<(&'static str, String)>,
+    flags: Vec<&'static str>,
+}
+
+impl Matches {
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+
+    fn values(&self, name: &str) -> Vec<String> {
+        self.values
+            .iter()
+            .filter(|v| v.0 == name)
+            .map(|v| v.1.clone())
+            .collect()
+    }
+
+    /// 複数の値を空白でつないで返す。値がないときは None を返す。
+    fn joined(&self, name: &str) -> Option<String> {
+        let values = self.values(name);
+        if values.is_empty() {
+            return None;
+        }
+        Some(values.join(" "))
+    }
+
+    /// `--output` で指定された出力形式を返す。
+    fn output(&self) -> OutputFormat {
+        self.value("output")
+            .and_then(|v| OutputFormat::from_string(&v))
+            .unwrap_or_default()
+    }
+
+    fn value(&self, name: &str) -> Option<String> {
+        self.values
+            .iter()
+            .find(|v| v.0 == name)
+            .map(|v| v.1.clone())
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// コマンドの解析時のエラー
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// 引用符が閉じられていない
+    UnclosedQuote,
+    /// 不明なコマンド
+    UnknownCommand {
+        name: String,
+        suggestion: Option<&'static str>,
+    },
+    /// 不明なオプション
+    UnknownOption {
+        command: &'static str,
+        option: String,
+        suggestion: Option<String>,
+    },
+    /// 必須の引数がない
+    MissingArgument {
+        command: &'static str,
+        arg: &'static str,
+    },
+    /// 引数が多すぎる
+    TooManyArguments { command: &'static str, arg: String },
+    /// オプションの値がない
+    MissingValue {
+        command: &'static str,
+        option: &'static str,
+    },
+    /// 値が不正
+    InvalidValue {
+        command: &'static str,
+        arg: &'static str,
+        value: String,
+        expected: String,
+    },
+}
+
+impl std::error::Error for ParseError {}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnclosedQuote => f.write_str("引用符が閉じられていません。"),
+            ParseError::UnknownCommand { name, suggestion } => {
+                write!(f, "不明なコマンド `{}` です。", name)?;
+                if let Some(s) = suggestion {
+                    write!(f, "もしかして: `{}`", s)?;
+                }
+                f.write_str("\n`help` でコマンドの一覧を表示します。")
+            }
+            ParseError::UnknownOption {
+                command,
+                option,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "`{}` に不明なオプション `{}` が指定されました。",
+                    command, option
+                )?;
+                if let Some(s) = suggestion {
+                    write!(f, "もしかして: `{}`", s)?;
+                }
+                write!(f, "\n`{} --help` で使い方を表示します。", command)
+            }
+            ParseError::MissingArgument { command, arg } => write!(
+                f,
+                "`{}` には <{}> を指定してください。\n`{} --help` で使い方を表示します。",
+                command, arg, command
+            ),
+            ParseError::TooManyArguments { command, arg } => write!(
+                f,
+                "`{}` に余分な引数 `{}` が指定されました。\n`{} --help` で使い方を表示します。",
+                command, arg, command
+            ),
+            ParseError::MissingValue { command, option } => write!(
+                f,
+                "`--{}` には値を指定してください。\n`{} --help` で使い方を表示します。",
+                option, command
+            ),
+            ParseError::InvalidValue {
+                command,
+                arg,
+                value,
+                expected,
+            } => write!(
+                f,
+                "<{}> の値 `{}` が正しくありません（{}）。\n`{} --help` で使い方を表示します。",
+                arg, value, expected, command
+            ),
+        }
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 入力を空白で区切られた単語に分割する。
+/// 引用符（`"` または `'`）で囲まれた部分は、空白を含めて1つの単語として扱う。
+/// `"` の中と引用符の外では、`\` で次の文字をエスケープできる。
+pub fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
+    let mut tokens = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(t) = current.take() {
+                    tokens.push(t);
+                }
+            }
+            '"' | '\'' => {
+                let buf = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(x) if x == c => break,
+                        Some('\\') if c == '"' => match chars.next() {
+                            Some(x) => buf.push(x),
+                            None => return Err(ParseError::UnclosedQuote),
+                        },
+                        Some(x) => buf.push(x),
+                        None => return Err(ParseError::UnclosedQuote),
+                    }
+                }
+            }
+            '\\' => {
+                let buf = current.get_or_insert_with(String::new);
+                if let Some(x) = chars.next() {
+                    buf.push(x);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(t) = current {
+        tokens.push(t);
+    }
+    Ok(tokens)
+}
+
+/// 単語に分割された入力を解析する。
+/// `--help` (`-h`) が指定されたときは、そのコマンドのヘルプを表示するコマンドを返す。
+pub fn parse<S: AsRef<str>>(tokens: &[S]) -> Result<Command, ParseError> {
+    let Some(name) = tokens.first().map(|v| v.as_ref()) else {
+        return Ok(Command::Help { command: None });
+    };
+    let spec = find_command(name).ok_or_else(|| ParseError::UnknownCommand {
+        name: name.to_string(),
+        suggestion: suggest(name, COMMANDS.iter().map(|v| v.name)),
+    })?;
+
+    let mut matches = Matches {
+        spec,
+        values: vec![],
+        flags: vec![],
+    };
+    let positionals: Vec<_> = spec
+        .args
+        .iter()
+        .filter(|v| v.kind.is_positional())
+        .collect();
+    let mut position = 0;
+    let mut rest = tokens[1..].iter().map(|v| v.as_ref());
+    let mut options_ended = false;
+
+    while let Some(token) = rest.next() {
+        if !options_ended && token == "--" {
+            options_ended = true;
+            continue;
+        }
+        if !options_ended && (token == "--help" || token == "-h") {
+            return Ok(Command::Help {
+                command: Some(spec.name.to_string()),
+            });
+        }
+
+        // オプション
+        if !options_ended && token.starts_with('-') && token.len() > 1 {
+            let (key, inline) = match token.split_once('=') {
+                Some((k, v)) => (k, Some(v.to_string())),
+                None => (token, None),
+            };
+            let arg = find_option(spec, key).ok_or_else(|| ParseError::UnknownOption {
+                command: spec.name,
+                option: key.to_string(),
+                suggestion: suggest(
+                    key.trim_start_matches('-'),
+                    spec.args
+                        .iter()
+                        .filter(|v| !v.kind.is_positional())
+                        .map(|v| v.name),
+                )
+                .map(|v| format!("--{}", v)),
+            })?;
+            match arg.kind {
+                ArgKind::Flag => {
+                    if inline.is_some() {
+                        return Err(ParseError::TooManyArguments {
+                            command: spec.name,
+                            arg: token.to_string(),
+                        });
+                    }
+                    matches.flags.push(arg.name);
+                }
+                ArgKind::Option { value } => {
+                    let v = match inline {
+                        Some(v) => v,
+                        None => rest
+                            .next()
+                            .ok_or(ParseError::MissingValue {
+                                command: spec.name,
+                                option: arg.name,
+                            })?
+                            .to_string(),
+                    };
+                    validate(spec, arg, value, &v)?;
+                    matches.values.retain(|x| x.0 != arg.name);
+                    matches.values.push((arg.name, v));
+                }
+                ArgKind::Positional { .. } | ArgKind::Rest { .. } => unreachable!(),
+            }
+            continue;
+        }
+
+        // 位置引数
+        let Some(arg) = positionals.get(position) else {
+            return Err(ParseError::TooManyArguments {
+                command: spec.name,
+                arg: token.to_string(),
+            });
+        };
+        match arg.kind {
+            ArgKind::Positional { value, .. } => {
+                validate(spec, arg, value, token)?;
+                position += 1;
+            }
+            ArgKind::Rest { value, .. } => {
+                // 残りの引数は全てこの引数の値とするため、位置は進めない。
+                validate(spec, arg, value, token)?;
+            }
+            _ => unreachable!(),
+        }
+        matches.values.push((arg.name, token.to_string()));
+    }
+
+    for arg in positionals.iter().skip(position) {
+        let required = match arg.kind {
+            ArgKind::Positional { required, .. } | ArgKind::Rest { required, .. } => required,
+            _ => false,
+        };
+        if required && matches.value(arg.name).is_none() {
+            return Err(ParseError::MissingArgument {
+                command: spec.name,
+                arg: arg.name,
+            });
+        }
+    }
+
+    Ok(Command::from_matches(&matches))
+}
+
+/// 名称または別名からコマンドの定義を探す。
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|v| v.name == name || v.aliases.contains(&name))
+}
+
+/// `--name` または `-n` の形式からオプションの定義を探す。
+pub(crate) fn find_option(spec: &'static CommandSpec, key: &str) -> Option<&'static ArgSpec> {
+    let options = spec.args.iter().filter(|v| !v.kind.is_positional());
+    if let Some(long) = key.strip_prefix("--") {
+        return options.clone().find(|v| v.name == long);
+    }
+    let short = key.strip_prefix('-')?;
+    let mut chars = short.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    options.clone().find(|v| v.short == Some(c))
+}
+
+/// 値が引数の種類に合っているかを検証する。
+fn validate(
+    spec: &'static CommandSpec,
+    arg: &'static ArgSpec,
+    kind: ValueKind,
+    value: &str,
+) -> Result<(), ParseError> {
+    let expected = match kind {
+        ValueKind::Text | ValueKind::Product => return Ok(()),
+        ValueKind::Url => {
+            if value.starts_with("http://") || value.starts_with("https://") {
+                return Ok(());
+            }
+            "http:// または https:// で始まるURL".to_string()
+        }
+        ValueKind::Window => {
+            if Window::from_string(value).is_some() {
+                return Ok(());
+            }
+            "30d や all のような期間".to_string()
+        }
+        ValueKind::Number => {
+            if value.parse::<u32>().is_ok_and(|v| v > 0) {
+                return Ok(());
+            }
+            "正の整数".to_string()
+        }
+        ValueKind::Choice(choices) => {
+            if choices.contains(&value) {
+                return Ok(());
+            }
+            let mut s = format!("{} のいずれか", choices.join(", "));
+            if let Some(x) = suggest(value, choices.iter().copied()) {
+                s = format!("{}。もしかして: `{}`", s, x);
+            }
+            s
+        }
+    };
+    Err(ParseError::InvalidValue {
+        command: spec.name,
+        arg: arg.name,
+        value: value.to_string(),
+        expected,
+    })
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 候補の中から、入力に最も近いものを返す。
+/// 近いものがないときは None を返す。
+pub(crate) fn suggest<'a, I>(input: &str, candidates: I) -> Option<I::Item>
+where
+    I: Iterator<Item = &'a str>,
+{
+    candidates
+        .map(|v| (edit_distance(input, v), v))
+        .filter(|&(d, v)| {
+            (d <= 2 && d < v.chars().count())
+                || (input.len() >= 2 && (v.starts_with(input) || input.starts_with(v)))
+        })
+        .min_by_key(|v| v.0)
+        .map(|v| v.1)
+}
+
+/// 2つの文字列の編集距離（レーベンシュタイン距離）を返す。
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// コマンドの一覧を表示する。
+pub fn print_help() {
+    println!("yodopriceは、ヨドバシ.comの価格の履歴を取得・表示するアプリです。");
+    for spec in COMMANDS {
+        if spec.aliases.is_empty() {
+            println!(" {}", usage(spec));
+        } else {
+            println!(" {}  ({})", usage(spec), spec.aliases.join(", "));
+        }
+        println!("     {}", spec.summary);
+    }
+    println!("各コマンドの詳しい使い方は `<command> --help` で表示します。");
+}
+
+/// コマンドの使い方を表示する。
+pub fn print_command_help(spec: &CommandSpec) {
+    println!("使い方: {}", usage(spec));
+    if !spec.aliases.is_empty() {
+        println!("別名: {}", spec.aliases.join(", "));
+    }
+    println!("{}", spec.summary);
+    for arg in spec.args {
+        let name = match (arg.kind, arg.short) {
+            (ArgKind::Positional { .. }, _) => format!("<{}>", arg.name),
+            (ArgKind::Rest { .. }, _) => format!("<{}...>", arg.name),
+            (_, Some(c)) => format!("-{}, --{}", c, arg.name),
+            (_, None) => format!("    --{}", arg.name),
+        };
+        let choices = match arg.kind {
+            ArgKind::Option {
+                value: ValueKind::Choice(c),
+            } => format!(" ({})", c.join(", ")),
+            _ => String::new(),
+        };
+        println!("  {:<20} {}{}", name, arg.help, choices);
+    }
+}
+
+/// `list [--compare] [--sort <sort>]` のような使い方の文字列を返す。
+fn usage(spec: &CommandSpec) -> String {
+    let mut s = spec.name.to_string();
+    for arg in spec.args {
+        let part = match arg.kind {
+            ArgKind::Positional { required: true, .. } => format!("<{}>", arg.name),
+            ArgKind::Positional {
+                required: false, ..
+            } => format!("[{}]", arg.name),
+            ArgKind::Rest { required: true, .. } => format!("<{}...>", arg.name),
+            ArgKind::Rest {
+                required: false, ..
+            } => format!("[{}...]", arg.name),
+            ArgKind::Flag => format!("[--{}]", arg.name),
+            ArgKind::Option { .. } => format!("[--{} <{}>]", arg.name, arg.name),
+        };
+        s.push(' ');
+        s.push_str(&part);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(input: &str) -> Result<Command, ParseError> {
+        parse(&tokenize(input)?)
+    }
+
+    #[test]
+    fn tokenize_test() {
+        assert_eq!(vec!["list", "-c"], tokenize("  list   -c ").unwrap());
+        assert_eq!(
+            vec!["profile", "gold card", "it's"],
+            tokenize(r#"profile "gold card" 'it'\''s'"#).unwrap()
+        );
+        assert_eq!(vec![r#"a"b"#], tokenize(r#""a\"b""#).unwrap());
+        assert_eq!(Vec::<String>::new(), tokenize("   ").unwrap());
+        assert_eq!(Err(ParseError::UnclosedQuote), tokenize(r#"add "http"#));
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Ok(Command::Quit), parse_str("q"));
+        assert_eq!(
+            Ok(Command::Add {
+                url: "https://www.yodobashi.com/product/1/".to_string()
+            }),
+            parse_str("a https://www.yodobashi.com/product/1/")
+        );
+        assert_eq!(
+            Ok(Command::List {
+                compare: true,
+                explain: false,
+                query: ListQuery {
+                    sort: Some("score".to_string()),
+                    ..Default::default()
+                },
+                view: None,
+                save: None,
+                archived: false,
+                output: OutputFormat::Human,
+            }),
+            parse_str("list -c --sort=score")
+        );
+        assert_eq!(
+            Ok(Command::List {
+                compare: false,
+                explain: false,
+                query: ListQuery {
+                    filter: Some("maker = Sony and price<30000".to_string()),
+                    sort: Some("price".to_string()),
+                    reverse: true,
+                    tag: None,
+                },
+                view: None,
+                save: Some("sony".to_string()),
+                archived: false,
+                output: OutputFormat::Human,
+            }),
+            parse_str("list -s price -r --save sony maker = Sony and price<30000")
+        );
+        assert_eq!(
+            Ok(Command::Stats {
+                product: "1".to_string(),
+                window: Some(Window::Days(30))
+            }),
+            parse_str("stats 1 30d")
+        );
+        assert_eq!(
+            Ok(Command::Help {
+                command: Some("list".to_string())
+            }),
+            parse_str("l --help")
+        );
+    }
+
+    #[test]
+    fn parse_remove_test() {
+        assert_eq!(
+            Ok(Command::Remove {
+                product: "2".to_string(),
+                yes: true
+            }),
+            parse_str("rm -y 2")
+        );
+        assert_eq!(
+            Ok(Command::Unarchive {
+                product: "100000001007414874".to_string()
+            }),
+            parse_str("unarchive 100000001007414874")
+        );
+    }
+
+    #[test]
+    fn parse_rest_test() {
+        assert_eq!(
+            Ok(Command::Tag {
+                product: "1".to_string(),
+                tags: vec!["camera".to_string(), "gift ideas".to_string()]
+            }),
+            parse_str(r#"tag 1 camera "gift ideas""#)
+        );
+        assert_eq!(
+            Ok(Command::Note {
+                product: "1".to_string(),
+                text: Some("wait for sale".to_string())
+            }),
+            parse_str("note 1 wait for sale")
+        );
+        assert_eq!(
+            Ok(Command::Rename {
+                product: "1".to_string(),
+                name: None
+            }),
+            parse_str("rename 1")
+        );
+        assert_eq!(
+            Ok(Command::Alert {
+                product: Some("2".to_string()),
+                rule: vec!["price".to_string(), "30000".to_string()],
+                remove: None,
+                reset: false,
+            }),
+            parse_str("alert 2 price 30000")
+        );
+        assert_eq!(
+            Ok(Command::Rules {
+                action: Some("add".to_string()),
+                name: Some("cheap".to_string()),
+                expr: Some("delta(point_ratio) >= 3 and in_stock".to_string()),
+                tag: Some("audio".to_string()),
+                product: None,
+                cooldown: Some("12h".to_string()),
+                quiet: None,
+            }),
+            parse_str(
+                "rules add cheap -t audio delta(point_ratio) >= 3 and in_stock --cooldown 12h"
+            )
+        );
+        assert_eq!(
+            Ok(Command::Backtest {
+                rule: "price < 30000".to_string(),
+                tag: None,
+                product: Some("2".to_string()),
+            }),
+            parse_str("backtest -p 2 price < 30000")
+        );
+        assert_eq!(
+            Ok(Command::Webhooks {
+                action: Some("add".to_string()),
+                name: Some("ops".to_string()),
+                url: Some("http://localhost:8080/hook".to_string()),
+                format: None,
+                secret: Some("s3cret".to_string()),
+                template: None,
+                events: Some("alert".to_string()),
+                retries: Some(5),
+            }),
+            parse_str(
+                "webhooks add ops http://localhost:8080/hook --secret s3cret --events alert --retries 5"
+            )
+        );
+        assert_eq!(
+            Ok(Command::Hooks {
+                action: Some("add".to_string()),
+                name: Some("log".to_string()),
+                command: vec!["/usr/local/bin/notify.sh".to_string(), "--quiet".to_string()],
+                events: Some("change,error".to_string()),
+                timeout: Some(10),
+            }),
+            parse_str("hooks add log --events change,error --timeout 10 /usr/local/bin/notify.sh -- --quiet")
+        );
+        assert_eq!(
+            Ok(Command::Feeds {
+                action: Some("add".to_string()),
+                name: Some("audio".to_string()),
+                path: Some("feeds/audio.xml".to_string()),
+                format: Some("rss".to_string()),
+                tag: Some("audio".to_string()),
+                limit: Some(20),
+                listen: None,
+            }),
+            parse_str("feeds add audio feeds/audio.xml -f rss -t audio --limit 20")
+        );
+        assert!(matches!(
+            parse_str("webhooks add ops http://localhost:8080/hook -f teams"),
+            Err(ParseError::InvalidValue { arg: "format", .. })
+        ));
+        assert_eq!(
+            Err(ParseError::MissingArgument {
+                command: "tag",
+                arg: "tag"
+            }),
+            parse_str("tag 1")
+        );
+    }
+
+    #[test]
+    fn parse_error_test() {
+        assert_eq!(
+            Err(ParseError::UnknownCommand {
+                name: "listing".to_string(),
+                suggestion: Some("list")
+            }),
+            parse_str("listing")
+        );
+        assert_eq!(
+            Err(ParseError::UnknownCommand {
+                name: "quitter".to_string(),
+                suggestion: Some("quit")
+            }),
+            parse_str("quitter")
+        );
+        assert_eq!(
+            Err(ParseError::UnknownCommand {
+                name: "xyz".to_string(),
+                suggestion: None
+            }),
+            parse_str("xyz")
+        );
+        assert_eq!(
+            Err(ParseError::MissingArgument {
+                command: "add",
+                arg: "url"
+            }),
+            parse_str("a")
+        );
+        assert_eq!(
+            Err(ParseError::UnknownOption {
+                command: "list",
+                option: "--comapre".to_string(),
+                suggestion: Some("--compare".to_string())
+            }),
+            parse_str("list --comapre")
+        );
+        assert!(matches!(
+            parse_str("add www.yodobashi.com"),
+            Err(ParseError::InvalidValue { arg: "url", .. })
+        ));
+        assert!(matches!(
+            parse_str("stats 1 2"),
+            Err(ParseError::InvalidValue { arg: "window", .. })
+        ));
+        assert!(matches!(
+            parse_str("chart 1 --height 0"),
+            Err(ParseError::InvalidValue { arg: "height", .. })
+        ));
+        assert!(matches!(
+            parse_str("webhooks add a https://example.com --retries 99999999999"),
+            Err(ParseError::InvalidValue { arg: "retries", .. })
+        ));
+        assert!(matches!(
+            parse_str("list --sort"),
+            Err(ParseError::MissingValue { option: "sort", .. })
+        ));
+        assert!(matches!(
+            parse_str("update now"),
+            Err(ParseError::TooManyArguments { .. })
+        ));
+    }
+
+    #[test]
+    fn edit_distance_test() {
+        assert_eq!(0, edit_distance("list", "list"));
+        assert_eq!(3, edit_distance("listing", "list"));
+        assert_eq!(2, edit_distance("comapre", "compare"));
+    }
+}
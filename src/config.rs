@@ -0,0 +1,191 @@
+use std::io::Write;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::feed::Feed;
+use crate::hook::Hook;
+use crate::mail::MailConfig;
+use crate::query::ListQuery;
+use crate::rule::Rule;
+use crate::util::set_current_dir_to_exe;
+use crate::webhook::Webhook;
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// アプリケーションの設定。
+pub static CONFIG: Mutex<Config> = Mutex::new(Config {
+    payment_profiles: vec![],
+    active_profile: None,
+    views: vec![],
+    rules: vec![],
+    webhooks: vec![],
+    hooks: vec![],
+    feeds: vec![],
+    mail: None,
+});
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 設定ファイルの内容
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// 支払い方法ごとのポイント補正
+    #[serde(default)]
+    pub payment_profiles: Vec<PaymentProfile>,
+    /// 使用中の支払い方法の名称
+    #[serde(default)]
+    pub active_profile: Option<String>,
+    /// 保存した一覧の表示条件
+    #[serde(default)]
+    pub views: Vec<SavedView>,
+    /// アラートのルール
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// 通知先の Webhook
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    /// イベントごとに実行する外部コマンド
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+    /// `update` のたびに書き出すフィード
+    #[serde(default)]
+    pub feeds: Vec<Feed>,
+    /// メールの送信の設定。None のときはメールを送らない。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mail: Option<MailConfig>,
+}
+
+impl Config {
+    /// 設定をファイルから読み込む。
+    /// ファイルが存在しないときは、デフォルトの設定を返す。内容が正しくないときはエラーを返す。
+    pub fn from_file() -> Result<Self, serde_json::Error> {
+        set_current_dir_to_exe();
+        match std::fs::read_to_string(CONFIG_FILE_NAME) {
+            Ok(input) => serde_json::from_str(&input),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// 設定をファイルへ出力する。
+    pub fn write_file(&self) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self).unwrap();
+
+        set_current_dir_to_exe();
+        let mut file = std::fs::File::create(CONFIG_FILE_NAME)?;
+        file.write_all(serialized.as_bytes())?;
+        Ok(())
+    }
+
+    /// 登録されている支払い方法の一覧を返す。
+    /// 1つも登録されていないときは、補正なしの支払い方法のみを返す。
+    pub fn profiles(&self) -> Vec<PaymentProfile> {
+        if self.payment_profiles.is_empty() {
+            return vec![PaymentProfile::default()];
+        }
+        self.payment_profiles.clone()
+    }
+
+    /// 指定された名称の支払い方法を返す。
+    pub fn profile(&self, name: &str) -> Option<PaymentProfile> {
+        self.profiles().into_iter().find(|v| v.name == name)
+    }
+
+    /// 使用中の支払い方法を返す。
+    /// 未設定のとき（または該当するものがないとき）は、先頭の支払い方法を返す。
+    pub fn active_profile(&self) -> PaymentProfile {
+        let profiles = self.profiles();
+        self.active_profile
+            .as_ref()
+            .and_then(|name| profiles.iter().find(|v| &v.name == name))
+            .unwrap_or(&profiles[0])
+            .clone()
+    }
+
+    /// 指定された名称の表示条件を返す。
+    pub fn view(&self, name: &str) -> Option<&SavedView> {
+        self.views.iter().find(|v| v.name == name)
+    }
+
+    /// 表示条件を保存する。同じ名称のものがあるときは置き換える。
+    pub fn save_view(&mut self, name: &str, query: ListQuery) {
+        let view = SavedView {
+            name: name.to_string(),
+            query,
+        };
+        match self.views.iter_mut().find(|v| v.name == name) {
+            Some(x) => *x = view,
+            None => self.views.push(view),
+        }
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 名前を付けて保存した一覧の表示条件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedView {
+    pub name: String,
+    #[serde(flatten)]
+    pub query: ListQuery,
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 支払い方法によるポイントの補正
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentProfile {
+    /// 支払い方法の名称
+    pub name: String,
+    /// ポイント還元率に加算する値(%)。減算するときは負の値にする。
+    #[serde(default)]
+    pub point_ratio_offset: i64,
+    /// 1ポイントあたりの価値（円）。ポイントが付かないときは 0 にする。
+    #[serde(default = "default_point_value")]
+    pub point_value: f64,
+}
+
+impl Default for PaymentProfile {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            point_ratio_offset: 0,
+            point_value: default_point_value(),
+        }
+    }
+}
+
+fn default_point_value() -> f64 {
+    1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_profile_test() {
+        let mut config = Config::default();
+        assert_eq!("default", config.active_profile().name);
+
+        config.payment_profiles = vec![
+            PaymentProfile {
+                name: "gold".to_string(),
+                point_ratio_offset: 1,
+                point_value: 1.0,
+            },
+            PaymentProfile {
+                name: "invoice".to_string(),
+                point_ratio_offset: 0,
+                point_value: 0.0,
+            },
+        ];
+        assert_eq!("gold", config.active_profile().name);
+
+        config.active_profile = Some("invoice".to_string());
+        assert_eq!("invoice", config.active_profile().name);
+
+        config.active_profile = Some("unknown".to_string());
+        assert_eq!("gold", config.active_profile().name);
+    }
+}
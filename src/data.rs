@@ -1,12 +1,17 @@
 use std::io::Write;
 use std::sync::Mutex;
 
-use chrono::serde::ts_seconds;
+use chrono::serde::{ts_seconds, ts_seconds_option};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::access::get_data;
+use crate::alert::{evaluate_all, Alert, AlertContext, FiredAlert};
+use crate::config::PaymentProfile;
+use crate::rule::RuleState;
 use crate::types::{AlreadyExistsError, StockStatus, WebData};
+use crate::util::set_current_dir_to_exe;
 
 const DATA_FILE_NAME: &str = "data.json";
 
@@ -23,10 +28,30 @@ pub struct AppData {
 
 impl AppData {
     /// データをファイルから読み込む。
-    pub fn from_file() -> Self {
-        let data = read_data_file();
-        let histories = data.unwrap_or_default();
-        Self { histories }
+    /// ファイルが存在しないときは空のデータを返す。読み込めないときや内容が正しくないときはエラーを返す。
+    pub fn from_file() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut histories = read_data_file()?;
+        // IDが割り振られていない製品（古いデータ）には、URLからIDを割り振る。
+        for h in histories.iter_mut().filter(|v| v.id.is_empty()) {
+            h.id = product_id_from_url(&h.url);
+        }
+        // 登録日時が記録されていない製品（古いデータ）は、最初の価格データの日時を登録日時とする。
+        for h in histories.iter_mut().filter(|v| v.registered_at.is_none()) {
+            h.registered_at = h.history.first().map(|v| v.datetime);
+        }
+        // 在庫状況を記録する前の価格データ（古いデータ）は、全て「お取り寄せ」として保存されている。
+        // 先頭から続く「お取り寄せ」は、在庫状況を記録する前のものとみなして「不明」にする。
+        for h in histories.iter_mut().filter(|v| !v.status_tracked) {
+            for p in h
+                .history
+                .iter_mut()
+                .take_while(|v| v.status == StockStatus::BackOrder)
+            {
+                p.status = StockStatus::Unknown;
+            }
+            h.status_tracked = true;
+        }
+        Ok(Self { histories })
     }
 
     /// データをファイルへ出力する。
@@ -38,7 +63,16 @@ impl AppData {
     /// URLにアクセスできないときや、すでに登録済みのときはエラーを返す。
     pub fn add_from_url(&mut self, url: &str) -> Result<(), Box<dyn std::error::Error>> {
         let data = get_data(url)?;
+        self.add_from_web_data(url, data, Utc::now())
+    }
 
+    /// `fetched_at` に取得したデータから製品を追加する。すでに登録済みのときはエラーを返す。
+    pub fn add_from_web_data(
+        &mut self,
+        url: &str,
+        data: WebData,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         // すでに登録されているURLと重複チェックし、重複する場合はエラーを返す。
         let url_list = self.url_list();
         if url_list.iter().any(|v| v == url) {
@@ -48,30 +82,53 @@ impl AppData {
         }
 
         // 新規追加する。
-        let product = ProductHistory::from_web_data(data);
+        let product = ProductHistory::from_web_data(data, fetched_at);
         self.histories.push(product);
 
         Ok(())
     }
 
     // 登録されている全製品の価格を取得し、更新する。
-    // TODO: リターン後の表示のため、戻り値の型を Vec<Result<(), String>>に変える。
-    pub fn update_all(&mut self) -> Result<(), Vec<String>> {
-        let mut array = vec![];
-
-        for url in self.url_list() {
-            let result = get_data(&url);
-            match result {
-                Ok(data) => {
-                    let item = self.borrow_mut_product_by_url(&url);
-                    if let Some(v) = item {
-                        v.add_web_data(data)
-                    }
-                }
-                Err(_) => array.push(format!("データを取得できませんでした{}", url)),
+    pub fn update_all(&mut self, context: &AlertContext) -> UpdateResult {
+        let mut result = UpdateResult::default();
+
+        // アーカイブされた製品は更新しない。
+        let urls: Vec<_> = self
+            .histories
+            .iter()
+            .filter(|v| !v.archived)
+            .map(|v| v.url.clone())
+            .collect();
+        for url in urls {
+            let fetched = get_data(&url).map_err(|e| e.to_string());
+            if let Some(item) = self.apply_update(&url, fetched, Utc::now(), context) {
+                result.items.push(item);
             }
         }
-        Ok(())
+        result
+    }
+
+    /// `fetched_at` に取得したデータ（または取得時のエラー）を製品に反映し、
+    /// アラートとルールを評価して、その結果を返す。
+    /// 該当するURLの製品がないとき（取得中に削除された等）は None を返す。
+    pub fn apply_update(
+        &mut self,
+        url: &str,
+        fetched: Result<WebData, String>,
+        fetched_at: DateTime<Utc>,
+        context: &AlertContext,
+    ) -> Option<UpdateItem> {
+        let product = self.borrow_mut_product_by_url(url)?;
+        let mut item = UpdateItem::new(product);
+        match fetched {
+            Ok(data) => {
+                product.add_web_data(data, fetched_at);
+                item.after = product.history.last().cloned();
+                item.alerts = evaluate_all(product, context);
+            }
+            Err(e) => item.error = Some(format!("データを取得できませんでした({})", e)),
+        }
+        Some(item)
     }
 
     // 登録されている製品のURLの一覧を返す。
@@ -80,6 +137,30 @@ impl AppData {
         array
     }
 
+    /// 指定された製品の価格履歴の借用を返す。
+    /// 製品は、一覧での番号(1始まり)またはIDで指定する。
+    pub fn find_product(&self, key: &str) -> Option<&ProductHistory> {
+        let index = self.product_index(key)?;
+        self.histories.get(index)
+    }
+
+    /// 指定された製品の、`histories` 内での位置を返す。
+    /// 製品は、一覧での番号(1始まり)またはIDで指定する。
+    pub fn product_index(&self, key: &str) -> Option<usize> {
+        if let Some(i) = self.histories.iter().position(|v| v.id == key) {
+            return Some(i);
+        }
+        match key.parse::<usize>() {
+            Ok(n) if 1 <= n && n <= self.histories.len() => Some(n - 1),
+            _ => None,
+        }
+    }
+
+    /// 指定された位置の製品を削除し、削除した製品を返す。
+    pub fn remove_product(&mut self, index: usize) -> ProductHistory {
+        self.histories.remove(index)
+    }
+
     // 指定されたURLを持つ価格履歴の借用を返す。
     // pub fn borrow_product_by_url(&self, url: &str) -> Option<&ProductHistory> {
     //     self.histories.iter().find(|&v| v.url == url)
@@ -91,10 +172,127 @@ impl AppData {
     }
 }
 
+/// 全製品の価格更新の結果
+#[derive(Debug, Default)]
+pub struct UpdateResult {
+    /// 製品ごとの結果
+    pub items: Vec<UpdateItem>,
+}
+
+impl UpdateResult {
+    /// 価格が変化した製品の結果を返す。
+    pub fn changed(&self) -> impl Iterator<Item = &UpdateItem> {
+        self.items.iter().filter(|v| v.is_changed())
+    }
+
+    /// 取得に失敗した製品の結果を返す。
+    pub fn errors(&self) -> impl Iterator<Item = &UpdateItem> {
+        self.items.iter().filter(|v| v.error.is_some())
+    }
+}
+
+/// 1つの製品の価格更新の結果
+#[derive(Debug, Clone, Default)]
+pub struct UpdateItem {
+    pub id: String,
+    /// 表示用の名称
+    pub name: String,
+    pub url: String,
+    /// 更新前の最新の価格データ
+    pub before: Option<OnePrice>,
+    /// 更新後の最新の価格データ。取得に失敗したときは None
+    pub after: Option<OnePrice>,
+    /// 更新前の最高値
+    pub old_high: Option<u64>,
+    /// 更新前の最安値
+    pub old_low: Option<u64>,
+    /// 取得に失敗したときのメッセージ
+    pub error: Option<String>,
+    /// 今回の更新で条件を満たしたアラートとルール
+    pub alerts: Vec<FiredAlert>,
+}
+
+impl UpdateItem {
+    /// 更新前の製品の状態を記録する。
+    pub fn new(product: &ProductHistory) -> Self {
+        Self {
+            id: product.id.clone(),
+            name: product.display_name().to_string(),
+            url: product.url.clone(),
+            before: product.history.last().cloned(),
+            after: None,
+            old_high: product.history.iter().map(|v| v.price).max(),
+            old_low: product.history.iter().map(|v| v.price).min(),
+            error: None,
+            alerts: vec![],
+        }
+    }
+
+    /// 更新前の価格を返す。
+    pub fn old_price(&self) -> Option<u64> {
+        self.before.as_ref().map(|v| v.price)
+    }
+
+    /// 更新後の価格を返す。
+    pub fn price(&self) -> Option<u64> {
+        self.after.as_ref().map(|v| v.price)
+    }
+
+    /// 価格が変化したかどうかを返す。
+    pub fn is_changed(&self) -> bool {
+        self.price().is_some() && self.price() != self.old_price()
+    }
+
+    /// 価格の変化（円）を返す。
+    pub fn change(&self) -> Option<i64> {
+        Some(self.price()? as i64 - self.old_price()? as i64)
+    }
+
+    /// 価格の変化率（%）を返す。
+    pub fn change_ratio(&self) -> Option<f64> {
+        let old = self.old_price()?;
+        if old == 0 {
+            return None;
+        }
+        Some(self.change()? as f64 * 100.0 / old as f64)
+    }
+
+    /// 最安値を更新したかどうかを返す。
+    pub fn is_new_low(&self) -> bool {
+        matches!((self.price(), self.old_low), (Some(x), Some(low)) if x < low)
+    }
+
+    /// 最高値を更新したかどうかを返す。
+    pub fn is_new_high(&self) -> bool {
+        matches!((self.price(), self.old_high), (Some(x), Some(high)) if x > high)
+    }
+
+    /// 在庫状況が変化したときは、変化前と変化後の在庫状況を返す。
+    pub fn status_change(&self) -> Option<(StockStatus, StockStatus)> {
+        let (before, after) = (self.before.as_ref()?, self.after.as_ref()?);
+        (before.status != after.status).then_some((before.status, after.status))
+    }
+
+    /// ポイント還元率が変化したときは、変化前と変化後の還元率を返す。
+    pub fn point_ratio_change(&self) -> Option<(u64, u64)> {
+        let (before, after) = (self.before.as_ref()?, self.after.as_ref()?);
+        (before.point_ratio != after.point_ratio).then_some((before.point_ratio, after.point_ratio))
+    }
+
+    /// 報告すべき変化（価格・在庫状況・還元率の変化、アラート、または取得の失敗）があるかどうかを返す。
+    pub fn is_notable(&self) -> bool {
+        self.error.is_some()
+            || !self.alerts.is_empty()
+            || self.is_changed()
+            || self.status_change().is_some()
+            || self.point_ratio_change().is_some()
+    }
+}
+
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
 
 /// 1つの製品における価格等の履歴データ
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ProductHistory {
     /// このアプリで独自に割り振ったID
     pub id: String,
@@ -108,34 +306,105 @@ pub struct ProductHistory {
     pub history: Vec<OnePrice>,
     /// 製造メーカ
     pub maker: String,
+    /// アーカイブされているかどうか。アーカイブされた製品は価格を更新しない。
+    #[serde(default)]
+    pub archived: bool,
+    /// メモ
+    #[serde(default)]
+    pub note: Option<String>,
+    /// タグ
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// SKU（ヨドバシ.comの製品番号）
+    #[serde(default)]
+    pub sku: String,
+    /// カテゴリのコード
+    #[serde(default)]
+    pub category: String,
+    /// 登録日時
+    #[serde(default, with = "ts_seconds_option")]
+    pub registered_at: Option<DateTime<Utc>>,
+    /// 最後に価格を取得した日時
+    #[serde(default, with = "ts_seconds_option")]
+    pub last_checked: Option<DateTime<Utc>>,
+    /// 価格のアラート
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+    /// ルールごとの通知状態
+    #[serde(default)]
+    pub rule_states: Vec<RuleState>,
+    /// 在庫状況を記録する前の価格データを「不明」に変換済みかどうか
+    #[serde(default)]
+    pub status_tracked: bool,
 }
 
 impl ProductHistory {
-    /// サイトから取得したデータを元に、新しい製品データを生成する。
-    fn from_web_data(data: WebData) -> Self {
-        let price = OnePrice::from_web_data(data.clone());
-        // TODO: IDを生成する
+    /// `datetime` にサイトから取得したデータを元に、新しい製品データを生成する。
+    fn from_web_data(data: WebData, datetime: DateTime<Utc>) -> Self {
+        let price = OnePrice::from_web_data(data.clone(), datetime);
         Self {
-            id: "".to_string(),
+            id: product_id_from_url(&data.url),
             name: data.name,
             custom_name: None,
             url: data.url,
             history: vec![price],
             maker: data.maker,
+            archived: false,
+            note: None,
+            tags: vec![],
+            sku: data.sku,
+            category: data.category,
+            registered_at: Some(datetime),
+            last_checked: Some(datetime),
+            alerts: vec![],
+            rule_states: vec![],
+            status_tracked: true,
+        }
+    }
+
+    /// 指定した名称のルールの通知状態を返す。まだないときは追加する。
+    pub fn rule_state_mut(&mut self, rule: &str) -> &mut RuleState {
+        match self.rule_states.iter().position(|v| v.rule == rule) {
+            Some(i) => &mut self.rule_states[i],
+            None => {
+                self.rule_states.push(RuleState::new(rule));
+                self.rule_states.last_mut().unwrap()
+            }
         }
     }
 
-    /// Webページから取得したデータを元に、価格履歴を追加する。
-    /// 前回と同じ価格だったときは追加しない。
-    fn add_web_data(&mut self, data: WebData) {
-        let item = OnePrice::from_web_data(data);
+    /// 表示用の名称を返す。自分で設定した名称があるときはそれを、ないときは製品名を返す。
+    pub fn display_name(&self) -> &str {
+        self.custom_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// 指定されたタグが付いているかどうかを返す。
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|v| v == tag)
+    }
+
+    /// `datetime` にWebページから取得したデータを元に、価格履歴を追加する。
+    /// 前回と同じ価格・在庫状況・ポイント還元率だったときは追加しない。
+    /// 価格が変化したときは true を返す。
+    fn add_web_data(&mut self, data: WebData, datetime: DateTime<Utc>) -> bool {
+        if !data.sku.is_empty() {
+            self.sku = data.sku.clone();
+        }
+        if !data.category.is_empty() {
+            self.category = data.category.clone();
+        }
+        let item = OnePrice::from_web_data(data, datetime);
+        self.last_checked = Some(item.datetime);
         let last = self.history.last();
+        let changed = last.is_none_or(|x| x.price != item.price);
         if let Some(x) = last {
-            if x.price == item.price {
-                return;
+            if x.price == item.price && x.status == item.status && x.point_ratio == item.point_ratio
+            {
+                return false;
             }
         }
         self.history.push(item);
+        changed
     }
 
     /// 価格履歴で、最高値・最安値・現在価格を返す。
@@ -159,10 +428,28 @@ impl ProductHistory {
     }
 }
 
+/// URLから製品のIDを生成する。
+/// ヨドバシ.comの製品URL(`/product/<製品番号>/`)のときは製品番号を、それ以外のときはURLのハッシュ値を元にしたIDを返す。
+/// 同じURLからは常に同じIDを生成する。
+fn product_id_from_url(url: &str) -> String {
+    let number = url
+        .split('/')
+        .skip_while(|&v| v != "product")
+        .nth(1)
+        .filter(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()));
+    match number {
+        Some(x) => x.to_string(),
+        None => {
+            let hash = Sha256::digest(url.as_bytes());
+            hash[..8].iter().map(|v| format!("{:02x}", v)).collect()
+        }
+    }
+}
+
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
 
 /// 1回分の価格データ
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnePrice {
     pub price: u64,
     pub point: u64,
@@ -173,33 +460,51 @@ pub struct OnePrice {
 }
 
 impl OnePrice {
-    /// サイトから取得したデータを元に、1回分の価格データを生成する。
-    /// 日時は現時刻を割り当てる。
-    fn from_web_data(data: WebData) -> Self {
+    /// サイトから取得したデータを元に、`datetime` の時点の1回分の価格データを生成する。
+    fn from_web_data(data: WebData, datetime: DateTime<Utc>) -> Self {
         Self {
             price: data.price,
             point: data.point,
             point_ratio: data.point_ratio,
-            status: StockStatus::default(),
-            datetime: Utc::now(),
+            status: data.status,
+            datetime,
         }
     }
+
+    /// 支払い方法を考慮したポイント還元率(%)を返す。
+    pub fn point_ratio_with(&self, profile: &PaymentProfile) -> u64 {
+        (self.point_ratio as i64 + profile.point_ratio_offset).max(0) as u64
+    }
+
+    /// 支払い方法を考慮した付与ポイント数を返す。
+    /// 還元率が変わらない場合は、サイトに表示されていたポイント数をそのまま使う。
+    pub fn point_with(&self, profile: &PaymentProfile) -> u64 {
+        if profile.point_ratio_offset == 0 {
+            return self.point;
+        }
+        self.price * self.point_ratio_with(profile) / 100
+    }
+
+    /// 支払い方法を考慮した実質価格（価格からポイント分を差し引いたもの）を返す。
+    pub fn effective_price(&self, profile: &PaymentProfile) -> u64 {
+        let value = (self.point_with(profile) as f64 * profile.point_value).round() as u64;
+        self.price.saturating_sub(value)
+    }
 }
 
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
 // ファイル操作系
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
 
-fn read_data_file() -> std::io::Result<Vec<ProductHistory>> {
+fn read_data_file() -> Result<Vec<ProductHistory>, Box<dyn std::error::Error>> {
     // 実行ファイルがある場所をカレントディレクトリに設定
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-    std::env::set_current_dir(exe_dir).unwrap();
-
-    let input = std::fs::read_to_string(DATA_FILE_NAME)?;
-    let data = serde_json::from_str(&input).unwrap();
+    set_current_dir_to_exe();
 
-    Ok(data)
+    match std::fs::read_to_string(DATA_FILE_NAME) {
+        Ok(input) => Ok(serde_json::from_str(&input)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
 }
 
 fn write_data_file(data: &Vec<ProductHistory>) -> std::io::Result<()> {
@@ -207,11 +512,122 @@ fn write_data_file(data: &Vec<ProductHistory>) -> std::io::Result<()> {
     let serialized = serde_json::to_string_pretty(&data).unwrap();
 
     // 実行ファイルがある場所をカレントディレクトリに設定
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-    std::env::set_current_dir(exe_dir).unwrap();
+    set_current_dir_to_exe();
 
-    let mut file = std::fs::File::create(DATA_FILE_NAME)?;
+    // 書き込み中に読み込まれたり中断されたりしても壊れたファイルが残らないよう、
+    // 一時ファイルへ書き込んでから置き換える。
+    let temp = format!("{}.tmp", DATA_FILE_NAME);
+    let mut file = std::fs::File::create(&temp)?;
     file.write_all(serialized.as_bytes())?;
-    Ok(())
+    file.sync_all()?;
+    std::fs::rename(&temp, DATA_FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price_with};
+
+    #[test]
+    fn effective_price_test() {
+        let p = one_price_with(4860, 10, StockStatus::Sufficient, 1);
+        let default = PaymentProfile::default();
+        assert_eq!(4374, p.effective_price(&default));
+
+        let gold = PaymentProfile {
+            name: "gold".to_string(),
+            point_ratio_offset: 1,
+            point_value: 1.0,
+        };
+        assert_eq!(11, p.point_ratio_with(&gold));
+        assert_eq!(534, p.point_with(&gold));
+        assert_eq!(4326, p.effective_price(&gold));
+
+        let other_card = PaymentProfile {
+            name: "card".to_string(),
+            point_ratio_offset: -2,
+            point_value: 1.0,
+        };
+        assert_eq!(388, p.point_with(&other_card));
+        assert_eq!(4472, p.effective_price(&other_card));
+
+        let invoice = PaymentProfile {
+            name: "invoice".to_string(),
+            point_ratio_offset: 0,
+            point_value: 0.0,
+        };
+        assert_eq!(4860, p.effective_price(&invoice));
+
+        let p = one_price_with(1000, 1, StockStatus::Sufficient, 1);
+        assert_eq!(0, p.point_ratio_with(&other_card));
+        assert_eq!(1000, p.effective_price(&other_card));
+    }
+
+    #[test]
+    fn product_id_from_url_test() {
+        assert_eq!(
+            "100000001007414874",
+            product_id_from_url("https://www.yodobashi.com/product/100000001007414874/")
+        );
+        assert_eq!(
+            "100000001007414874",
+            product_id_from_url("https://www.yodobashi.com/product/100000001007414874")
+        );
+        // 製品番号がないときは、URLごとに異なり、同じURLでは変わらないIDにする。
+        let id = product_id_from_url("https://example.com/a");
+        assert_eq!(16, id.len());
+        assert_eq!(id, product_id_from_url("https://example.com/a"));
+        assert_ne!(id, product_id_from_url("https://example.com/b"));
+    }
+
+    #[test]
+    fn update_item_test() {
+        let web_data = |price: u64, point_ratio: u64, status: StockStatus| WebData {
+            price,
+            point: price * point_ratio / 100,
+            point_ratio,
+            status,
+            ..Default::default()
+        };
+        let mut product = ProductHistory::default();
+        product.add_web_data(web_data(5000, 10, StockStatus::Sufficient), day(1));
+        product.add_web_data(web_data(4000, 10, StockStatus::Sufficient), day(2));
+
+        let mut item = UpdateItem::new(&product);
+        product.add_web_data(web_data(3800, 15, StockStatus::Limited), day(3));
+        assert_eq!(day(3), product.history.last().unwrap().datetime);
+        assert_eq!(Some(day(3)), product.last_checked);
+        item.after = product.history.last().cloned();
+        assert!(item.is_changed());
+        assert_eq!(Some(-200), item.change());
+        assert_eq!(Some(-5.0), item.change_ratio());
+        assert!(item.is_new_low());
+        assert!(!item.is_new_high());
+        assert_eq!(
+            Some((StockStatus::Sufficient, StockStatus::Limited)),
+            item.status_change()
+        );
+        assert_eq!(Some((10, 15)), item.point_ratio_change());
+
+        // ポイント還元率のみが変化したときも記録し、報告する。
+        let mut item = UpdateItem::new(&product);
+        assert!(!product.add_web_data(web_data(3800, 20, StockStatus::Limited), day(4)));
+        assert_eq!(day(4), product.history.last().unwrap().datetime);
+        item.after = product.history.last().cloned();
+        assert!(!item.is_changed());
+        assert_eq!(None, item.status_change());
+        assert_eq!(Some((15, 20)), item.point_ratio_change());
+        assert!(item.is_notable());
+
+        // 変化がないときは報告しない。
+        let mut item = UpdateItem::new(&product);
+        item.after = product.history.last().cloned();
+        assert!(!item.is_notable());
+
+        // 取得に失敗したときは報告する。
+        let mut item = UpdateItem::new(&product);
+        item.error = Some("error".to_string());
+        assert!(!item.is_changed());
+        assert!(item.is_notable());
+    }
 }
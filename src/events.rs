@@ -0,0 +1,197 @@
+//! セール（一時的な値下がり）の検出。
+//!
+//! 価格が下がってから、元の価格（値下がり直前の価格）以上に戻るまでを1回のセールとして扱う。
+//! 一定期間が過ぎても元の価格に戻らないときは、セールではなく値下げとみなす。
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::data::OnePrice;
+
+/// この日数が過ぎても元の価格に戻らないときは、値下げとみなす。
+const MAX_EVENT_DAYS: i64 = 30;
+
+/// 1回分のセール
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaleEvent {
+    /// 値下がりした日時
+    pub start: DateTime<Utc>,
+    /// 元の価格に戻った日時。セール中のときは None。
+    pub end: Option<DateTime<Utc>>,
+    /// 値下がり直前の価格
+    pub base_price: u64,
+    /// セール中の最安値
+    pub bottom_price: u64,
+    /// 最安値になった日時
+    pub bottom_at: DateTime<Utc>,
+    /// セール中にポイント還元率も変化したかどうか
+    pub point_changed: bool,
+}
+
+impl SaleEvent {
+    /// セールの期間を返す。セール中のときは現時刻までの期間を返す。
+    pub fn duration(&self, now: DateTime<Utc>) -> Duration {
+        self.end.unwrap_or(now) - self.start
+    }
+
+    /// 値下がり幅（円）を返す。
+    pub fn depth(&self) -> u64 {
+        self.base_price - self.bottom_price
+    }
+
+    /// 値下がり幅(%)を返す。
+    pub fn depth_ratio(&self) -> f64 {
+        self.depth() as f64 * 100.0 / self.base_price.max(1) as f64
+    }
+}
+
+/// 価格履歴からセールを検出し、古い順に返す。
+pub fn sale_events(history: &[OnePrice], now: DateTime<Utc>) -> Vec<SaleEvent> {
+    let max = Duration::days(MAX_EVENT_DAYS);
+    let mut array = vec![];
+    let mut open: Option<(SaleEvent, u64)> = None;
+
+    for v in history.windows(2) {
+        let (prev, cur) = (&v[0], &v[1]);
+
+        if let Some((mut event, base_ratio)) = open.take() {
+            if cur.datetime - event.start > max {
+                // 元の価格に戻らなかったため、値下げとみなす。
+            } else if cur.price >= event.base_price {
+                event.end = Some(cur.datetime);
+                event.point_changed |= cur.point_ratio != base_ratio;
+                array.push(event);
+                continue;
+            } else {
+                if cur.price < event.bottom_price {
+                    event.bottom_price = cur.price;
+                    event.bottom_at = cur.datetime;
+                }
+                event.point_changed |= cur.point_ratio != base_ratio;
+                open = Some((event, base_ratio));
+                continue;
+            }
+        }
+
+        if cur.price < prev.price {
+            let event = SaleEvent {
+                start: cur.datetime,
+                end: None,
+                base_price: prev.price,
+                bottom_price: cur.price,
+                bottom_at: cur.datetime,
+                point_changed: cur.point_ratio != prev.point_ratio,
+            };
+            open = Some((event, prev.point_ratio));
+        }
+    }
+
+    if let Some((event, _)) = open {
+        if now - event.start <= max {
+            array.push(event);
+        }
+    }
+    array
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// セールの傾向の集計
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventSummary {
+    /// セールの回数
+    pub count: usize,
+    /// 90日あたりのセールの回数
+    pub per_90_days: f64,
+    /// 終了したセールの平均期間（日）
+    pub average_days: f64,
+    /// 平均の値下がり幅(%)
+    pub average_depth_ratio: f64,
+}
+
+/// セールの傾向を集計する。
+/// 集計期間は、価格履歴の最初の日時から現時刻まで。
+pub fn summarize(events: &[SaleEvent], since: DateTime<Utc>, now: DateTime<Utc>) -> EventSummary {
+    let days = ((now - since).num_seconds() as f64 / 86400.0).max(1.0);
+    let finished: Vec<_> = events.iter().filter(|v| v.end.is_some()).collect();
+    let average_days = if finished.is_empty() {
+        0.0
+    } else {
+        finished
+            .iter()
+            .map(|v| v.duration(now).num_seconds() as f64 / 86400.0)
+            .sum::<f64>()
+            / finished.len() as f64
+    };
+    let average_depth_ratio = if events.is_empty() {
+        0.0
+    } else {
+        events.iter().map(|v| v.depth_ratio()).sum::<f64>() / events.len() as f64
+    };
+
+    EventSummary {
+        count: events.len(),
+        per_90_days: events.len() as f64 * 90.0 / days,
+        average_days,
+        average_depth_ratio,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price, one_price_with};
+    use crate::types::StockStatus;
+
+    #[test]
+    fn sale_events_test() {
+        let history = vec![
+            one_price(1000, 1),
+            one_price(900, 3),
+            one_price_with(800, 13, StockStatus::Sufficient, 4),
+            one_price(1000, 6),
+            one_price(1100, 8),
+            one_price(1050, 10),
+        ];
+        let events = sale_events(&history, day(12));
+        assert_eq!(2, events.len());
+
+        assert_eq!(day(3), events[0].start);
+        assert_eq!(Some(day(6)), events[0].end);
+        assert_eq!(200, events[0].depth());
+        assert_eq!(20.0, events[0].depth_ratio());
+        assert_eq!(day(4), events[0].bottom_at);
+        assert!(events[0].point_changed);
+        assert_eq!(Duration::days(3), events[0].duration(day(12)));
+
+        // セール中
+        assert_eq!(None, events[1].end);
+        assert_eq!(50, events[1].depth());
+        assert!(!events[1].point_changed);
+    }
+
+    #[test]
+    fn sale_events_price_cut_test() {
+        // 元の価格に戻らないときは値下げとみなす。
+        let history = vec![one_price(1000, 1), one_price(900, 2)];
+        assert_eq!(1, sale_events(&history, day(20)).len());
+        let now = day(2) + Duration::days(MAX_EVENT_DAYS + 1);
+        assert!(sale_events(&history, now).is_empty());
+    }
+
+    #[test]
+    fn summarize_test() {
+        let history = vec![
+            one_price(1000, 1),
+            one_price(900, 3),
+            one_price(1000, 5),
+            one_price(800, 7),
+            one_price(1000, 11),
+        ];
+        let events = sale_events(&history, day(31));
+        let summary = summarize(&events, day(1), day(31));
+        assert_eq!(2, summary.count);
+        assert_eq!(6.0, summary.per_90_days);
+        assert_eq!(3.0, summary.average_days);
+        assert_eq!(15.0, summary.average_depth_ratio);
+    }
+}
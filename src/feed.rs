@@ -0,0 +1,595 @@
+//! 価格の変化のフィード（Atom・RSS 2.0）。
+//!
+//! 価格履歴から、価格・ポイント・在庫状況が変化した時点を新しい順に取り出してフィードにする。
+//! エントリの ID は製品 ID と価格を取得した日時から作るため、何度生成しても同じになる。
+//! 設定したファイルへ `update` のたびに書き出すほか、`feeds serve` で HTTP で配信する。
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::PaymentProfile;
+use crate::data::{AppData, OnePrice, ProductHistory};
+use crate::util::{commafy, escape_html, set_current_dir_to_exe, signed_yen, yen};
+
+/// フィードの形式の名称の一覧
+pub const FEED_FORMATS: &[&str] = &["atom", "rss"];
+
+/// フィードのリンク先
+const HOME_URL: &str = "https://www.yodobashi.com/";
+
+/// 配信時に、要求を読み終えるまで待つ最長の時間
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 配信時に、1件の要求から読み込む最大のバイト数
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+/// 配信時に、同時に応答する最大の接続数
+const MAX_CONNECTIONS: usize = 8;
+
+/// フィードの形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedFormat {
+    #[default]
+    Atom,
+    /// RSS 2.0
+    Rss,
+}
+
+impl FeedFormat {
+    pub fn from_string(string: &str) -> Option<FeedFormat> {
+        match string {
+            "atom" => Some(FeedFormat::Atom),
+            "rss" => Some(FeedFormat::Rss),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "atom",
+            FeedFormat::Rss => "rss",
+        }
+    }
+
+    /// HTTP で配信するときの Content-Type を返す。
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+}
+
+/// `update` のたびに書き出すフィード
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Feed {
+    /// 名称
+    pub name: String,
+    /// 書き出すファイルのパス（相対パスのときは実行ファイルのある場所から）
+    pub path: String,
+    #[serde(default)]
+    pub format: FeedFormat,
+    /// 対象にするタグ。None のときは全製品
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// エントリの最大の件数
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+pub fn default_limit() -> usize {
+    50
+}
+
+impl Feed {
+    pub fn new(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            path: path.to_string(),
+            format: FeedFormat::default(),
+            tag: None,
+            limit: default_limit(),
+        }
+    }
+
+    /// パス以外の設定の説明を返す。
+    pub fn describe_options(&self) -> String {
+        let mut parts = vec![format!("形式: {}", self.format.key())];
+        if let Some(tag) = &self.tag {
+            parts.push(format!("タグ: {}", tag));
+        }
+        parts.push(format!("件数: {}", self.limit));
+        parts.join("、")
+    }
+
+    /// フィードを生成してファイルへ書き出す。
+    pub fn write(
+        &self,
+        histories: &[ProductHistory],
+        profile: &PaymentProfile,
+        now: DateTime<Utc>,
+    ) -> std::io::Result<()> {
+        let entries = entries(histories, self.tag.as_deref(), self.limit);
+        let body = render(self.format, self.tag.as_deref(), &entries, profile, now);
+        set_current_dir_to_exe();
+        // 書き込み中に読み込まれたり中断されたりしても壊れたファイルが残らないよう、
+        // 一時ファイルへ書き込んでから置き換える。
+        let temp = format!("{}.tmp", self.path);
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(body.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temp, &self.path)
+    }
+}
+
+/// 全てのフィードを書き出す。失敗したもののメッセージを返す。
+pub fn write_all(
+    feeds: &[Feed],
+    histories: &[ProductHistory],
+    profile: &PaymentProfile,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    feeds
+        .iter()
+        .filter_map(|feed| {
+            feed.write(histories, profile, now).err().map(|e| {
+                format!(
+                    "フィード「{}」を {} へ書き出せませんでした（{}）",
+                    feed.name, feed.path, e
+                )
+            })
+        })
+        .collect()
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// フィードの1件のエントリ（1つの製品の1回の変化）
+#[derive(Debug, Clone, Copy)]
+pub struct FeedEntry<'a> {
+    pub product: &'a ProductHistory,
+    pub before: &'a OnePrice,
+    pub after: &'a OnePrice,
+}
+
+impl FeedEntry<'_> {
+    /// エントリの ID。製品 ID と、変化後の価格を取得した日時（UNIX 時間）から作る。
+    pub fn id(&self) -> String {
+        format!(
+            "urn:yodoprice:{}:{}",
+            self.product.id,
+            self.after.datetime.timestamp()
+        )
+    }
+
+    /// 製品名と、変化した値の要約
+    pub fn title(&self) -> String {
+        let (before, after) = (self.before, self.after);
+        let mut parts = vec![];
+        if before.price != after.price {
+            parts.push(format!("{} → {}", yen(before.price), yen(after.price)));
+        }
+        // ポイントは価格か還元率から決まるため、どちらも変わらずにポイントのみが変化したときに表示する。
+        if before.point_ratio != after.point_ratio {
+            parts.push(format!(
+                "還元率 {}% → {}%",
+                before.point_ratio, after.point_ratio
+            ));
+        } else if before.price == after.price && before.point != after.point {
+            parts.push(format!(
+                "ポイント {} → {}",
+                commafy(before.point),
+                commafy(after.point)
+            ));
+        }
+        if before.status != after.status {
+            parts.push(format!("{} → {}", before.status, after.status));
+        }
+        format!("{}：{}", self.product.display_name(), parts.join("、"))
+    }
+
+    /// 変化前と変化後の値の一覧（HTML）
+    pub fn content(&self, profile: &PaymentProfile) -> String {
+        let (before, after) = (self.before, self.after);
+        let (old_effective, effective) = (
+            before.effective_price(profile),
+            after.effective_price(profile),
+        );
+        let items = [
+            format!(
+                "価格: {} → {} ({})",
+                yen(before.price),
+                yen(after.price),
+                signed_yen(after.price as i64 - before.price as i64)
+            ),
+            format!(
+                "実質価格: {} → {} ({})",
+                yen(old_effective),
+                yen(effective),
+                signed_yen(effective as i64 - old_effective as i64)
+            ),
+            format!(
+                "ポイント: {} → {} ({}% → {}%)",
+                commafy(before.point),
+                commafy(after.point),
+                before.point_ratio,
+                after.point_ratio
+            ),
+            format!("在庫: {} → {}", before.status, after.status),
+        ];
+        let mut html = "<ul>".to_string();
+        for item in items {
+            html += &format!("<li>{}</li>", escape_html(&item));
+        }
+        html += &format!(
+            "</ul><p><a href=\"{}\">{}</a></p>",
+            escape_html(&self.product.url),
+            escape_html(self.product.display_name())
+        );
+        html
+    }
+}
+
+/// 価格履歴から、変化の新しい順に最大 `limit` 件のエントリを返す。
+/// タグを指定したときは、そのタグが付いた製品のみを対象にする。アーカイブした製品は含めない。
+pub fn entries<'a>(
+    histories: &'a [ProductHistory],
+    tag: Option<&str>,
+    limit: usize,
+) -> Vec<FeedEntry<'a>> {
+    let mut entries: Vec<_> = histories
+        .iter()
+        .filter(|v| !v.archived && tag.is_none_or(|t| v.has_tag(t)))
+        .flat_map(|product| {
+            product.history.windows(2).map(move |w| FeedEntry {
+                product,
+                before: &w[0],
+                after: &w[1],
+            })
+        })
+        .collect();
+    entries.sort_by_key(|v| std::cmp::Reverse(v.after.datetime));
+    entries.truncate(limit);
+    entries
+}
+
+/// フィードの文書（XML）を返す。エントリがないときの更新日時は `now` にする。
+pub fn render(
+    format: FeedFormat,
+    tag: Option<&str>,
+    entries: &[FeedEntry],
+    profile: &PaymentProfile,
+    now: DateTime<Utc>,
+) -> String {
+    let title = match tag {
+        Some(tag) => format!("yodoprice: 価格の変化（{}）", tag),
+        None => "yodoprice: 価格の変化".to_string(),
+    };
+    let updated = entries.first().map_or(now, |v| v.after.datetime);
+    match format {
+        FeedFormat::Atom => render_atom(&title, tag, entries, profile, updated),
+        FeedFormat::Rss => render_rss(&title, entries, profile, updated),
+    }
+}
+
+fn render_atom(
+    title: &str,
+    tag: Option<&str>,
+    entries: &[FeedEntry],
+    profile: &PaymentProfile,
+    updated: DateTime<Utc>,
+) -> String {
+    let mut id = reqwest::Url::parse("urn:yodoprice:feed").unwrap();
+    if let Some(tag) = tag {
+        id.query_pairs_mut().append_pair("tag", tag);
+    }
+    let mut xml = String::new();
+    xml += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+    xml += "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n";
+    xml += &format!("  <title>{}</title>\n", escape_html(title));
+    xml += &format!("  <id>{}</id>\n", escape_html(id.as_str()));
+    xml += &format!("  <updated>{}</updated>\n", rfc3339(updated));
+    xml += &format!("  <link href=\"{}\"/>\n", HOME_URL);
+    xml += "  <author><name>yodoprice</name></author>\n";
+    xml += "  <generator>yodoprice</generator>\n";
+    for entry in entries {
+        xml += "  <entry>\n";
+        xml += &format!("    <title>{}</title>\n", escape_html(&entry.title()));
+        xml += &format!("    <id>{}</id>\n", entry.id());
+        xml += &format!("    <link href=\"{}\"/>\n", escape_html(&entry.product.url));
+        xml += &format!("    <updated>{}</updated>\n", rfc3339(entry.after.datetime));
+        xml += &format!(
+            "    <content type=\"html\">{}</content>\n",
+            escape_html(&entry.content(profile))
+        );
+        xml += "  </entry>\n";
+    }
+    xml += "</feed>\n";
+    xml
+}
+
+fn render_rss(
+    title: &str,
+    entries: &[FeedEntry],
+    profile: &PaymentProfile,
+    updated: DateTime<Utc>,
+) -> String {
+    let mut xml = String::new();
+    xml += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+    xml += "<rss version=\"2.0\">\n<channel>\n";
+    xml += &format!("  <title>{}</title>\n", escape_html(title));
+    xml += &format!("  <link>{}</link>\n", HOME_URL);
+    xml += "  <description>登録した製品の価格・ポイント・在庫状況の変化</description>\n";
+    xml += &format!(
+        "  <lastBuildDate>{}</lastBuildDate>\n",
+        updated.to_rfc2822()
+    );
+    xml += "  <generator>yodoprice</generator>\n";
+    for entry in entries {
+        xml += "  <item>\n";
+        xml += &format!("    <title>{}</title>\n", escape_html(&entry.title()));
+        xml += &format!("    <link>{}</link>\n", escape_html(&entry.product.url));
+        xml += &format!("    <guid isPermaLink=\"false\">{}</guid>\n", entry.id());
+        xml += &format!(
+            "    <pubDate>{}</pubDate>\n",
+            entry.after.datetime.to_rfc2822()
+        );
+        xml += &format!(
+            "    <description>{}</description>\n",
+            escape_html(&entry.content(profile))
+        );
+        xml += "  </item>\n";
+    }
+    xml += "</channel>\n</rss>\n";
+    xml
+}
+
+fn rfc3339(datetime: DateTime<Utc>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// `listen`（`127.0.0.1:8080` 等）で HTTP の要求を待ち受け、フィードを配信する。
+/// `/atom.xml`（または `/`）で Atom を、`/rss.xml` で RSS 2.0 を返し、`?tag=` でタグを指定できる。
+/// 別に実行した `update` の結果も反映するよう、要求のたびにデータファイルを読み込む。
+/// 要求を送ってこない接続で他の要求が待たされないよう、接続ごとに別のスレッドで応答する。
+/// 同時に応答している接続が `MAX_CONNECTIONS` 件あるときは、新しい接続をすぐに閉じる。
+pub fn serve(listen: &str, limit: usize, profile: &PaymentProfile) -> std::io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    println!(
+        "http://{}/atom.xml で配信しています（/rss.xml で RSS、?tag= でタグを指定）。",
+        listener.local_addr()?
+    );
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("要求の処理中にエラーが発生しました({})", e);
+                continue;
+            }
+        };
+        // 増やすのはこのスレッドだけなので、確かめてから増やしても上限を超えない。
+        if active.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+            eprintln!("接続が多すぎるため、新しい接続を閉じました。");
+            continue;
+        }
+        active.fetch_add(1, Ordering::SeqCst);
+        let active = Arc::clone(&active);
+        let profile = profile.clone();
+        std::thread::spawn(move || {
+            let result = handle(stream, |format, tag| {
+                let data = AppData::from_file().map_err(|e| e.to_string())?;
+                let entries = entries(&data.histories, tag, limit);
+                Ok(render(format, tag, &entries, &profile, Utc::now()))
+            });
+            if let Err(e) = result {
+                eprintln!("要求の処理中にエラーが発生しました({})", e);
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// 1件の要求に応答する。
+/// フィードを生成できないとき（データファイルを読み込めないとき等）は 503 を返す。
+fn handle<F>(stream: TcpStream, render: F) -> std::io::Result<()>
+where
+    F: Fn(FeedFormat, Option<&str>) -> Result<String, String>,
+{
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    // 改行を送らない接続でメモリを使い尽くさないよう、読み込む量を制限する。
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST_BYTES));
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // ヘッダは使わないが、読み終えてから応答する。
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut words = request.split_whitespace();
+    let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+    let (status, content_type, body) = match route(target) {
+        _ if method != "GET" && method != "HEAD" => {
+            ("405 Method Not Allowed", "text/plain", String::new())
+        }
+        Some((format, tag)) => match render(format, tag.as_deref()) {
+            Ok(body) => ("200 OK", format.content_type(), body),
+            Err(e) => {
+                eprintln!("フィードを生成できませんでした({})", e);
+                ("503 Service Unavailable", "text/plain", String::new())
+            }
+        },
+        None => ("404 Not Found", "text/plain", String::new()),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+/// 要求のパスから、フィードの形式とタグを返す。該当しないときは None を返す。
+fn route(target: &str) -> Option<(FeedFormat, Option<String>)> {
+    let url = reqwest::Url::parse("http://localhost/")
+        .ok()?
+        .join(target)
+        .ok()?;
+    let format = match url.path() {
+        "/" | "/atom.xml" => FeedFormat::Atom,
+        "/rss.xml" => FeedFormat::Rss,
+        _ => return None,
+    };
+    let tag = url
+        .query_pairs()
+        .find(|(k, _)| k == "tag")
+        .map(|(_, v)| v.into_owned());
+    Some((format, tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::fixtures::one_price_with;
+    use crate::types::StockStatus;
+
+    fn histories() -> Vec<ProductHistory> {
+        vec![
+            ProductHistory {
+                id: "1001".to_string(),
+                name: "A & B".to_string(),
+                url: "https://www.yodobashi.com/product/1001/".to_string(),
+                tags: vec!["audio".to_string()],
+                history: vec![
+                    one_price_with(30000, 10, StockStatus::Sufficient, 1),
+                    one_price_with(27800, 10, StockStatus::Limited, 3),
+                    one_price_with(27800, 13, StockStatus::Limited, 5),
+                ],
+                ..Default::default()
+            },
+            ProductHistory {
+                id: "1002".to_string(),
+                name: "ヘルメット".to_string(),
+                url: "https://www.yodobashi.com/product/1002/".to_string(),
+                history: vec![
+                    one_price_with(5000, 10, StockStatus::BackOrder, 2),
+                    one_price_with(5000, 10, StockStatus::Sufficient, 4),
+                ],
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn entries_test() {
+        let histories = histories();
+        let titles = |tag, limit| {
+            entries(&histories, tag, limit)
+                .iter()
+                .map(|v| (v.id(), v.title()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                (
+                    "urn:yodoprice:1001:1704412800".to_string(),
+                    "A & B：還元率 10% → 13%".to_string()
+                ),
+                (
+                    "urn:yodoprice:1002:1704326400".to_string(),
+                    "ヘルメット：お取り寄せ → 在庫あり".to_string()
+                ),
+                (
+                    "urn:yodoprice:1001:1704240000".to_string(),
+                    "A & B：￥30,000 → ￥27,800、在庫あり → 在庫僅少".to_string()
+                ),
+            ],
+            titles(None, 50)
+        );
+        assert_eq!(2, titles(Some("audio"), 50).len());
+        assert_eq!(1, titles(None, 1).len());
+    }
+
+    #[test]
+    fn render_test() {
+        let histories = histories();
+        let profile = PaymentProfile::default();
+        let now = Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap();
+        let entries = entries(&histories, Some("gift idea"), 50);
+        let atom = render(FeedFormat::Atom, Some("gift idea"), &entries, &profile, now);
+        assert!(atom.contains("<id>urn:yodoprice:feed?tag=gift+idea</id>"));
+        assert!(atom.contains("<updated>2024-02-01T00:00:00Z</updated>"));
+        assert!(!atom.contains("<entry>"));
+
+        let entries = super::entries(&histories, None, 50);
+        let atom = render(FeedFormat::Atom, None, &entries, &profile, now);
+        assert!(atom.contains("<updated>2024-01-05T00:00:00Z</updated>"));
+        assert!(atom.contains("<title>A &amp; B：還元率 10% → 13%</title>"));
+        assert!(atom.contains("<link href=\"https://www.yodobashi.com/product/1001/\"/>"));
+        assert!(atom.contains(
+            "&lt;li&gt;価格: ￥30,000 → ￥27,800 (-￥2,200)&lt;/li&gt;&lt;li&gt;実質価格: ￥27,000 → ￥25,020 (-￥1,980)&lt;/li&gt;"
+        ));
+        assert_eq!(3, atom.matches("<entry>").count());
+
+        let rss = render(FeedFormat::Rss, None, &entries, &profile, now);
+        assert!(rss.contains("<lastBuildDate>Fri, 5 Jan 2024 00:00:00 +0000</lastBuildDate>"));
+        assert!(rss.contains("<guid isPermaLink=\"false\">urn:yodoprice:1002:1704326400</guid>"));
+        assert_eq!(3, rss.matches("<item>").count());
+    }
+
+    #[test]
+    fn route_test() {
+        assert_eq!(Some((FeedFormat::Atom, None)), route("/"));
+        assert_eq!(
+            Some((FeedFormat::Rss, Some("gift idea".to_string()))),
+            route("/rss.xml?tag=gift%20idea")
+        );
+        assert_eq!(
+            Some((FeedFormat::Atom, Some("音楽".to_string()))),
+            route("/atom.xml?tag=%E9%9F%B3%E6%A5%BD")
+        );
+        assert_eq!(None, route("/favicon.ico"));
+    }
+
+    #[test]
+    fn handle_test() {
+        // 1件の要求を送り、応答を返す。
+        let request = |result: Result<String, String>| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            client
+                .write_all(b"GET /atom.xml HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+            handle(stream, |_, _| result.clone()).unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            response
+        };
+        let ok = request(Ok("<feed/>".to_string()));
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(ok.ends_with("\r\n\r\n<feed/>"));
+        // データファイルを読み込めないときは、落ちずに 503 を返す。
+        let error = request(Err("broken".to_string()));
+        assert!(error.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    }
+}
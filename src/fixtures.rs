@@ -0,0 +1,46 @@
+//! テストで共通に使う日時と価格データ。
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::data::{OnePrice, UpdateItem};
+use crate::types::StockStatus;
+
+/// 2024年1月 `d` 日 0時 (UTC) を返す。
+pub fn day(d: u32) -> DateTime<Utc> {
+    day_hour(d, 0)
+}
+
+/// 2024年1月 `d` 日 `h` 時 (UTC) を返す。
+pub fn day_hour(d: u32, h: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, d, h, 0, 0).unwrap()
+}
+
+/// `d` 日に取得した、在庫あり・ポイント還元率10%の価格データを返す。
+pub fn one_price(price: u64, d: u32) -> OnePrice {
+    one_price_with(price, 10, StockStatus::Sufficient, d)
+}
+
+/// ポイント還元率と在庫状況を指定して、`d` 日に取得した価格データを返す。
+pub fn one_price_with(price: u64, point_ratio: u64, status: StockStatus, d: u32) -> OnePrice {
+    OnePrice {
+        price,
+        point: price * point_ratio / 100,
+        point_ratio,
+        status,
+        datetime: day(d),
+    }
+}
+
+/// 製品 `id` の更新の結果を返す。名称は `id` と同じにし、更新前の価格を最高値・最安値とする。
+pub fn update_item(id: &str, before: OnePrice, after: Option<OnePrice>) -> UpdateItem {
+    UpdateItem {
+        id: id.to_string(),
+        name: id.to_string(),
+        url: format!("https://example.com/{}", id),
+        old_high: Some(before.price),
+        old_low: Some(before.price),
+        before: Some(before),
+        after,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,280 @@
+//! 外部コマンドによるフック。
+//!
+//! 更新で生じたイベントごとに、登録したコマンドを実行する。
+//! イベントは JSON にして標準入力へ渡し、各項目を `YODOPRICE_` で始まる環境変数（`YODOPRICE_PRICE` 等）にも設定する。
+//! 標準出力は捨て、標準エラー出力は失敗したときのメッセージに使う。
+//! 制限時間を過ぎても終わらないときは強制終了する。失敗は呼び出し元へメッセージとして返し、更新は止めない。
+
+use std::io::{Read, Write};
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::notify::{Event, EventKind};
+
+/// 実行するコマンド
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+    /// 名称
+    pub name: String,
+    /// 実行ファイル
+    pub command: String,
+    /// 引数
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// 実行するイベントの種類。空のときは全ての種類で実行する。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventKind>,
+    /// 制限時間（秒）
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+impl Hook {
+    pub fn new(name: &str, command: &str, args: &[String]) -> Self {
+        Self {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.to_vec(),
+            events: vec![],
+            timeout: default_timeout(),
+        }
+    }
+
+    /// 指定した種類のイベントで実行するかどうかを返す。
+    pub fn accepts(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+
+    /// 実行するコマンドライン（表示用）を返す。
+    pub fn command_line(&self) -> String {
+        let mut words = vec![self.command.as_str()];
+        words.extend(self.args.iter().map(|v| v.as_str()));
+        words.join(" ")
+    }
+
+    /// コマンド以外の設定の説明を返す。
+    pub fn describe_options(&self) -> String {
+        let mut parts = vec![];
+        if !self.events.is_empty() {
+            let events = self.events.iter().map(|v| v.key()).collect::<Vec<_>>();
+            parts.push(format!("イベント: {}", events.join(",")));
+        }
+        parts.push(format!("制限時間: {}秒", self.timeout));
+        parts.join("、")
+    }
+
+    /// イベントを渡してコマンドを実行し、終了を待つ。
+    pub fn run(&self, event: &Event) -> Result<(), HookError> {
+        self.run_with_timeout(event, Duration::from_secs(self.timeout))
+    }
+
+    fn run_with_timeout(&self, event: &Event, timeout: Duration) -> Result<(), HookError> {
+        let json = serde_json::to_string(event).unwrap();
+        let mut child = std::process::Command::new(&self.command)
+            .args(&self.args)
+            .envs(env_vars(event))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| HookError::Spawn(e.to_string()))?;
+
+        // 標準入力を読まないコマンドや、大量に出力するコマンドで止まらないよう、入出力は別のスレッドで行う。
+        let mut stdin = child.stdin.take().unwrap();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(json.as_bytes());
+        });
+        let mut stderr = child.stderr.take().unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            let _ = tx.send(output);
+        });
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(HookError::Timeout(timeout));
+                }
+                Err(e) => return Err(HookError::Spawn(e.to_string())),
+            }
+        };
+        if status.success() {
+            return Ok(());
+        }
+        // 子プロセスが起動したプロセスが標準エラー出力を開いたままのこともあるため、
+        // 残りの制限時間だけ待ち、読み終わらなければ出力は使わない。
+        let output = rx
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .unwrap_or_default();
+        Err(HookError::Failed {
+            status: status.to_string(),
+            stderr: output.trim().lines().last().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// イベントの各項目を、環境変数の名前と値の組にする。値がない項目は含めない。
+fn env_vars(event: &Event) -> Vec<(String, String)> {
+    let value = serde_json::to_value(event).unwrap();
+    let Some(map) = value.as_object() else {
+        return vec![];
+    };
+    map.iter()
+        .filter_map(|(k, v)| {
+            let value = match v {
+                serde_json::Value::Null => return None,
+                serde_json::Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            Some((format!("YODOPRICE_{}", k.to_uppercase()), value))
+        })
+        .collect()
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// フックの実行時のエラー
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookError {
+    /// 起動できなかった
+    Spawn(String),
+    /// 制限時間を過ぎた
+    Timeout(Duration),
+    /// 0 以外の終了コードで終了した
+    Failed { status: String, stderr: String },
+}
+
+impl std::error::Error for HookError {}
+
+impl std::fmt::Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HookError::Spawn(message) => write!(f, "起動できませんでした: {}", message),
+            HookError::Timeout(timeout) => {
+                write!(
+                    f,
+                    "{}秒以内に終了しなかったため中断しました",
+                    timeout.as_secs_f64()
+                )
+            }
+            HookError::Failed { status, stderr } if stderr.is_empty() => {
+                write!(f, "異常終了しました ({})", status)
+            }
+            HookError::Failed { status, stderr } => {
+                write!(f, "異常終了しました ({}): {}", status, stderr)
+            }
+        }
+    }
+}
+
+/// 全てのフックを、それぞれが受け付けるイベントごとに実行する。失敗したもののメッセージを返す。
+pub fn run_all(hooks: &[Hook], events: &[Event]) -> Vec<String> {
+    let mut errors = vec![];
+    for hook in hooks.iter() {
+        for event in events.iter().filter(|v| hook.accepts(v.event)) {
+            if let Err(e) = hook.run(event) {
+                errors.push(format!(
+                    "フック「{}」が失敗しました（{}、{}: {}）",
+                    hook.name,
+                    event.event.key(),
+                    event.name,
+                    e
+                ));
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Hook {
+        Hook::new("test", "sh", &["-c".to_string(), script.to_string()])
+    }
+
+    #[test]
+    fn run_test() {
+        let path = std::env::temp_dir().join(format!("yodoprice-hook-{}", std::process::id()));
+        let hook = sh(&format!(
+            r#"cat > "{0}"; echo >> "{0}"; echo "$YODOPRICE_EVENT $YODOPRICE_PRICE $YODOPRICE_RULE [$YODOPRICE_ERROR]" >> "{0}""#,
+            path.display()
+        ));
+        let event = Event::sample(EventKind::Alert);
+        assert_eq!(Ok(()), hook.run(&event));
+
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let (json, env) = output.split_at(output.find('\n').unwrap_or(output.len()));
+        assert_eq!(serde_json::to_string(&event).unwrap(), json);
+        assert_eq!("alert 27800 price < 28000 []", env.trim());
+    }
+
+    #[test]
+    fn run_failure_test() {
+        let event = Event::sample(EventKind::Error);
+        assert_eq!(
+            Err(HookError::Failed {
+                status: "exit status: 3".to_string(),
+                stderr: "boom".to_string(),
+            }),
+            sh("echo first >&2; echo boom >&2; exit 3").run(&event)
+        );
+        assert!(matches!(
+            Hook::new("test", "/nonexistent/yodoprice-hook", &[]).run(&event),
+            Err(HookError::Spawn(_))
+        ));
+
+        // 制限時間を過ぎたときは強制終了する。標準入力を読まないコマンドでも止まらない。
+        let started = Instant::now();
+        let timeout = Duration::from_millis(200);
+        assert_eq!(
+            Err(HookError::Timeout(timeout)),
+            sh("sleep 5").run_with_timeout(&event, timeout)
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // 終了後も標準エラー出力が開いたままのときは、出力を待たずに失敗を返す。
+        let started = Instant::now();
+        assert_eq!(
+            Err(HookError::Failed {
+                status: "exit status: 1".to_string(),
+                stderr: String::new(),
+            }),
+            sh("sleep 5 & exit 1").run_with_timeout(&event, timeout)
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn run_all_test() {
+        let mut hook = sh("exit 1");
+        hook.events = vec![EventKind::Error];
+        let events = [
+            Event::sample(EventKind::Change),
+            Event::sample(EventKind::Error),
+        ];
+        let errors = run_all(&[hook], &events);
+        assert_eq!(
+            vec!["フック「test」が失敗しました（error、テスト用の製品: 異常終了しました (exit status: 1)）"],
+            errors
+        );
+    }
+}
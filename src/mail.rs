@@ -0,0 +1,636 @@
+//! メールによる通知。
+//!
+//! `update` でアラートの条件を満たしたときは、その回の分を1通にまとめてすぐに送る。
+//! まとめ（ダイジェスト）を有効にしたときは、更新の結果を `digest.json` に溜めておき、
+//! 前回のまとめから1日以上経った更新の後に、価格の変化・最安値の更新・在庫の復活・取得の失敗をまとめて送る。
+//! どちらもテキストと HTML の両方の本文を付ける。
+
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::serde::{ts_seconds, ts_seconds_option};
+use chrono::{DateTime, Utc};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+
+use crate::data::UpdateResult;
+use crate::notify::{Event, EventKind};
+use crate::types::StockStatus;
+use crate::util::{escape_html, format_jst, set_current_dir_to_exe, signed_yen, yen};
+
+pub const DIGEST_FILE_NAME: &str = "digest.json";
+
+/// `mail` コマンドの操作
+pub const MAIL_ACTIONS: &[&str] = &["status", "test", "digest"];
+
+/// メールの送信の設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailConfig {
+    /// SMTP サーバのホスト名
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// 接続の暗号化
+    #[serde(default)]
+    pub security: MailSecurity,
+    /// 認証のユーザ名。None のときは認証しない。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// 送信元のアドレス
+    pub from: String,
+    /// 送信先のアドレス
+    pub to: Vec<String>,
+    /// アラートの条件を満たしたときにすぐに送るかどうか
+    #[serde(default = "default_alerts")]
+    pub alerts: bool,
+    /// 1日1回のまとめを送るかどうか
+    #[serde(default)]
+    pub digest: bool,
+}
+
+fn default_port() -> u16 {
+    587
+}
+
+fn default_alerts() -> bool {
+    true
+}
+
+/// SMTP の接続の暗号化
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MailSecurity {
+    /// 暗号化しない（ローカルのサーバ向け）
+    None,
+    /// 平文で接続してから STARTTLS で暗号化する
+    #[default]
+    Starttls,
+    /// 最初から TLS で接続する
+    Tls,
+}
+
+impl std::fmt::Display for MailSecurity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            MailSecurity::None => "none",
+            MailSecurity::Starttls => "starttls",
+            MailSecurity::Tls => "tls",
+        };
+        f.write_str(s)
+    }
+}
+
+/// テキストと HTML の本文を持つメールを送る。
+pub fn send_mail(
+    config: &MailConfig,
+    subject: &str,
+    text: String,
+    html: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = lettre::Message::builder()
+        .from(config.from.parse::<Mailbox>()?)
+        .subject(subject);
+    for to in config.to.iter() {
+        builder = builder.to(to.parse::<Mailbox>()?);
+    }
+    let message = builder.multipart(MultiPart::alternative_plain_html(text, html))?;
+
+    let mut transport = match config.security {
+        MailSecurity::None => SmtpTransport::builder_dangerous(&config.host),
+        MailSecurity::Starttls => SmtpTransport::starttls_relay(&config.host)?,
+        MailSecurity::Tls => SmtpTransport::relay(&config.host)?,
+    }
+    .port(config.port)
+    .timeout(Some(Duration::from_secs(10)));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.build().send(&message)?;
+    Ok(())
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// アラートのメールの件名と、テキスト・HTML の本文を返す。
+pub fn render_alerts(events: &[Event]) -> (String, String, String) {
+    let subject = match events {
+        [event] => format!("[yodoprice] アラート: {}", event.name),
+        _ => format!("[yodoprice] アラート {}件", events.len()),
+    };
+
+    let mut text = vec![];
+    let mut html = vec![
+        format!("<h2>アラート {}件</h2>", events.len()),
+        "<ul>".to_string(),
+    ];
+    for event in events {
+        let rule = event.rule.as_deref().unwrap_or_default();
+        let mut parts = vec![];
+        match (event.old_price, event.price) {
+            (Some(old), Some(new)) if old != new => {
+                parts.push(format!("{} → {}", yen(old), yen(new)))
+            }
+            (_, Some(price)) => parts.push(yen(price)),
+            _ => {}
+        }
+        if let Some(effective) = event.effective_price {
+            parts.push(format!("実質 {}", yen(effective)));
+        }
+        if let Some(status) = event.status {
+            parts.push(status.to_string());
+        }
+        let detail = parts.join("  ");
+
+        text.push(String::new());
+        text.push(event.name.clone());
+        text.push(format!("  {}", rule));
+        text.push(format!("  {}", detail));
+        text.push(format!("  {}", event.url));
+        html.push(format!(
+            "<li>{}: <strong>{}</strong><br>{}</li>",
+            link(&event.name, &event.url),
+            escape_html(rule),
+            escape_html(&detail)
+        ));
+    }
+    html.push("</ul>".to_string());
+
+    let mut lines = vec![format!("アラート {}件", events.len())];
+    lines.extend(text);
+    (subject, lines.join("\n") + "\n", html.join("\n") + "\n")
+}
+
+/// 名称を URL へのリンクにした HTML を返す。
+fn link(name: &str, url: &str) -> String {
+    format!("<a href=\"{}\">{}</a>", escape_html(url), escape_html(name))
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// まとめのために溜めておく、1回の更新での1つの製品の結果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DigestEntry {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub old_price: Option<u64>,
+    pub price: Option<u64>,
+    pub old_status: Option<StockStatus>,
+    pub status: Option<StockStatus>,
+    /// 最安値を更新した
+    pub new_low: bool,
+    /// 取得に失敗したときのメッセージ
+    pub error: Option<String>,
+    #[serde(with = "ts_seconds")]
+    pub datetime: DateTime<Utc>,
+}
+
+/// 前回のまとめ以降の更新の結果
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Digest {
+    /// 溜め始めた日時（前回のまとめを送った日時）
+    #[serde(default, with = "ts_seconds_option")]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub entries: Vec<DigestEntry>,
+}
+
+impl Digest {
+    /// ファイルから読み込む。ファイルが存在しないときは空にする。
+    pub fn from_file() -> Result<Self, Box<dyn std::error::Error>> {
+        set_current_dir_to_exe();
+        Self::read(Path::new(DIGEST_FILE_NAME))
+    }
+
+    fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(input) => Ok(serde_json::from_str(&input)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// ファイルへ出力する。
+    pub fn write_file(&self) -> std::io::Result<()> {
+        set_current_dir_to_exe();
+        self.write(Path::new(DIGEST_FILE_NAME))
+    }
+
+    /// 書き込みが中断されても壊れたファイルが残らないよう、一時ファイルへ書き込んでから置き換える。
+    fn write(&self, path: &Path) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self).unwrap();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(serialized.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    }
+
+    /// 更新の結果のうち、変化があったものと取得に失敗したものを溜める。
+    pub fn record(&mut self, result: &UpdateResult, now: DateTime<Utc>) {
+        self.since.get_or_insert(now);
+        for item in result.items.iter().filter(|v| v.is_notable()) {
+            self.entries.push(DigestEntry {
+                id: item.id.clone(),
+                name: item.name.clone(),
+                url: item.url.clone(),
+                old_price: item.old_price(),
+                price: item.price(),
+                old_status: item.before.as_ref().map(|v| v.status),
+                status: item.after.as_ref().map(|v| v.status),
+                new_low: item.is_new_low(),
+                error: item.error.clone(),
+                datetime: item.after.as_ref().map_or(now, |v| v.datetime),
+            });
+        }
+    }
+
+    /// 前回のまとめから1日以上経ったかどうかを返す。
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.since
+            .is_some_and(|v| now - v >= chrono::Duration::days(1))
+    }
+
+    /// まとめを送った後に、溜めた結果を消す。
+    pub fn reset(&mut self, now: DateTime<Utc>) {
+        self.since = Some(now);
+        self.entries.clear();
+    }
+
+    /// まとめのメールの件名と、テキスト・HTML の本文を返す。
+    pub fn render(&self, now: DateTime<Utc>) -> (String, String, String) {
+        let period = format!(
+            "{}〜{}",
+            self.since
+                .map_or(String::new(), |v| format_jst(&v, "%Y/%m/%d %H:%M")),
+            format_jst(&now, "%Y/%m/%d %H:%M")
+        );
+        let subject = format!("[yodoprice] 価格のまとめ（{}）", period);
+
+        let sections = self.sections();
+        let mut text = vec![format!("価格のまとめ（{}）", period)];
+        let mut html = vec![format!("<h2>価格のまとめ（{}）</h2>", escape_html(&period))];
+        if sections.is_empty() {
+            text.push(String::new());
+            text.push("この期間に変化はありませんでした。".to_string());
+            html.push("<p>この期間に変化はありませんでした。</p>".to_string());
+        }
+        for (title, lines) in sections {
+            let title = format!("{}（{}件）", title, lines.len());
+            text.push(String::new());
+            text.push(format!("■ {}", title));
+            html.push(format!("<h3>{}</h3>", escape_html(&title)));
+            html.push("<ul>".to_string());
+            for (entry, detail) in lines {
+                text.push(format!("・{}: {}", entry.name, detail));
+                text.push(format!("  {}", entry.url));
+                html.push(format!(
+                    "<li>{}: {}</li>",
+                    link(&entry.name, &entry.url),
+                    escape_html(&detail)
+                ));
+            }
+            html.push("</ul>".to_string());
+        }
+        (subject, text.join("\n") + "\n", html.join("\n") + "\n")
+    }
+
+    /// 溜めた結果を製品ごとに集計し、項目の名称と、製品ごとの説明の一覧を返す。項目が空のときは含めない。
+    fn sections(&self) -> Vec<(&'static str, Vec<(&DigestEntry, String)>)> {
+        let mut changes = vec![];
+        let mut new_lows = vec![];
+        let mut restocks = vec![];
+        let mut failures = vec![];
+
+        let mut ids: Vec<&str> = vec![];
+        for entry in self.entries.iter() {
+            if !ids.contains(&entry.id.as_str()) {
+                ids.push(&entry.id);
+            }
+        }
+        for id in ids {
+            let entries: Vec<_> = self.entries.iter().filter(|v| v.id == id).collect();
+            let last = *entries.last().unwrap();
+
+            let changed: Vec<_> = entries
+                .iter()
+                .filter(|v| v.price.is_some() && v.old_price.is_some() && v.price != v.old_price)
+                .collect();
+            if let (Some(first), Some(end)) = (changed.first(), changed.last()) {
+                let (old, new) = (first.old_price.unwrap(), end.price.unwrap());
+                let mut detail = format!("{} → {}", yen(old), yen(new));
+                if old != new {
+                    detail += &format!(" ({})", signed_yen(new as i64 - old as i64));
+                }
+                if changed.len() > 1 {
+                    detail += &format!("（{}回変化）", changed.len());
+                }
+                changes.push((last, detail));
+            }
+
+            if let Some(low) = entries
+                .iter()
+                .filter(|v| v.new_low)
+                .filter_map(|v| v.price)
+                .min()
+            {
+                new_lows.push((last, yen(low)));
+            }
+
+            let restocked = entries.iter().rev().find(|v| {
+                matches!((v.old_status, v.status), (Some(before), Some(after))
+                    if before == StockStatus::BackOrder && after.is_in_stock())
+            });
+            if let Some(entry) = restocked {
+                let detail = format!("{} → {}", entry.old_status.unwrap(), entry.status.unwrap());
+                restocks.push((last, detail));
+            }
+
+            let errors: Vec<_> = entries.iter().filter_map(|v| v.error.as_ref()).collect();
+            if let Some(error) = errors.last() {
+                failures.push((last, format!("{}回（{}）", errors.len(), error)));
+            }
+        }
+
+        [
+            ("価格の変化", changes),
+            ("最安値を更新", new_lows),
+            ("在庫が復活", restocks),
+            ("取得に失敗", failures),
+        ]
+        .into_iter()
+        .filter(|(_, v)| !v.is_empty())
+        .collect()
+    }
+}
+
+/// 設定に従って、アラートのメールと、期限が来ていればまとめのメールを送る。送信に失敗したもののメッセージを返す。
+/// まとめを送れなかったときは、溜めた結果を残して次の更新で再び送る。
+pub fn notify_all(
+    config: &MailConfig,
+    events: &[Event],
+    result: &UpdateResult,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let mut errors = vec![];
+    let alerts: Vec<_> = events
+        .iter()
+        .filter(|v| v.event == EventKind::Alert)
+        .cloned()
+        .collect();
+    if config.alerts && !alerts.is_empty() {
+        let (subject, text, html) = render_alerts(&alerts);
+        if let Err(e) = send_mail(config, &subject, text, html) {
+            errors.push(format!("アラートのメールを送れませんでした（{}）", e));
+        }
+    }
+
+    if config.digest {
+        let mut digest = match Digest::from_file() {
+            Ok(v) => v,
+            Err(e) => {
+                errors.push(format!(
+                    "{} を読み込めませんでした({})",
+                    DIGEST_FILE_NAME, e
+                ));
+                return errors;
+            }
+        };
+        digest.record(result, now);
+        if digest.is_due(now) {
+            let (subject, text, html) = digest.render(now);
+            match send_mail(config, &subject, text, html) {
+                Ok(_) => digest.reset(now),
+                Err(e) => errors.push(format!("まとめのメールを送れませんでした（{}）", e)),
+            }
+        }
+        if let Err(e) = digest.write_file() {
+            errors.push(format!(
+                "{} の保存時にエラーが発生しました({})",
+                DIGEST_FILE_NAME, e
+            ));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use super::*;
+    use crate::data::UpdateItem;
+    use crate::fixtures::{day_hour, one_price_with, update_item};
+
+    /// ローカルで SMTP サーバの代わりを起動し、ポート番号と、受け取ったコマンドとメールの内容を返すスレッドを返す。
+    fn sink() -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = vec![];
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let command = line.trim_end().to_string();
+                let reply: &[u8] = if command.starts_with("DATA") {
+                    stream.write_all(b"354 go ahead\r\n").unwrap();
+                    let mut data = String::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        data += &line;
+                    }
+                    received.push(data);
+                    b"250 OK\r\n"
+                } else if command.starts_with("QUIT") {
+                    stream.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    received.push(command);
+                    b"250 OK\r\n"
+                };
+                stream.write_all(reply).unwrap();
+            }
+            received
+        });
+        (port, handle)
+    }
+
+    fn config(port: u16) -> MailConfig {
+        MailConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: MailSecurity::None,
+            username: None,
+            password: None,
+            from: "yodoprice <bot@example.com>".to_string(),
+            to: vec!["me@example.com".to_string()],
+            alerts: true,
+            digest: false,
+        }
+    }
+
+    fn item(id: &str, before: (u64, StockStatus), after: Option<(u64, StockStatus)>) -> UpdateItem {
+        let one_price = |(price, status)| one_price_with(price, 10, status, 1);
+        UpdateItem {
+            name: format!("製品{}", id),
+            ..update_item(id, one_price(before), after.map(one_price))
+        }
+    }
+
+    #[test]
+    fn send_mail_test() {
+        let (port, server) = sink();
+        let (subject, text, html) = render_alerts(&[Event::sample(EventKind::Alert)]);
+        assert_eq!("[yodoprice] アラート: テスト用の製品", subject);
+        send_mail(&config(port), &subject, text, html).unwrap();
+
+        let received = server.join().unwrap();
+        assert!(received.contains(&"MAIL FROM:<bot@example.com>".to_string()));
+        assert!(received.contains(&"RCPT TO:<me@example.com>".to_string()));
+        let data = received.last().unwrap();
+        assert!(data.contains("Content-Type: multipart/alternative"));
+        assert!(data.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(data.contains("Content-Type: text/html; charset=utf-8"));
+    }
+
+    #[test]
+    fn render_alerts_test() {
+        let mut other = Event::sample(EventKind::Alert);
+        other.name = "A & B".to_string();
+        other.old_price = Some(27800);
+        let (subject, text, html) = render_alerts(&[Event::sample(EventKind::Alert), other]);
+        assert_eq!("[yodoprice] アラート 2件", subject);
+        assert_eq!(
+            [
+                "アラート 2件",
+                "",
+                "テスト用の製品",
+                "  price < 28000",
+                "  ￥30,000 → ￥27,800  実質 ￥25,020  在庫僅少",
+                "  https://www.yodobashi.com/",
+                "",
+                "A & B",
+                "  price < 28000",
+                "  ￥27,800  実質 ￥25,020  在庫僅少",
+                "  https://www.yodobashi.com/",
+                "",
+            ]
+            .join("\n"),
+            text
+        );
+        assert!(html.contains(
+            "<li><a href=\"https://www.yodobashi.com/\">A &amp; B</a>: <strong>price &lt; 28000</strong><br>"
+        ));
+    }
+
+    #[test]
+    fn digest_test() {
+        use StockStatus::*;
+        let mut digest = Digest::default();
+        let first = UpdateResult {
+            items: vec![
+                item("1", (1000, Sufficient), Some((900, Sufficient))),
+                item("2", (500, BackOrder), Some((500, Sufficient))),
+                item("3", (700, Sufficient), Some((700, Sufficient))),
+            ],
+        };
+        digest.record(&first, day_hour(1, 0));
+        let second = UpdateResult {
+            items: vec![
+                item("1", (900, Sufficient), Some((950, Sufficient))),
+                UpdateItem {
+                    error: Some("タイムアウト".to_string()),
+                    ..item("3", (700, Sufficient), None)
+                },
+            ],
+        };
+        digest.record(&second, day_hour(1, 12));
+        assert_eq!(Some(day_hour(1, 0)), digest.since);
+        assert_eq!(4, digest.entries.len());
+        assert!(!digest.is_due(day_hour(1, 23)));
+        assert!(digest.is_due(day_hour(2, 0)));
+
+        let (subject, text, html) = digest.render(day_hour(2, 0));
+        assert_eq!(
+            "[yodoprice] 価格のまとめ（2024/01/01 09:00〜2024/01/02 09:00）",
+            subject
+        );
+        assert_eq!(
+            [
+                "価格のまとめ（2024/01/01 09:00〜2024/01/02 09:00）",
+                "",
+                "■ 価格の変化（1件）",
+                "・製品1: ￥1,000 → ￥950 (-￥50)（2回変化）",
+                "  https://example.com/1",
+                "",
+                "■ 最安値を更新（1件）",
+                "・製品1: ￥900",
+                "  https://example.com/1",
+                "",
+                "■ 在庫が復活（1件）",
+                "・製品2: お取り寄せ → 在庫あり",
+                "  https://example.com/2",
+                "",
+                "■ 取得に失敗（1件）",
+                "・製品3: 1回（タイムアウト）",
+                "  https://example.com/3",
+                "",
+            ]
+            .join("\n"),
+            text
+        );
+        assert!(html.contains("<h3>在庫が復活（1件）</h3>"));
+        assert!(html.contains(
+            "<li><a href=\"https://example.com/2\">製品2</a>: お取り寄せ → 在庫あり</li>"
+        ));
+
+        digest.reset(day_hour(2, 0));
+        assert!(digest.entries.is_empty());
+        assert!(!digest.is_due(day_hour(2, 1)));
+        assert!(digest
+            .render(day_hour(3, 0))
+            .1
+            .contains("この期間に変化はありませんでした。"));
+    }
+
+    #[test]
+    fn digest_file_test() {
+        let path =
+            std::env::temp_dir().join(format!("yodoprice-digest-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(Digest::read(&path).unwrap().entries.is_empty());
+
+        let mut digest = Digest::default();
+        digest.record(
+            &UpdateResult {
+                items: vec![item("1", (1000, StockStatus::Sufficient), None)],
+            },
+            day_hour(1, 0),
+        );
+        digest.write(&path).unwrap();
+        assert_eq!(digest.entries, Digest::read(&path).unwrap().entries);
+
+        // 壊れたファイルは空として扱わず、そのまま残す
+        std::fs::write(&path, "{").unwrap();
+        assert!(Digest::read(&path).is_err());
+        assert_eq!("{", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
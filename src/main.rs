@@ -1,13 +1,56 @@
 use std::io::Write;
 
+use config::{Config, CONFIG};
 use data::{AppData, APP_STATE};
+use rustyline::error::ReadlineError;
 
-use crate::util::{commafy, omitted_string};
+use crate::alert::{Alert, AlertContext, AlertRule};
+use crate::backtest::backtest;
+use crate::chart::{price_sparkline, render_chart, terminal_width};
+use crate::chat::WebhookFormat;
+use crate::command::{find_command, parse, tokenize, Command};
+use crate::events::{sale_events, summarize};
+use crate::feed::{Feed, FeedFormat};
+use crate::hook::Hook;
+use crate::notify::{Event, EventKind, Notifiers, EVENT_KINDS};
+use crate::output::{render, HistoryRow, ListRow, OutputFormat, ProductDetail, UpdateRow};
+use crate::query::{parse_query, sort_rows, ListQuery, SortKey};
+use crate::report::{render_report, use_color};
+use crate::rule::{parse_cooldown, CompiledRule, QuietHours, Rule, RuleState};
+use crate::stats::{price_stats, Window};
+use crate::stock::{stock_summary, timeline};
+use crate::table::{Align, Column, Table};
+use crate::util::{commafy, format_jst, omitted_string, signed_yen, yen};
+use crate::webhook::Webhook;
 
 mod access;
+mod alert;
+mod backtest;
+mod chart;
+mod chat;
+mod command;
+mod config;
 mod data;
+mod events;
+mod feed;
+#[cfg(test)]
+mod fixtures;
+mod hook;
+mod mail;
+mod notify;
+mod output;
+mod query;
+mod repl;
+mod report;
+mod rule;
+mod score;
+mod stats;
+mod stock;
+mod table;
+mod tui;
 mod types;
 mod util;
+mod webhook;
 
 fn main() {
     // access::get_data("https://www.rust-lang.org");
@@ -18,91 +61,1902 @@ fn main() {
     // Mutexのロックを解除するために別のブロックにしている。
     {
         let mut app_state = APP_STATE.lock().unwrap();
-        *app_state = AppData::from_file();
+        match AppData::from_file() {
+            Ok(x) => *app_state = x,
+            Err(e) => {
+                // 読み込めないまま保存して、価格履歴を失わないように終了する。
+                eprintln!("データファイル data.json を読み込めませんでした({})", e);
+                std::process::exit(EXIT_ERROR);
+            }
+        }
+        let mut config = CONFIG.lock().unwrap();
+        match Config::from_file() {
+            Ok(x) => *config = x,
+            Err(e) => {
+                // 手で編集した設定の誤りは、位置（行・列）とともに知らせて終了する。
+                eprintln!("設定ファイル config.json を読み込めませんでした({})", e);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    // 引数が指定されたときは、そのコマンドのみを実行して終了する。
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        let code = match parse(&args) {
+            Ok(command) => run(command, false),
+            Err(e) => {
+                eprintln!("{}", e);
+                EXIT_USAGE
+            }
+        };
+        std::process::exit(code);
     }
 
     main_loop();
 }
 
+/// 終了コード：正常終了（価格の変化なし）
+const EXIT_OK: i32 = 0;
+/// 終了コード：エラーが発生した（一部の製品の取得に失敗した場合を含む）
+const EXIT_ERROR: i32 = 1;
+/// 終了コード：コマンドや引数の誤り
+const EXIT_USAGE: i32 = 2;
+/// 終了コード：正常終了（価格が変化した製品がある）
+const EXIT_CHANGED: i32 = 3;
+
 /// アプリのメインループ。
 pub fn main_loop() {
+    let mut editor = match repl::editor() {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("端末の初期化に失敗しました({})", e);
+            return;
+        }
+    };
     loop {
-        print!("-> ");
+        let buf = match editor.readline("-> ") {
+            Ok(x) => x,
+            // Ctrl-C のときは入力中の行を破棄する。
+            Err(ReadlineError::Interrupted) => continue,
+            // 入力の終端(EOF)
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("入力に失敗しました({})", e);
+                break;
+            }
+        };
+        if !buf.trim().is_empty() {
+            repl::add_history(&mut editor, &buf);
+        }
+
+        let tokens = match tokenize(&buf) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        if tokens.is_empty() {
+            continue;
+        }
+        match parse(&tokens) {
+            Ok(Command::Quit) => break,
+            Ok(command) => {
+                run(command, true);
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// 解析済みのコマンドを実行し、終了コードを返す。
+/// 対話モードでないとき (`interactive` が false のとき) は、確認等の入力を求めない。
+pub fn run(command: Command, interactive: bool) -> i32 {
+    match command {
+        Command::Help { command } => print_help(command.as_deref()),
+        Command::Quit => {}
+        Command::Add { url } => {
+            let ok = command_add(&url);
+            save_file();
+            if !ok {
+                return EXIT_ERROR;
+            }
+        }
+        Command::Update { output } => return update_all(output),
+        Command::Remove { product, yes } => {
+            let code = command_remove(&product, yes, interactive);
+            save_file();
+            return code;
+        }
+        Command::Archive { product } => {
+            let code = command_archive(&product, true);
+            save_file();
+            return code;
+        }
+        Command::Unarchive { product } => {
+            let code = command_archive(&product, false);
+            save_file();
+            return code;
+        }
+        Command::Rename { product, name } => {
+            let code = command_rename(&product, name);
+            save_file();
+            return code;
+        }
+        Command::Note { product, text } => {
+            let code = command_note(&product, text);
+            save_file();
+            return code;
+        }
+        Command::Tag { product, tags } => {
+            let code = command_tag(&product, &tags, true);
+            save_file();
+            return code;
+        }
+        Command::Untag { product, tags } => {
+            let code = command_tag(&product, &tags, false);
+            save_file();
+            return code;
+        }
+        Command::Alert {
+            product,
+            rule,
+            remove,
+            reset,
+        } => {
+            let code = command_alert(product.as_deref(), &rule, remove, reset);
+            save_file();
+            return code;
+        }
+        Command::Rules {
+            action,
+            name,
+            expr,
+            tag,
+            product,
+            cooldown,
+            quiet,
+        } => {
+            let options = RuleOptions {
+                tag,
+                product,
+                cooldown,
+                quiet,
+            };
+            let code = command_rules(
+                action.as_deref(),
+                name.as_deref(),
+                expr.as_deref(),
+                &options,
+            );
+            save_file();
+            return code;
+        }
+        Command::Backtest { rule, tag, product } => {
+            return command_backtest(&rule, tag.as_deref(), product.as_deref())
+        }
+        Command::Hooks {
+            action,
+            name,
+            command,
+            events,
+            timeout,
+        } => {
+            return command_hooks(
+                action.as_deref(),
+                name.as_deref(),
+                &command,
+                events.as_deref(),
+                timeout,
+            )
+        }
+        Command::Mail { action } => return command_mail(action.as_deref()),
+        Command::Webhooks {
+            action,
+            name,
+            url,
+            format,
+            secret,
+            template,
+            events,
+            retries,
+        } => {
+            let options = WebhookOptions {
+                format,
+                secret,
+                template,
+                events,
+                retries,
+            };
+            return command_webhooks(action.as_deref(), name.as_deref(), url.as_deref(), &options);
+        }
+        Command::Feeds {
+            action,
+            name,
+            path,
+            format,
+            tag,
+            limit,
+            listen,
+        } => {
+            let options = FeedOptions {
+                format,
+                tag,
+                limit,
+                listen,
+            };
+            return command_feeds(
+                action.as_deref(),
+                name.as_deref(),
+                path.as_deref(),
+                &options,
+            );
+        }
+        Command::List {
+            compare,
+            explain,
+            query,
+            view,
+            save,
+            archived,
+            output,
+        } => {
+            let options = ListOptions {
+                compare,
+                explain,
+                archived,
+                output,
+            };
+            return print_list(&query, view.as_deref(), save.as_deref(), &options);
+        }
+        Command::Views { delete } => return command_views(delete.as_deref()),
+        Command::Show { product, output } => return command_show(&product, output),
+        Command::Chart {
+            product,
+            height,
+            width,
+        } => return command_chart(&product, height, width),
+        Command::Stats { product, window } => return command_stats(&product, window),
+        Command::Events { product } => return command_events(product.as_deref()),
+        Command::Stock { product } => return command_stock(product.as_deref()),
+        Command::Profile { name } => return command_profile(name.as_deref()),
+        Command::Tui => {
+            let result = tui::run_dashboard();
+            save_file();
+            if let Err(e) = result {
+                eprintln!("画面の表示中にエラーが発生しました({})", e);
+                return EXIT_ERROR;
+            }
+        }
+    }
+    EXIT_OK
+}
+
+/// ヘルプを表示する。コマンドが指定されたときは、そのコマンドの使い方を表示する。
+pub fn print_help(command: Option<&str>) {
+    let Some(name) = command else {
+        command::print_help();
+        return;
+    };
+    match find_command(name) {
+        Some(spec) => command::print_command_help(spec),
+        None => println!("{}", parse(&[name]).unwrap_err()),
+    }
+}
+
+/// URLから製品を追加する。正常に追加できたときは true を返す。
+pub fn command_add(url: &str) -> bool {
+    let mut app_state = APP_STATE.lock().unwrap();
+    let result = (*app_state).add_from_url(url);
+    match result {
+        Ok(_) => {
+            println!("登録が正常に完了しました");
+            true
+        }
+        Err(e) => {
+            eprintln!("登録時にエラーが発生しました({})", e);
+            false
+        }
+    }
+}
+
+/// 製品を削除し、終了コードを返す。
+/// 対話モードで `yes` が指定されていないときは、削除してよいか確認する。
+/// 対話モードでないときは確認できないため、`yes` の指定を必須とする。
+pub fn command_remove(key: &str, yes: bool, interactive: bool) -> i32 {
+    let mut app_state = APP_STATE.lock().unwrap();
+    let Some(index) = app_state.product_index(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+
+    if !yes {
+        if !interactive {
+            eprintln!("削除するときは `--yes` を指定してください。");
+            return EXIT_USAGE;
+        }
+        print!(
+            "{} を削除します。価格の履歴も削除されます。よろしいですか？ [y/N] ",
+            app_state.histories[index].name
+        );
         std::io::stdout().flush().unwrap();
         let mut buf = String::new();
         std::io::stdin()
             .read_line(&mut buf)
             .expect("stdinからの入力に失敗しました");
-        let input = buf.trim();
-
-        if input.starts_with("quit") || input == "q" || input.starts_with("q ") {
-            // 終了
-            break;
-        } else if input.starts_with("help") || input == "h" {
-            // ヘルプを表示
-            print_help();
-        } else if input.starts_with("add ") || input.starts_with("a ") {
-            // 追加
-            command_add(input);
-            save_file();
-        } else if input.starts_with("update") || input == "u" {
-            // 更新
-            update_all();
-            save_file();
-        } else if input.starts_with("list") || input == "l" {
-            // 一覧表示
-            print_list();
-        } else if input == "add" || input == "a" {
-            println!("追加するためには、URLも一緒に入力してください。");
+        if !matches!(buf.trim(), "y" | "Y" | "yes") {
+            println!("削除を中止しました。");
+            return EXIT_OK;
         }
     }
+
+    let product = app_state.remove_product(index);
+    println!("{} を削除しました。", product.name);
+    EXIT_OK
 }
 
-/// ヘルプを表示する。
-pub fn print_help() {
-    println!("yodopriceは、ヨドバシ.comの価格の履歴を取得・表示するアプリです。");
-    println!(" help / h  ヘルプを表示します。(この画面)");
-    println!(" add <url> / a   指定されたURLの製品を追加します。");
-    println!(" update / u   登録されている製品の価格を取得・更新します。");
+/// 製品をアーカイブする（`archived` が false のときは元に戻す）。
+pub fn command_archive(key: &str, archived: bool) -> i32 {
+    let mut app_state = APP_STATE.lock().unwrap();
+    let Some(index) = app_state.product_index(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    let product = &mut app_state.histories[index];
+    product.archived = archived;
+    if archived {
+        println!("{} をアーカイブしました。", product.name);
+    } else {
+        println!("{} のアーカイブを解除しました。", product.name);
+    }
+    EXIT_OK
 }
 
-pub fn command_add(input: &str) {
-    let buf: Vec<_> = input.split_whitespace().collect();
-    if buf.len() <= 1 {
-        println!("URLを指定してください。");
-        return;
+/// 製品に表示用の名称を設定する（`name` が None か空のときは設定を解除する）。
+pub fn command_rename(key: &str, name: Option<String>) -> i32 {
+    let mut app_state = APP_STATE.lock().unwrap();
+    let Some(index) = app_state.product_index(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    let product = &mut app_state.histories[index];
+    // 空の名前は、TUIと同じく表示名の解除として扱う。
+    let name = name.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    match &name {
+        Some(x) => println!("{} の表示名を `{}` に設定しました。", product.name, x),
+        None => println!("{} の表示名の設定を解除しました。", product.name),
     }
-    let url = buf[1];
+    product.custom_name = name;
+    EXIT_OK
+}
+
+/// 製品にメモを設定する（`text` が None のときはメモを削除する）。
+pub fn command_note(key: &str, text: Option<String>) -> i32 {
     let mut app_state = APP_STATE.lock().unwrap();
-    let result = (*app_state).add_from_url(url);
-    match result {
-        Ok(_) => println!("登録が正常に完了しました"),
-        Err(e) => println!("登録時にエラーが発生しました({})", e),
+    let Some(index) = app_state.product_index(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    let product = &mut app_state.histories[index];
+    match &text {
+        Some(_) => println!("{} にメモを設定しました。", product.display_name()),
+        None => println!("{} のメモを削除しました。", product.display_name()),
     }
+    product.note = text;
+    EXIT_OK
 }
 
-pub fn update_all() {
+/// 製品にタグを追加する（`add` が false のときはタグを削除する）。
+pub fn command_tag(key: &str, tags: &[String], add: bool) -> i32 {
     let mut app_state = APP_STATE.lock().unwrap();
-    let _result = (*app_state).update_all();
-    // TODO: 更新結果のメッセージを表示する。最安値が更新された、エラーが発生した、等。
+    let Some(index) = app_state.product_index(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    let product = &mut app_state.histories[index];
+    for tag in tags {
+        if add && !product.has_tag(tag) {
+            product.tags.push(tag.clone());
+        } else if !add {
+            product.tags.retain(|v| v != tag);
+        }
+    }
+    println!(
+        "{} のタグ: {}",
+        product.display_name(),
+        product.tags.join(", ")
+    );
+    EXIT_OK
+}
+
+/// 製品のアラートを表示・追加・削除する。
+/// 製品を省略したときは、アラートを設定した全製品のアラートを表示する。
+pub fn command_alert(
+    key: Option<&str>,
+    rule: &[String],
+    remove: Option<usize>,
+    reset: bool,
+) -> i32 {
+    let profile = CONFIG.lock().unwrap().active_profile();
+    let mut app_state = APP_STATE.lock().unwrap();
+    let Some(key) = key else {
+        let products: Vec<_> = app_state
+            .histories
+            .iter()
+            .filter(|v| !v.alerts.is_empty())
+            .collect();
+        if products.is_empty() {
+            println!("アラートは設定されていません。");
+        }
+        for product in products {
+            println!("{}", product.display_name());
+            print_alerts(&product.alerts);
+        }
+        return EXIT_OK;
+    };
+    let Some(index) = app_state.product_index(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    let product = &mut app_state.histories[index];
+
+    if let Some(n) = remove {
+        if n == 0 || n > product.alerts.len() {
+            eprintln!("アラート {} は設定されていません。", n);
+            return EXIT_USAGE;
+        }
+        let alert = product.alerts.remove(n - 1);
+        println!(
+            "{} のアラート「{}」を削除しました。",
+            product.display_name(),
+            alert.rule
+        );
+    }
+    if reset {
+        for alert in product.alerts.iter_mut() {
+            alert.triggered = false;
+        }
+        println!(
+            "{} のアラートの通知状態を戻しました。",
+            product.display_name()
+        );
+    }
+    if !rule.is_empty() {
+        let rule = match AlertRule::parse(rule) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        };
+        let alert = Alert::new(rule, product, &profile);
+        if alert.triggered {
+            println!("現在すでに条件を満たしているため、条件を満たさなくなった後に知らせます。");
+        }
+        println!(
+            "{} にアラート「{}」を追加しました。",
+            product.display_name(),
+            alert.rule
+        );
+        product.alerts.push(alert);
+    }
+
+    if remove.is_none() && !reset && rule.is_empty() {
+        println!("{}", product.display_name());
+        if product.alerts.is_empty() {
+            println!("  アラートは設定されていません。");
+        }
+        print_alerts(&product.alerts);
+    }
+    EXIT_OK
+}
+
+/// アラートの一覧を、番号と通知状態を付けて表示する。
+fn print_alerts(alerts: &[Alert]) {
+    for (i, alert) in (1..).zip(alerts.iter()) {
+        let state = match (alert.triggered, alert.triggered_at) {
+            (false, _) => "".to_string(),
+            (true, Some(at)) => format!("  [通知済み {}]", format_jst(&at, "%Y/%m/%d %H:%M")),
+            (true, None) => "  [条件を満たしています]".to_string(),
+        };
+        println!("  {}. {}{}", i, alert.rule, state);
+    }
+}
+
+/// ルールの適用範囲と通知の設定
+pub struct RuleOptions {
+    pub tag: Option<String>,
+    pub product: Option<String>,
+    /// クールダウンの期間 (12h, 3d 等)
+    pub cooldown: Option<String>,
+    /// 知らせない時間帯 (22-7 等)
+    pub quiet: Option<String>,
+}
+
+/// ルールを表示・追加・削除する。`test` のときは、現在条件を満たす製品を表示する。
+pub fn command_rules(
+    action: Option<&str>,
+    name: Option<&str>,
+    expr: Option<&str>,
+    options: &RuleOptions,
+) -> i32 {
+    match action.unwrap_or("list") {
+        "add" => rules_add(name, expr, options),
+        "remove" => rules_remove(name),
+        "test" => rules_test(name, expr),
+        _ => {
+            let config = CONFIG.lock().unwrap();
+            if config.rules.is_empty() {
+                println!("ルールはありません。`rules add <name> <expr>` で追加します。");
+            }
+            for r in config.rules.iter() {
+                println!("{}: {}", r.name, r.expr);
+                let options = r.describe_options();
+                if !options.is_empty() {
+                    println!("  {}", options);
+                }
+            }
+            EXIT_OK
+        }
+    }
+}
+
+/// ルールを追加する。同じ名称のルールがあるときは置き換える。
+fn rules_add(name: Option<&str>, expr: Option<&str>, options: &RuleOptions) -> i32 {
+    let (Some(name), Some(expr)) = (name, expr) else {
+        eprintln!(
+            "ルールの名称と条件式を指定してください（例: rules add cheap 'price < 30000'）。"
+        );
+        return EXIT_USAGE;
+    };
+    let cooldown_hours = match options.cooldown.as_deref().map(parse_cooldown) {
+        Some(None) => {
+            eprintln!("クールダウンの期間は 12h や 3d のように、1年以内で指定してください。");
+            return EXIT_USAGE;
+        }
+        x => x.flatten(),
+    };
+    let quiet_hours = match options.quiet.as_deref().map(QuietHours::from_string) {
+        Some(None) => {
+            eprintln!("知らせない時間帯は 22-7 のように、0〜23時の範囲で指定してください。");
+            return EXIT_USAGE;
+        }
+        x => x.flatten(),
+    };
+
+    let mut config = CONFIG.lock().unwrap();
+    let profile = config.active_profile();
+    let mut app_state = APP_STATE.lock().unwrap();
+    let mut products = vec![];
+    if let Some(key) = &options.product {
+        let Some(product) = app_state.find_product(key) else {
+            eprintln!("製品 `{}` は登録されていません。", key);
+            return EXIT_ERROR;
+        };
+        products.push(product.id.clone());
+    }
+    let rule = Rule {
+        name: name.to_string(),
+        expr: expr.to_string(),
+        tags: options.tag.iter().cloned().collect(),
+        products,
+        cooldown_hours,
+        quiet_hours,
+    };
+    let compiled = match CompiledRule::new(rule.clone()) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    // 現在すでに条件を満たしている製品は、通知済みとして扱う。
+    let now = chrono::Utc::now();
+    let mut matched = 0;
+    for product in app_state.histories.iter_mut() {
+        product.rule_states.retain(|v| v.rule != name);
+        if !rule.applies_to(product) {
+            continue;
+        }
+        let holds = compiled.holds(product, &profile, now);
+        product.rule_state_mut(name).triggered = holds;
+        matched += holds as usize;
+    }
+
+    match config.rules.iter_mut().find(|v| v.name == name) {
+        Some(x) => *x = rule,
+        None => config.rules.push(rule),
+    }
+    if let Err(e) = config.write_file() {
+        eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+        return EXIT_ERROR;
+    }
+    println!("ルール「{}」を保存しました。", name);
+    if matched > 0 {
+        println!(
+            "現在{}件の製品が条件を満たしています。これらは条件を満たさなくなった後に知らせます。",
+            matched
+        );
+    }
+    EXIT_OK
+}
+
+/// ルールを削除する。
+fn rules_remove(name: Option<&str>) -> i32 {
+    let Some(name) = name else {
+        eprintln!("削除するルールの名称を指定してください。");
+        return EXIT_USAGE;
+    };
+    let mut config = CONFIG.lock().unwrap();
+    let Some(index) = config.rules.iter().position(|v| v.name == name) else {
+        eprintln!("ルール `{}` はありません。", name);
+        return EXIT_ERROR;
+    };
+    config.rules.remove(index);
+    for product in APP_STATE.lock().unwrap().histories.iter_mut() {
+        product.rule_states.retain(|v| v.rule != name);
+    }
+    match config.write_file() {
+        Ok(_) => {
+            println!("ルール「{}」を削除しました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// ルールごとに、現在条件を満たす製品を表示する。
+/// 名称を省略したときは全てのルールを、ルールの名称でないときは条件式として評価する。
+fn rules_test(name: Option<&str>, expr: Option<&str>) -> i32 {
+    let config = CONFIG.lock().unwrap();
+    let profile = config.active_profile();
+    let saved = name.and_then(|n| config.rules.iter().find(|v| v.name == n));
+    let (rules, ad_hoc) = match (name, saved, expr) {
+        (None, _, _) => (config.rules.clone(), false),
+        (Some(_), Some(rule), None) => (vec![rule.clone()], false),
+        (Some(name), _, expr) => {
+            let expr = [Some(name), expr].into_iter().flatten().collect::<Vec<_>>();
+            (vec![Rule::ad_hoc(&expr.join(" "))], true)
+        }
+    };
+    if rules.is_empty() {
+        println!("ルールはありません。`rules add <name> <expr>` で追加します。");
+        return EXIT_OK;
+    }
+
+    let app_state = APP_STATE.lock().unwrap();
+    let now = chrono::Utc::now();
+    for rule in rules {
+        let compiled = match CompiledRule::new(rule) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        };
+        let rule = &compiled.rule;
+        if !ad_hoc {
+            println!("{}: {}", rule.name, rule.expr);
+        }
+
+        let mut columns = vec![
+            Column::new("#", Align::Right),
+            Column::new("名称", Align::Left).shrink(16),
+            Column::new("現在", Align::Right),
+            Column::new("実質", Align::Right),
+        ];
+        if !ad_hoc {
+            columns.push(Column::new("状態", Align::Left).optional(1));
+        }
+        let mut table = Table::new(columns);
+        let mut count = 0;
+        for (i, product) in (1..).zip(app_state.histories.iter()) {
+            if product.archived || !rule.applies_to(product) {
+                continue;
+            }
+            let Some(last) = product.history.last() else {
+                continue;
+            };
+            if !compiled.holds(product, &profile, now) {
+                continue;
+            }
+            let mut row = vec![
+                i.to_string(),
+                product.display_name().to_string(),
+                yen(last.price),
+                yen(last.effective_price(&profile)),
+            ];
+            if !ad_hoc {
+                let state = product
+                    .rule_states
+                    .iter()
+                    .find(|v| v.rule == rule.name)
+                    .cloned()
+                    .unwrap_or_else(|| RuleState::new(&rule.name));
+                row.push(state.outcome(rule, true, now).label().to_string());
+            }
+            table.push(row);
+            count += 1;
+        }
+        if count == 0 {
+            println!("条件を満たす製品はありません。");
+        } else {
+            for line in table.render(terminal_width()) {
+                println!("{}", line);
+            }
+            println!("{}件が条件を満たしています。", count);
+        }
+    }
+    EXIT_OK
+}
+
+/// 記録済みの価格履歴でルールを試し、知らせることになった時点とその後の価格を表示する。
+/// `rule` が保存済みのルールの名称のときはその適用範囲に従い、そうでなければ条件式として評価する。
+pub fn command_backtest(rule: &str, tag: Option<&str>, product: Option<&str>) -> i32 {
+    let config = CONFIG.lock().unwrap();
+    let profile = config.active_profile();
+    let rule = match config.rules.iter().find(|v| v.name == rule) {
+        Some(x) => x.clone(),
+        None => Rule::ad_hoc(rule),
+    };
+    let compiled = match CompiledRule::new(rule) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let app_state = APP_STATE.lock().unwrap();
+    let only = match product {
+        Some(key) => match app_state.find_product(key) {
+            Some(x) => Some(x.id.clone()),
+            None => {
+                eprintln!("製品 `{}` は登録されていません。", key);
+                return EXIT_ERROR;
+            }
+        },
+        None => None,
+    };
+
+    let end = chrono::Utc::now();
+    let mut products = 0;
+    let mut days = 0.0;
+    let mut firings = vec![];
+    for (i, product) in (1..).zip(app_state.histories.iter()) {
+        if !compiled.rule.applies_to(product)
+            || tag.is_some_and(|t| !product.has_tag(t))
+            || only.as_ref().is_some_and(|v| *v != product.id)
+        {
+            continue;
+        }
+        let Some(first) = product.history.first() else {
+            continue;
+        };
+        products += 1;
+        days += (end - first.datetime).num_seconds() as f64 / 86400.0;
+
+        let result = backtest(&product.history, &compiled, &profile, end);
+        if result.is_empty() {
+            continue;
+        }
+        println!("#{} {}（{}回）", i, product.display_name(), result.len());
+        let mut table = Table::new(vec![
+            Column::new("日時", Align::Left),
+            Column::new("価格", Align::Right),
+            Column::new("7日後", Align::Right).optional(2),
+            Column::new("30日後", Align::Right),
+            Column::new("30日間の最安値", Align::Right).optional(1),
+        ]);
+        let after = |v: Option<u64>| v.map_or("-".to_string(), yen);
+        for f in result.iter() {
+            table.push(vec![
+                format_jst(&f.datetime, "%Y/%m/%d %H:%M"),
+                yen(f.price),
+                after(f.after_7d),
+                after(f.after_30d),
+                yen(f.low),
+            ]);
+        }
+        for line in table.render(terminal_width()) {
+            println!("{}", line);
+        }
+        println!();
+        firings.extend(result);
+    }
+
+    if products == 0 {
+        println!("対象の製品はありません。");
+        return EXIT_OK;
+    }
+    let fired = firings.len();
+    let rate = if days > 0.0 {
+        fired as f64 * 30.0 / days
+    } else {
+        0.0
+    };
+    println!(
+        "{}製品中、計{}回知らせることになりました（1製品あたり30日に{:.2}回）。",
+        products, fired, rate
+    );
+
+    // 30日後の価格が分かるものについて、その後の価格の動きを集計する。
+    let ratios = firings
+        .iter()
+        .filter_map(|v| v.change_ratio_30d())
+        .collect::<Vec<_>>();
+    if !ratios.is_empty() {
+        let down = ratios.iter().filter(|v| **v < 0.0).count();
+        let up = ratios.iter().filter(|v| **v > 0.0).count();
+        let average = ratios.iter().sum::<f64>() / ratios.len() as f64;
+        println!(
+            "30日後: 値下がり{}回、値上がり{}回、変わらず{}回（平均{:+.1}%）",
+            down,
+            up,
+            ratios.len() - down - up,
+            average
+        );
+    }
+    if ratios.len() < fired {
+        println!(
+            "{}回は30日後の価格がまだ確定していません。",
+            fired - ratios.len()
+        );
+    }
+    EXIT_OK
+}
+
+/// Webhook の設定
+pub struct WebhookOptions {
+    /// 本文の形式
+    pub format: Option<String>,
+    /// 署名に使う秘密鍵
+    pub secret: Option<String>,
+    /// 本文のテンプレート
+    pub template: Option<String>,
+    /// 送るイベントの種類（カンマ区切り）
+    pub events: Option<String>,
+    /// 再送する回数
+    pub retries: Option<u32>,
+}
+
+/// Webhook を表示・追加・削除する。`test` のときは、テスト用のイベントを送る。
+pub fn command_webhooks(
+    action: Option<&str>,
+    name: Option<&str>,
+    url: Option<&str>,
+    options: &WebhookOptions,
+) -> i32 {
+    match action.unwrap_or("list") {
+        "add" => webhooks_add(name, url, options),
+        "remove" => webhooks_remove(name),
+        "test" => webhooks_test(name),
+        _ => {
+            let config = CONFIG.lock().unwrap();
+            if config.webhooks.is_empty() {
+                println!("Webhook はありません。`webhooks add <name> <url>` で追加します。");
+            }
+            for hook in config.webhooks.iter() {
+                println!("{}: {}", hook.name, hook.url);
+                println!("  {}", hook.describe_options());
+            }
+            EXIT_OK
+        }
+    }
+}
+
+/// Webhook を追加する。同じ名称のものがあるときは置き換える。
+fn webhooks_add(name: Option<&str>, url: Option<&str>, options: &WebhookOptions) -> i32 {
+    let (Some(name), Some(url)) = (name, url) else {
+        eprintln!("Webhook の名称と URL を指定してください（例: webhooks add ops https://example.com/hook）。");
+        return EXIT_USAGE;
+    };
+    let mut hook = Webhook::new(name, url);
+    if let Some(format) = &options.format {
+        hook.format = WebhookFormat::from_string(format).unwrap_or_default();
+    }
+    if hook.format.is_chat() && options.template.is_some() {
+        eprintln!("テンプレートは json の形式でのみ使えます。");
+        return EXIT_USAGE;
+    }
+    hook.secret = options.secret.clone();
+    hook.template = options.template.clone();
+    if let Some(retries) = options.retries {
+        hook.retries = retries;
+    }
+    match parse_event_kinds(options.events.as_deref()) {
+        Some(kinds) => hook.events = kinds,
+        None => return EXIT_USAGE,
+    }
+    // テンプレートの誤りは、追加する時点で知らせる。
+    if let Err(e) = hook.body(&Event::sample(EventKind::Alert)) {
+        eprintln!("{}", e);
+        return EXIT_USAGE;
+    }
+
+    let mut config = CONFIG.lock().unwrap();
+    match config.webhooks.iter_mut().find(|v| v.name == name) {
+        Some(x) => *x = hook,
+        None => config.webhooks.push(hook),
+    }
+    match config.write_file() {
+        Ok(_) => {
+            println!("Webhook「{}」を保存しました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Webhook を削除する。
+fn webhooks_remove(name: Option<&str>) -> i32 {
+    let Some(name) = name else {
+        eprintln!("削除する Webhook の名称を指定してください。");
+        return EXIT_USAGE;
+    };
+    let mut config = CONFIG.lock().unwrap();
+    let Some(index) = config.webhooks.iter().position(|v| v.name == name) else {
+        eprintln!("Webhook `{}` はありません。", name);
+        return EXIT_ERROR;
+    };
+    config.webhooks.remove(index);
+    match config.write_file() {
+        Ok(_) => {
+            println!("Webhook「{}」を削除しました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Webhook へテスト用のイベントを送る。名称を省略したときは全ての Webhook へ送る。
+fn webhooks_test(name: Option<&str>) -> i32 {
+    let hooks = CONFIG.lock().unwrap().webhooks.clone();
+    let hooks = hooks
+        .into_iter()
+        .filter(|v| name.is_none_or(|n| v.name == n))
+        .collect::<Vec<_>>();
+    if hooks.is_empty() {
+        match name {
+            Some(name) => eprintln!("Webhook `{}` はありません。", name),
+            None => eprintln!("Webhook はありません。"),
+        }
+        return EXIT_ERROR;
+    }
+
+    let events = [
+        Event::sample(EventKind::Change),
+        Event::sample(EventKind::Alert),
+    ];
+    let sender = webhook::Sender::new();
+    let mut code = EXIT_OK;
+    for hook in hooks.iter() {
+        let result = hook.messages(&events).and_then(|messages| {
+            messages.iter().try_for_each(|v| sender.send(hook, v))?;
+            Ok(messages.len())
+        });
+        match result {
+            Ok(0) => {
+                eprintln!("{}: 送信対象のイベントがありません。", hook.name);
+                code = EXIT_ERROR;
+            }
+            Ok(_) => println!("{}: 送信しました。", hook.name),
+            Err(e) => {
+                eprintln!("{}: {}", hook.name, e);
+                code = EXIT_ERROR;
+            }
+        }
+    }
+    code
+}
+
+/// フックを表示・追加・削除する。`test` のときは、テスト用のイベントで実行する。
+pub fn command_hooks(
+    action: Option<&str>,
+    name: Option<&str>,
+    command: &[String],
+    events: Option<&str>,
+    timeout: Option<u64>,
+) -> i32 {
+    match action.unwrap_or("list") {
+        "add" => hooks_add(name, command, events, timeout),
+        "remove" => hooks_remove(name),
+        "test" => hooks_test(name),
+        _ => {
+            let config = CONFIG.lock().unwrap();
+            if config.hooks.is_empty() {
+                println!("フックはありません。`hooks add <name> <command>` で追加します。");
+            }
+            for hook in config.hooks.iter() {
+                println!("{}: {}", hook.name, hook.command_line());
+                println!("  {}", hook.describe_options());
+            }
+            EXIT_OK
+        }
+    }
+}
+
+/// フックを追加する。同じ名称のものがあるときは置き換える。
+fn hooks_add(
+    name: Option<&str>,
+    command: &[String],
+    events: Option<&str>,
+    timeout: Option<u64>,
+) -> i32 {
+    let (Some(name), Some((program, args))) = (name, command.split_first()) else {
+        eprintln!("フックの名称とコマンドを指定してください（例: hooks add log /usr/local/bin/notify.sh）。");
+        return EXIT_USAGE;
+    };
+    let mut hook = Hook::new(name, program, args);
+    if let Some(timeout) = timeout {
+        hook.timeout = timeout;
+    }
+    match parse_event_kinds(events) {
+        Some(kinds) => hook.events = kinds,
+        None => return EXIT_USAGE,
+    }
+
+    let mut config = CONFIG.lock().unwrap();
+    match config.hooks.iter_mut().find(|v| v.name == name) {
+        Some(x) => *x = hook,
+        None => config.hooks.push(hook),
+    }
+    match config.write_file() {
+        Ok(_) => {
+            println!("フック「{}」を保存しました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// フックを削除する。
+fn hooks_remove(name: Option<&str>) -> i32 {
+    let Some(name) = name else {
+        eprintln!("削除するフックの名称を指定してください。");
+        return EXIT_USAGE;
+    };
+    let mut config = CONFIG.lock().unwrap();
+    let Some(index) = config.hooks.iter().position(|v| v.name == name) else {
+        eprintln!("フック `{}` はありません。", name);
+        return EXIT_ERROR;
+    };
+    config.hooks.remove(index);
+    match config.write_file() {
+        Ok(_) => {
+            println!("フック「{}」を削除しました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// フックをテスト用のイベントで実行する。名称を省略したときは全てのフックを実行する。
+fn hooks_test(name: Option<&str>) -> i32 {
+    let hooks = CONFIG.lock().unwrap().hooks.clone();
+    let hooks = hooks
+        .into_iter()
+        .filter(|v| name.is_none_or(|n| v.name == n))
+        .collect::<Vec<_>>();
+    if hooks.is_empty() {
+        match name {
+            Some(name) => eprintln!("フック `{}` はありません。", name),
+            None => eprintln!("フックはありません。"),
+        }
+        return EXIT_ERROR;
+    }
+
+    let mut code = EXIT_OK;
+    for hook in hooks.iter() {
+        let kind = [EventKind::Alert, EventKind::Change, EventKind::Error]
+            .into_iter()
+            .find(|v| hook.accepts(*v))
+            .unwrap_or(EventKind::Alert);
+        match hook.run(&Event::sample(kind)) {
+            Ok(_) => println!("{}: 正常に終了しました。", hook.name),
+            Err(e) => {
+                println!("{}: {}", hook.name, e);
+                code = EXIT_ERROR;
+            }
+        }
+    }
+    code
+}
+
+/// カンマ区切りのイベントの種類を解析する。省略したときは空（全ての種類）を返す。
+/// 不明な種類があるときはメッセージを表示して None を返す。
+fn parse_event_kinds(events: Option<&str>) -> Option<Vec<EventKind>> {
+    let mut kinds = vec![];
+    for key in events.iter().flat_map(|v| v.split(',')).map(|v| v.trim()) {
+        match EventKind::from_string(key) {
+            Some(kind) => kinds.push(kind),
+            None => {
+                eprintln!(
+                    "イベントの種類 `{}` はありません（{} のいずれか）。",
+                    key,
+                    EVENT_KINDS.join(", ")
+                );
+                return None;
+            }
+        }
+    }
+    Some(kinds)
+}
+
+/// フィードの設定
+pub struct FeedOptions {
+    /// フィードの形式
+    pub format: Option<String>,
+    /// 対象にするタグ
+    pub tag: Option<String>,
+    /// エントリの最大の件数
+    pub limit: Option<usize>,
+    /// HTTP で待ち受けるアドレス
+    pub listen: Option<String>,
+}
+
+/// フィードを表示・追加・削除する。`print` のときはフィードを出力し、`serve` のときは HTTP で配信する。
+pub fn command_feeds(
+    action: Option<&str>,
+    name: Option<&str>,
+    path: Option<&str>,
+    options: &FeedOptions,
+) -> i32 {
+    match action.unwrap_or("list") {
+        "add" => feeds_add(name, path, options),
+        "remove" => feeds_remove(name),
+        "print" => feeds_print(name, options),
+        "serve" => {
+            let listen = options.listen.as_deref().unwrap_or("127.0.0.1:8080");
+            let limit = options.limit.unwrap_or_else(feed::default_limit);
+            let profile = CONFIG.lock().unwrap().active_profile();
+            match feed::serve(listen, limit, &profile) {
+                Ok(_) => EXIT_OK,
+                Err(e) => {
+                    eprintln!("{} で待ち受けられませんでした({})", listen, e);
+                    EXIT_ERROR
+                }
+            }
+        }
+        _ => {
+            let config = CONFIG.lock().unwrap();
+            if config.feeds.is_empty() {
+                println!("フィードはありません。`feeds add <name> <path>` で追加します。");
+            }
+            for feed in config.feeds.iter() {
+                println!("{}: {}", feed.name, feed.path);
+                println!("  {}", feed.describe_options());
+            }
+            EXIT_OK
+        }
+    }
+}
+
+/// 指定されたオプションでフィードの設定を上書きする。
+fn apply_feed_options(feed: &mut Feed, options: &FeedOptions) {
+    if let Some(format) = &options.format {
+        feed.format = FeedFormat::from_string(format).unwrap_or_default();
+    }
+    if let Some(tag) = &options.tag {
+        feed.tag = Some(tag.clone());
+    }
+    if let Some(limit) = options.limit {
+        feed.limit = limit;
+    }
+}
+
+/// フィードを追加する。同じ名称のものがあるときは置き換える。
+fn feeds_add(name: Option<&str>, path: Option<&str>, options: &FeedOptions) -> i32 {
+    let (Some(name), Some(path)) = (name, path) else {
+        eprintln!("フィードの名称とパスを指定してください（例: feeds add all /var/www/html/yodoprice.xml）。");
+        return EXIT_USAGE;
+    };
+    let mut feed = Feed::new(name, path);
+    apply_feed_options(&mut feed, options);
+
+    // 追加した時点の内容で書き出し、パスに書き込めるか確かめる。
+    let mut config = CONFIG.lock().unwrap();
+    let profile = config.active_profile();
+    let written = {
+        let app_state = APP_STATE.lock().unwrap();
+        feed.write(&app_state.histories, &profile, chrono::Utc::now())
+    };
+    if let Err(e) = written {
+        eprintln!("{} へ書き出せませんでした({})", path, e);
+        return EXIT_ERROR;
+    }
+    match config.feeds.iter_mut().find(|v| v.name == name) {
+        Some(x) => *x = feed,
+        None => config.feeds.push(feed),
+    }
+    match config.write_file() {
+        Ok(_) => {
+            println!("フィード「{}」を保存し、{} へ書き出しました。", name, path);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// フィードを削除する。書き出したファイルは削除しない。
+fn feeds_remove(name: Option<&str>) -> i32 {
+    let Some(name) = name else {
+        eprintln!("削除するフィードの名称を指定してください。");
+        return EXIT_USAGE;
+    };
+    let mut config = CONFIG.lock().unwrap();
+    let Some(index) = config.feeds.iter().position(|v| v.name == name) else {
+        eprintln!("フィード `{}` はありません。", name);
+        return EXIT_ERROR;
+    };
+    config.feeds.remove(index);
+    match config.write_file() {
+        Ok(_) => {
+            println!("フィード「{}」を削除しました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// フィードを標準出力へ出力する。名称を指定したときは、そのフィードの設定を使う。
+fn feeds_print(name: Option<&str>, options: &FeedOptions) -> i32 {
+    let config = CONFIG.lock().unwrap();
+    let mut feed = match name {
+        Some(name) => match config.feeds.iter().find(|v| v.name == name) {
+            Some(x) => x.clone(),
+            None => {
+                eprintln!("フィード `{}` はありません。", name);
+                return EXIT_ERROR;
+            }
+        },
+        None => Feed::new("", ""),
+    };
+    apply_feed_options(&mut feed, options);
+
+    let app_state = APP_STATE.lock().unwrap();
+    let entries = feed::entries(&app_state.histories, feed.tag.as_deref(), feed.limit);
+    print!(
+        "{}",
+        feed::render(
+            feed.format,
+            feed.tag.as_deref(),
+            &entries,
+            &config.active_profile(),
+            chrono::Utc::now()
+        )
+    );
+    EXIT_OK
+}
+
+/// メールの設定とまとめの状況を表示する。`test` のときはテスト用のアラートを、`digest` のときはまとめをすぐに送る。
+pub fn command_mail(action: Option<&str>) -> i32 {
+    let Some(config) = CONFIG.lock().unwrap().mail.clone() else {
+        eprintln!("メールの設定がありません。config.json の `mail` に SMTP サーバと送信先を設定してください。");
+        return EXIT_ERROR;
+    };
+    match action.unwrap_or("status") {
+        "test" => {
+            let (subject, text, html) = mail::render_alerts(&[Event::sample(EventKind::Alert)]);
+            match mail::send_mail(&config, &subject, text, html) {
+                Ok(_) => {
+                    println!("{} へ送信しました。", config.to.join(", "));
+                    EXIT_OK
+                }
+                Err(e) => {
+                    eprintln!("メールを送れませんでした（{}）", e);
+                    EXIT_ERROR
+                }
+            }
+        }
+        "digest" => {
+            let now = chrono::Utc::now();
+            let mut digest = match mail::Digest::from_file() {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("{} を読み込めませんでした({})", mail::DIGEST_FILE_NAME, e);
+                    return EXIT_ERROR;
+                }
+            };
+            let (subject, text, html) = digest.render(now);
+            if let Err(e) = mail::send_mail(&config, &subject, text, html) {
+                eprintln!("メールを送れませんでした（{}）", e);
+                return EXIT_ERROR;
+            }
+            digest.reset(now);
+            if let Err(e) = digest.write_file() {
+                eprintln!("まとめの保存時にエラーが発生しました({})", e);
+                return EXIT_ERROR;
+            }
+            println!("まとめを {} へ送信しました。", config.to.join(", "));
+            EXIT_OK
+        }
+        _ => {
+            println!(
+                "SMTP: {}:{} ({})",
+                config.host, config.port, config.security
+            );
+            println!("送信元: {}", config.from);
+            println!("送信先: {}", config.to.join(", "));
+            println!(
+                "アラート: {}",
+                if config.alerts {
+                    "すぐに送る"
+                } else {
+                    "送らない"
+                }
+            );
+            if !config.digest {
+                println!("まとめ: 送らない");
+                return EXIT_OK;
+            }
+            let digest = match mail::Digest::from_file() {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("{} を読み込めませんでした({})", mail::DIGEST_FILE_NAME, e);
+                    return EXIT_ERROR;
+                }
+            };
+            match digest.since {
+                Some(since) => println!(
+                    "まとめ: {} 以降の {}件（1日ごとに送る）",
+                    format_jst(&since, "%Y/%m/%d %H:%M"),
+                    digest.entries.len()
+                ),
+                None => println!("まとめ: 次の更新から溜め始めます（1日ごとに送る）"),
+            }
+            EXIT_OK
+        }
+    }
+}
+
+/// 全製品の価格を更新し、終了コードを返す。
+/// 取得に失敗した製品があるときは EXIT_ERROR を、価格が変化した製品があるときは EXIT_CHANGED を返す。
+pub fn update_all(output: OutputFormat) -> i32 {
+    let (context, notifiers) = {
+        let config = CONFIG.lock().unwrap();
+        let (context, errors) = AlertContext::from_config(&config);
+        for e in errors {
+            eprintln!("{}", e);
+        }
+        (context, Notifiers::from_config(&config))
+    };
+    let result = APP_STATE.lock().unwrap().update_all(&context);
+    if output == OutputFormat::Human {
+        for line in render_report(&result, use_color()) {
+            println!("{}", line);
+        }
+    } else {
+        let rows: Vec<_> = result.items.iter().map(UpdateRow::new).collect();
+        println!("{}", render(&rows, output));
+        for e in result.errors() {
+            eprintln!("{}: {}", e.error.as_deref().unwrap_or_default(), e.url);
+        }
+    }
+
+    // 通知に時間がかかったり失敗したりしても更新の結果を失わないよう、先にデータを保存する。
+    // 通知の失敗は更新の結果（終了コード）には含めない。
+    save_file();
+    let errors = {
+        let app_state = APP_STATE.lock().unwrap();
+        notifiers.dispatch(
+            &result,
+            &app_state.histories,
+            &context.profile,
+            chrono::Utc::now(),
+        )
+    };
+    for e in errors {
+        eprintln!("{}", e);
+    }
+
+    if result.errors().next().is_some() {
+        EXIT_ERROR
+    } else if result.changed().next().is_some() {
+        EXIT_CHANGED
+    } else {
+        EXIT_OK
+    }
+}
+
+/// 一覧の表示方法
+pub struct ListOptions {
+    /// 全ての支払い方法での実質価格を並べて表示する。
+    pub compare: bool,
+    /// 買い時の評価の根拠も表示する。
+    pub explain: bool,
+    /// アーカイブした製品のみを表示する（通常はアーカイブした製品を表示しない）。
+    pub archived: bool,
+    /// 出力形式
+    pub output: OutputFormat,
 }
 
 // 製品の一覧を表示する。
-pub fn print_list() {
+// `view` が指定されたときは、保存した表示条件に `query` を重ねて使う。
+// `save` が指定されたときは、表示条件をその名称で保存する。
+// 絞り込みの条件の誤り等で表示できないときは EXIT_USAGE を返す。
+pub fn print_list(
+    query: &ListQuery,
+    view: Option<&str>,
+    save: Option<&str>,
+    options: &ListOptions,
+) -> i32 {
+    let mut config = CONFIG.lock().unwrap();
+    let query = match view {
+        Some(name) => match config.view(name) {
+            Some(v) => v.query.merge(query),
+            None => {
+                eprintln!("表示条件 `{}` は保存されていません。", name);
+                return EXIT_USAGE;
+            }
+        },
+        None => query.clone(),
+    };
+    let filter = match query.filter.as_deref().map(parse_query).transpose() {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let sort = query.sort.as_deref().and_then(SortKey::from_string);
+    if let Some(name) = save {
+        config.save_view(name, query.clone());
+        match config.write_file() {
+            Ok(_) => eprintln!("表示条件を `{}` として保存しました。", name),
+            Err(e) => eprintln!("設定ファイルの保存時にエラーが発生しました({})", e),
+        }
+    }
+
+    let profile = config.active_profile();
     let app_state = APP_STATE.lock().unwrap();
-    let products = &app_state.histories;
-    let mut i = 1;
+    let now = chrono::Utc::now();
+
+    let mut rows: Vec<_> = (1..)
+        .zip(app_state.histories.iter())
+        .filter(|(_, product)| product.archived == options.archived)
+        .filter(|(_, product)| query.tag.as_ref().is_none_or(|t| product.has_tag(t)))
+        .filter_map(|(i, product)| Some((ListRow::new(i, product, &profile, now)?, product)))
+        .filter(|(row, _)| filter.as_ref().is_none_or(|f| f.matches(row)))
+        .collect();
+    if let Some(key) = sort {
+        sort_rows(&mut rows, key, query.reverse);
+    } else if query.reverse {
+        rows.reverse();
+    }
+
+    if options.output != OutputFormat::Human {
+        let (rows, _): (Vec<_>, Vec<_>) = rows.into_iter().unzip();
+        println!("{}", render(&rows, options.output));
+        return EXIT_OK;
+    }
+
+    let mut table = Table::new(vec![
+        Column::new("#", Align::Right),
+        Column::new("名称", Align::Left).shrink(16),
+        Column::new("現在", Align::Right),
+        Column::new("最高", Align::Right),
+        Column::new("最安", Align::Right),
+        Column::new("ポイント", Align::Right),
+        Column::new("在庫", Align::Left),
+        Column::new("変化", Align::Right),
+        Column::new("実質", Align::Right).optional(2),
+        Column::new("30日間", Align::Left).optional(3),
+        Column::new("評価", Align::Left).optional(1),
+    ]);
+    let mut extra_lines = vec![];
+    for (row, product) in rows.iter() {
+        let score_text = match &row.buy_score {
+            Some(s) => format!("{}({})", s.rating, s.score),
+            None => "-".to_string(),
+        };
+        table.push(vec![
+            row.index.to_string(),
+            row.display_name().to_string(),
+            yen(row.price),
+            yen(row.high),
+            yen(row.low),
+            format!("{}({}%)", commafy(row.point), row.point_ratio),
+            row.status.to_string(),
+            signed_yen(row.change),
+            yen(row.effective_price),
+            price_sparkline(&product.history, 30, 12, now),
+            score_text,
+        ]);
 
-    for product in products {
-        print!("{}: {}  ", i, omitted_string(&product.name));
-        let (high, low, now) = product.high_low_now();
+        // 表の各行の下に表示する内容
+        let mut lines = vec![];
+        if options.compare {
+            let last = product.history.last().unwrap();
+            for p in config.profiles() {
+                lines.push(format!(
+                    "    {}: 実質￥{} ({}%還元)",
+                    p.name,
+                    commafy(last.effective_price(&p)),
+                    last.point_ratio_with(&p)
+                ));
+            }
+        }
+        if !product.tags.is_empty() {
+            lines.push(format!("    タグ: {}", product.tags.join(", ")));
+        }
+        if options.explain {
+            if let Some(s) = &row.buy_score {
+                lines.push(format!("    {}", s.reasons.join("、")));
+            }
+        }
+        extra_lines.push(lines);
+    }
+
+    let lines = table.render(terminal_width());
+    println!("{}", lines[0]);
+    for (line, extra) in lines[1..].iter().zip(extra_lines) {
+        println!("{}", line);
+        for x in extra {
+            println!("{}", x);
+        }
+    }
+    EXIT_OK
+}
+
+/// 保存した表示条件の一覧を表示する。`delete` が指定されたときは、その表示条件を削除する。
+pub fn command_views(delete: Option<&str>) -> i32 {
+    let mut config = CONFIG.lock().unwrap();
+
+    let Some(name) = delete else {
+        if config.views.is_empty() {
+            println!("保存した表示条件はありません。`list --save <name>` で保存します。");
+        }
+        for v in config.views.iter() {
+            println!("{}: list {}", v.name, v.query.describe());
+        }
+        return EXIT_OK;
+    };
+
+    let Some(index) = config.views.iter().position(|v| v.name == name) else {
+        eprintln!("表示条件 `{}` は保存されていません。", name);
+        return EXIT_ERROR;
+    };
+    config.views.remove(index);
+    match config.write_file() {
+        Ok(_) => {
+            println!("表示条件 `{}` を削除しました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// 製品の詳細と、全ての価格履歴を表示する。
+/// 日時は日本時間で表示する。`output` が JSON のときは詳細と価格履歴を、
+/// CSV 等のときは価格履歴のみを出力する（日時は UTC）。
+pub fn command_show(key: &str, output: OutputFormat) -> i32 {
+    let config = CONFIG.lock().unwrap();
+    let profile = config.active_profile();
+    let app_state = APP_STATE.lock().unwrap();
+    let Some(product) = app_state.find_product(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    match output {
+        OutputFormat::Human => {}
+        OutputFormat::Json => {
+            let detail = ProductDetail::new(product, &profile);
+            println!("{}", serde_json::to_string_pretty(&detail).unwrap());
+            return EXIT_OK;
+        }
+        _ => {
+            let rows = HistoryRow::from_history(&product.history, &profile);
+            println!("{}", render(&rows, output));
+            return EXIT_OK;
+        }
+    }
+    let datetime = |v: Option<chrono::DateTime<chrono::Utc>>| match v {
+        Some(x) => format_jst(&x, "%Y/%m/%d %H:%M"),
+        None => "-".to_string(),
+    };
+
+    println!("製品名: {}", product.name);
+    if let Some(x) = &product.custom_name {
+        println!("表示名: {}", x);
+    }
+    println!("メーカ: {}", product.maker);
+    println!("URL: {}", product.url);
+    println!("ID: {}", product.id);
+    println!("SKU: {}", product.sku);
+    println!("カテゴリ: {}", product.category);
+    println!("登録日時: {}", datetime(product.registered_at));
+    println!("最終取得日時: {}", datetime(product.last_checked));
+    if !product.tags.is_empty() {
+        println!("タグ: {}", product.tags.join(", "));
+    }
+    if let Some(x) = &product.note {
+        println!("メモ: {}", x);
+    }
+    if product.archived {
+        println!("（アーカイブ済み）");
+    }
+
+    if let Some(last) = product.history.last() {
+        println!("実質価格:");
+        for p in config.profiles() {
+            let mark = if p.name == profile.name { "*" } else { " " };
+            println!(
+                " {} {}: ￥{} ({}%還元)",
+                mark,
+                p.name,
+                commafy(last.effective_price(&p)),
+                last.point_ratio_with(&p)
+            );
+        }
+    }
+
+    println!();
+    let mut table = Table::new(vec![
+        Column::new("日時(JST)", Align::Left),
+        Column::new("価格", Align::Right),
+        Column::new("ポイント", Align::Right),
+        Column::new("還元率", Align::Right),
+        Column::new("実質価格", Align::Right),
+        Column::new("変化", Align::Right),
+        Column::new("在庫", Align::Left),
+    ]);
+    for row in HistoryRow::from_history(&product.history, &profile) {
+        table.push(vec![
+            format_jst(&row.datetime, "%Y/%m/%d %H:%M"),
+            yen(row.price),
+            commafy(row.point),
+            format!("{}%", row.point_ratio),
+            yen(row.effective_price),
+            signed_yen(row.change),
+            row.status.to_string(),
+        ]);
+    }
+    for line in table.render(usize::MAX) {
+        println!("{}", line);
+    }
+    EXIT_OK
+}
+
+/// 製品の価格と実質価格の推移をグラフで表示する。
+/// 幅が指定されなかったときは、端末の幅に合わせる。
+pub fn command_chart(key: &str, height: Option<usize>, width: Option<usize>) -> i32 {
+    let config = CONFIG.lock().unwrap();
+    let profile = config.active_profile();
+    let app_state = APP_STATE.lock().unwrap();
+    let Some(product) = app_state.find_product(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+
+    println!("{}", product.display_name());
+    let lines = render_chart(
+        &product.history,
+        |v| v.effective_price(&profile),
+        width.unwrap_or_else(terminal_width),
+        height.unwrap_or(12),
+        chrono::Utc::now(),
+    );
+    for line in lines {
+        println!("{}", line);
+    }
+    println!(
+        "█ 実質価格 ({})  ░ ポイント分  H 最高値  L 最安値  ━ 在庫あり  ┅ 在庫僅少",
+        profile.name
+    );
+    EXIT_OK
+}
+
+/// 製品の価格の統計を表示する。
+/// 期間が指定されなかったときは、30日・90日・全期間の統計を表示する。
+pub fn command_stats(key: &str, window: Option<Window>) -> i32 {
+    let windows = match window {
+        Some(w) => vec![w],
+        None => vec![Window::Days(30), Window::Days(90), Window::All],
+    };
+
+    let app_state = APP_STATE.lock().unwrap();
+    let Some(product) = app_state.find_product(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+
+    println!("{}", product.name);
+    let now = chrono::Utc::now();
+    for window in windows {
+        let Some(stats) = price_stats(&product.history, window, now) else {
+            continue;
+        };
+        println!("[{}]", window);
+        println!(
+            "  平均価格(時間加重): ￥{}",
+            commafy(stats.average.round() as i64)
+        );
+        println!("  中央値: ￥{}", commafy(stats.median));
+        println!("  現在価格のパーセンタイル: {:.1}%", stats.percentile);
+        println!("  価格の変化回数: {}回", stats.changes);
+        println!("  典型的な変化幅: ￥{}", commafy(stats.typical_change));
+        println!("  最後の変化からの日数: {}日", stats.days_since_change);
+        println!(
+            "  最安値が続いた最長期間: {:.1}日",
+            stats.longest_at_low.num_seconds() as f64 / 86400.0
+        );
+    }
+    EXIT_OK
+}
+
+/// セール（一時的な値下がり）の履歴を表示する。
+/// 製品が指定されなかったときは、全製品のセールの傾向を表示する。
+pub fn command_events(key: Option<&str>) -> i32 {
+    let app_state = APP_STATE.lock().unwrap();
+    let now = chrono::Utc::now();
+
+    let Some(key) = key else {
+        for (i, product) in (1..).zip(app_state.histories.iter()) {
+            if product.archived {
+                continue;
+            }
+            let Some(first) = product.history.first() else {
+                continue;
+            };
+            let events = sale_events(&product.history, now);
+            let summary = summarize(&events, first.datetime, now);
+            println!(
+                "{}: {}  {}回 (90日あたり{:.1}回)  平均{:.1}日  平均-{:.1}%",
+                i,
+                omitted_string(product.display_name()),
+                summary.count,
+                summary.per_90_days,
+                summary.average_days,
+                summary.average_depth_ratio
+            );
+        }
+        return EXIT_OK;
+    };
+
+    let Some(product) = app_state.find_product(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    println!("{}", product.name);
+    let events = sale_events(&product.history, now);
+    if events.is_empty() {
+        println!("セールは検出されませんでした。");
+        return EXIT_OK;
+    }
+    for e in events.iter() {
+        let end = match e.end {
+            Some(x) => format_jst(&x, "%Y/%m/%d"),
+            None => "セール中".to_string(),
+        };
+        println!(
+            "  {} - {} ({:.1}日)  ￥{} → ￥{} ({}の最安値)  -￥{} (-{:.1}%){}",
+            format_jst(&e.start, "%Y/%m/%d"),
+            end,
+            e.duration(now).num_seconds() as f64 / 86400.0,
+            commafy(e.base_price),
+            commafy(e.bottom_price),
+            format_jst(&e.bottom_at, "%m/%d"),
+            commafy(e.depth()),
+            e.depth_ratio(),
+            if e.point_changed {
+                "  ポイント変化あり"
+            } else {
+                ""
+            }
+        );
+    }
+    EXIT_OK
+}
+
+/// 在庫状況の推移を表示する。
+/// 製品が指定されなかったときは、在庫があった時間の割合が低い順に全製品を表示する。
+pub fn command_stock(key: Option<&str>) -> i32 {
+    let app_state = APP_STATE.lock().unwrap();
+    let now = chrono::Utc::now();
+
+    let Some(key) = key else {
+        let mut rows: Vec<_> = (1..)
+            .zip(app_state.histories.iter())
+            .filter(|(_, p)| !p.archived)
+            .filter_map(|(i, p)| stock_summary(&p.history, now).map(|s| (i, p, s)))
+            .collect();
+        rows.sort_by(|a, b| a.2.in_stock_ratio.total_cmp(&b.2.in_stock_ratio));
+        for (i, product, summary) in rows {
+            let restock = match summary.mean_restock {
+                Some(x) => format!("{:.1}日", x.num_seconds() as f64 / 86400.0),
+                None => "-".to_string(),
+            };
+            println!(
+                "{}: {}  在庫あり{:.1}%  在庫切れ{}回  平均復活期間{}",
+                i,
+                omitted_string(product.display_name()),
+                summary.in_stock_ratio,
+                summary.out_of_stock_count,
+                restock
+            );
+        }
+        return EXIT_OK;
+    };
+
+    let Some(product) = app_state.find_product(key) else {
+        eprintln!("製品 `{}` は登録されていません。", key);
+        return EXIT_ERROR;
+    };
+    println!("{}", product.name);
+    for period in timeline(&product.history, now) {
+        println!(
+            "  {}  {} ({:.1}日)",
+            format_jst(&period.start, "%Y/%m/%d %H:%M"),
+            period.status,
+            period.duration().num_seconds() as f64 / 86400.0
+        );
+    }
+    if let Some(summary) = stock_summary(&product.history, now) {
         println!(
-            "￥{} - (￥{}/￥{})",
-            commafy(now),
-            commafy(high),
-            commafy(low)
+            "在庫あり{:.1}%  在庫切れ{}回",
+            summary.in_stock_ratio, summary.out_of_stock_count
         );
-        i += 1;
+    }
+    EXIT_OK
+}
+
+/// 支払い方法の一覧を表示する。名称が指定されたときは、その支払い方法に切り替える。
+pub fn command_profile(name: Option<&str>) -> i32 {
+    let mut config = CONFIG.lock().unwrap();
+
+    let Some(name) = name else {
+        let active = config.active_profile();
+        for p in config.profiles() {
+            let mark = if p.name == active.name { "*" } else { " " };
+            println!(
+                "{} {}  還元率{:+}%  1ポイント={}円",
+                mark, p.name, p.point_ratio_offset, p.point_value
+            );
+        }
+        return EXIT_OK;
+    };
+
+    if config.profile(name).is_none() {
+        eprintln!("支払い方法 `{}` は登録されていません。", name);
+        return EXIT_ERROR;
+    }
+    config.active_profile = Some(name.to_string());
+    match config.write_file() {
+        Ok(_) => {
+            println!("支払い方法を `{}` に切り替えました。", name);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("設定ファイルの保存時にエラーが発生しました({})", e);
+            EXIT_ERROR
+        }
     }
 }
 
@@ -111,3 +1965,36 @@ pub fn save_file() {
     let app_state = APP_STATE.lock().unwrap();
     let _ = (*app_state).write_file();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_name_exits_with_error() {
+        let commands = [
+            Command::Stats {
+                product: "no-such-product".to_string(),
+                window: None,
+            },
+            Command::Events {
+                product: Some("no-such-product".to_string()),
+            },
+            Command::Stock {
+                product: Some("no-such-product".to_string()),
+            },
+            Command::Profile {
+                name: Some("no-such-profile".to_string()),
+            },
+        ];
+        for command in commands {
+            assert_eq!(run(command, false), EXIT_ERROR);
+        }
+    }
+
+    #[test]
+    fn listing_without_product_exits_ok() {
+        assert_eq!(run(Command::Events { product: None }, false), EXIT_OK);
+        assert_eq!(run(Command::Stock { product: None }, false), EXIT_OK);
+    }
+}
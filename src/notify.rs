@@ -0,0 +1,256 @@
+//! 更新の結果を外部へ知らせるためのイベント。
+//!
+//! 価格の更新で生じた変化・アラート・取得の失敗を、製品ごと・種類ごとのイベントに分けて表す。
+//! Webhook 等の通知先には、このイベントを JSON にしたものを送る。
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, PaymentProfile};
+use crate::data::{ProductHistory, UpdateResult};
+use crate::feed::{self, Feed};
+use crate::hook::{self, Hook};
+use crate::mail::{self, MailConfig};
+use crate::types::StockStatus;
+use crate::webhook::{self, Webhook};
+
+/// イベントの種類の名称の一覧
+pub const EVENT_KINDS: &[&str] = &["change", "alert", "error"];
+
+/// イベントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// 価格・在庫状況・ポイント還元率の変化
+    Change,
+    /// アラートやルールの条件を満たした
+    Alert,
+    /// 価格の取得に失敗した
+    Error,
+}
+
+impl EventKind {
+    pub fn from_string(string: &str) -> Option<EventKind> {
+        match string {
+            "change" => Some(EventKind::Change),
+            "alert" => Some(EventKind::Alert),
+            "error" => Some(EventKind::Error),
+            _ => None,
+        }
+    }
+
+    /// JSON やヘッダに使う名称を返す。
+    pub fn key(&self) -> &'static str {
+        match self {
+            EventKind::Change => "change",
+            EventKind::Alert => "alert",
+            EventKind::Error => "error",
+        }
+    }
+}
+
+/// 1つの製品についての1件のイベント
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    pub event: EventKind,
+    pub product_id: String,
+    /// 表示用の名称
+    pub name: String,
+    pub url: String,
+    /// 更新前の価格（円）
+    pub old_price: Option<u64>,
+    /// 更新後の価格（円）
+    pub price: Option<u64>,
+    /// 使用中の支払い方法での実質価格（円）
+    pub effective_price: Option<u64>,
+    pub point: Option<u64>,
+    pub old_point_ratio: Option<u64>,
+    pub point_ratio: Option<u64>,
+    pub old_status: Option<StockStatus>,
+    pub status: Option<StockStatus>,
+    /// 条件を満たしたアラートやルールの説明。アラートのときのみ
+    pub rule: Option<String>,
+    /// 取得に失敗したときのメッセージ
+    pub error: Option<String>,
+    /// 価格を取得した日時（取得に失敗したときは更新した日時）
+    pub datetime: DateTime<Utc>,
+}
+
+impl Event {
+    /// 通知先の確認に使う、架空の製品のイベントを返す。
+    pub fn sample(kind: EventKind) -> Self {
+        let fetched = kind != EventKind::Error;
+        Self {
+            event: kind,
+            product_id: "sample".to_string(),
+            name: "テスト用の製品".to_string(),
+            url: "https://www.yodobashi.com/".to_string(),
+            old_price: Some(30000),
+            price: fetched.then_some(27800),
+            effective_price: fetched.then_some(25020),
+            point: fetched.then_some(2780),
+            old_point_ratio: Some(10),
+            point_ratio: fetched.then_some(10),
+            old_status: Some(StockStatus::Sufficient),
+            status: fetched.then_some(StockStatus::Limited),
+            rule: (kind == EventKind::Alert).then(|| "price < 28000".to_string()),
+            error: (kind == EventKind::Error).then(|| "ページを取得できませんでした".to_string()),
+            datetime: Utc::now(),
+        }
+    }
+}
+
+/// `now` に行った更新の結果からイベントの一覧を作る。変化のなかった製品は含まない。
+pub fn events(result: &UpdateResult, profile: &PaymentProfile, now: DateTime<Utc>) -> Vec<Event> {
+    let mut events = vec![];
+    for item in result.items.iter() {
+        let before = item.before.as_ref();
+        let Some(after) = &item.after else {
+            if let Some(error) = &item.error {
+                events.push(Event {
+                    event: EventKind::Error,
+                    product_id: item.id.clone(),
+                    name: item.name.clone(),
+                    url: item.url.clone(),
+                    old_price: item.old_price(),
+                    price: None,
+                    effective_price: None,
+                    point: None,
+                    old_point_ratio: before.map(|v| v.point_ratio),
+                    point_ratio: None,
+                    old_status: before.map(|v| v.status),
+                    status: None,
+                    rule: None,
+                    error: Some(error.clone()),
+                    datetime: now,
+                });
+            }
+            continue;
+        };
+        let event = |kind, rule| Event {
+            event: kind,
+            product_id: item.id.clone(),
+            name: item.name.clone(),
+            url: item.url.clone(),
+            old_price: item.old_price(),
+            price: Some(after.price),
+            effective_price: Some(after.effective_price(profile)),
+            point: Some(after.point),
+            old_point_ratio: before.map(|v| v.point_ratio),
+            point_ratio: Some(after.point_ratio),
+            old_status: before.map(|v| v.status),
+            status: Some(after.status),
+            rule,
+            error: None,
+            datetime: after.datetime,
+        };
+        if item.is_changed()
+            || item.status_change().is_some()
+            || item.point_ratio_change().is_some()
+        {
+            events.push(event(EventKind::Change, None));
+        }
+        for alert in item.alerts.iter() {
+            events.push(event(EventKind::Alert, Some(alert.rule.clone())));
+        }
+    }
+    events
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 更新の結果を知らせる先（フィード・Webhook・フック・メール）
+#[derive(Debug, Clone, Default)]
+pub struct Notifiers {
+    pub feeds: Vec<Feed>,
+    pub webhooks: Vec<Webhook>,
+    pub hooks: Vec<Hook>,
+    pub mail: Option<MailConfig>,
+}
+
+impl Notifiers {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            feeds: config.feeds.clone(),
+            webhooks: config.webhooks.clone(),
+            hooks: config.hooks.clone(),
+            mail: config.mail.clone(),
+        }
+    }
+
+    /// `now` に終わった更新の結果を、フィードへ書き出し、Webhook・フック・メールで知らせる。
+    /// `histories` は更新後の全製品の価格履歴。失敗したもののメッセージを返す。
+    pub fn dispatch(
+        &self,
+        result: &UpdateResult,
+        histories: &[ProductHistory],
+        profile: &PaymentProfile,
+        now: DateTime<Utc>,
+    ) -> Vec<String> {
+        let mut errors = feed::write_all(&self.feeds, histories, profile, now);
+        let events = events(result, profile, now);
+        errors.extend(webhook::notify_all(&self.webhooks, &events));
+        errors.extend(hook::run_all(&self.hooks, &events));
+        if let Some(mail) = &self.mail {
+            errors.extend(mail::notify_all(mail, &events, result, now));
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::FiredAlert;
+    use crate::data::UpdateItem;
+    use crate::fixtures::{day, one_price_with, update_item};
+
+    #[test]
+    fn events_test() {
+        let one_price = |price, status| one_price_with(price, 10, status, 1);
+        let result = UpdateResult {
+            items: vec![
+                // 変化なし
+                update_item(
+                    "a",
+                    one_price(1000, StockStatus::Sufficient),
+                    Some(one_price(1000, StockStatus::Sufficient)),
+                ),
+                // 在庫状況のみ変化し、アラートの条件も満たした
+                UpdateItem {
+                    alerts: vec![FiredAlert {
+                        rule: "在庫あり".to_string(),
+                        price: 1000,
+                    }],
+                    ..update_item(
+                        "b",
+                        one_price(1000, StockStatus::BackOrder),
+                        Some(one_price(1000, StockStatus::Sufficient)),
+                    )
+                },
+                // 取得に失敗した
+                UpdateItem {
+                    error: Some("error".to_string()),
+                    ..update_item("c", one_price(1000, StockStatus::Sufficient), None)
+                },
+            ],
+        };
+
+        let profile = PaymentProfile::default();
+        let events = events(&result, &profile, day(1));
+        assert_eq!(3, events.len());
+        assert_eq!(EventKind::Change, events[0].event);
+        assert_eq!("b", events[0].product_id);
+        assert_eq!(Some(StockStatus::BackOrder), events[0].old_status);
+        assert_eq!(Some(StockStatus::Sufficient), events[0].status);
+        assert_eq!(Some(900), events[0].effective_price);
+        assert_eq!(None, events[0].rule);
+        assert_eq!(EventKind::Alert, events[1].event);
+        assert_eq!(Some("在庫あり".to_string()), events[1].rule);
+        assert_eq!(EventKind::Error, events[2].event);
+        assert_eq!("c", events[2].product_id);
+        assert_eq!(Some(1000), events[2].old_price);
+        assert_eq!(None, events[2].price);
+        assert_eq!(Some("error".to_string()), events[2].error);
+    }
+}
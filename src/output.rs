@@ -0,0 +1,536 @@
+//! スクリプト等から利用するための、機械可読な出力。
+//!
+//! 金額は全て円単位の整数で出力する。項目名は README に記載しており、変更しないこと。
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::config::PaymentProfile;
+use crate::data::{OnePrice, ProductHistory, UpdateItem};
+use crate::score::{buy_score, BuyScore};
+use crate::types::StockStatus;
+
+/// 出力形式として指定できる名称
+pub const OUTPUT_FORMATS: &[&str] = &["human", "json", "csv", "tsv", "markdown"];
+
+/// 出力形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 人が読むための表示
+    #[default]
+    Human,
+    Json,
+    Csv,
+    Tsv,
+    Markdown,
+}
+
+impl OutputFormat {
+    pub fn from_string(string: &str) -> Option<OutputFormat> {
+        let format = match string {
+            "human" => OutputFormat::Human,
+            "json" => OutputFormat::Json,
+            "csv" => OutputFormat::Csv,
+            "tsv" => OutputFormat::Tsv,
+            "markdown" => OutputFormat::Markdown,
+            _ => return None,
+        };
+        Some(format)
+    }
+}
+
+/// 表形式で出力できるデータ
+pub trait Record: Serialize {
+    /// 列の名称
+    const FIELDS: &'static [&'static str];
+
+    /// 各列の値（`FIELDS` と同じ順序）
+    fn values(&self) -> Vec<String>;
+}
+
+/// データの配列を、指定された形式の文字列に変換する。
+/// `Human` は各コマンドが独自に表示するため、ここでは JSON と同じ扱いにする。
+pub fn render<T: Record>(rows: &[T], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Human | OutputFormat::Json => serde_json::to_string_pretty(rows).unwrap(),
+        OutputFormat::Csv => delimited(rows, ',', csv_field),
+        OutputFormat::Tsv => delimited(rows, '\t', tsv_field),
+        OutputFormat::Markdown => markdown(rows),
+    }
+}
+
+fn delimited<T: Record>(rows: &[T], separator: char, escape: fn(&str) -> String) -> String {
+    let mut lines = vec![T::FIELDS.join(&separator.to_string())];
+    for row in rows {
+        let values: Vec<_> = row.values().iter().map(|v| escape(v)).collect();
+        lines.push(values.join(&separator.to_string()));
+    }
+    lines.join("\n")
+}
+
+/// CSV の値を、必要に応じて引用符で囲む (RFC 4180)。
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// TSV の値から、タブと改行を取り除く。
+fn tsv_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+fn markdown<T: Record>(rows: &[T]) -> String {
+    let mut lines = vec![
+        format!("| {} |", T::FIELDS.join(" | ")),
+        format!("|{}", "---|".repeat(T::FIELDS.len())),
+    ];
+    for row in rows {
+        let values: Vec<_> = row
+            .values()
+            .iter()
+            .map(|v| v.replace('|', "\\|").replace('\n', " "))
+            .collect();
+        lines.push(format!("| {} |", values.join(" | ")));
+    }
+    lines.join("\n")
+}
+
+fn datetime_text(datetime: &DateTime<Utc>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 一覧の1行分のデータ
+#[derive(Debug, Default, Serialize)]
+pub struct ListRow {
+    /// 一覧での番号(1始まり)
+    pub index: usize,
+    pub id: String,
+    pub name: String,
+    /// 表示用の名称
+    pub custom_name: Option<String>,
+    pub url: String,
+    pub maker: String,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    /// 現在価格（円）
+    pub price: u64,
+    pub point: u64,
+    pub point_ratio: u64,
+    pub status: StockStatus,
+    /// 使用中の支払い方法での実質価格（円）
+    pub effective_price: u64,
+    /// 最高値（円）
+    pub high: u64,
+    /// 最安値（円）
+    pub low: u64,
+    /// 最高値からの値下がり率（%）
+    pub discount: f64,
+    /// 最後の価格の取得での変化（円）。値下がりのときは負の値
+    pub change: i64,
+    /// 最後に価格が変化した日時
+    pub last_change: Option<DateTime<Utc>>,
+    /// 買い時の点数 (0〜100)
+    pub score: Option<u64>,
+    /// 買い時の点数の評価と根拠（一覧の表示用）
+    #[serde(skip)]
+    pub buy_score: Option<BuyScore>,
+}
+
+impl ListRow {
+    /// 製品の価格履歴から1行分のデータを生成する。価格履歴が空のときは None を返す。
+    pub fn new(
+        index: usize,
+        product: &ProductHistory,
+        profile: &PaymentProfile,
+        now: DateTime<Utc>,
+    ) -> Option<Self> {
+        let last = product.history.last()?;
+        let (high, low, _) = product.high_low_now();
+        // 価格が変化した最後の位置
+        let changed_at = product
+            .history
+            .windows(2)
+            .rposition(|v| v[0].price != v[1].price)
+            .map(|i| i + 1);
+        // 最後の価格の取得で履歴が追加されたときだけ、直前の価格との差を変化とする。
+        let n = product.history.len();
+        let checked = product.last_checked.unwrap_or(last.datetime);
+        let change = if n >= 2 && last.datetime == checked {
+            last.price as i64 - product.history[n - 2].price as i64
+        } else {
+            0
+        };
+        // 出力が安定するように、小数第2位までにする。
+        let discount = if high > 0 {
+            ((high - last.price) as f64 * 10000.0 / high as f64).round() / 100.0
+        } else {
+            0.0
+        };
+        let score = buy_score(product, profile, now);
+        Some(Self {
+            index,
+            id: product.id.clone(),
+            name: product.name.clone(),
+            custom_name: product.custom_name.clone(),
+            url: product.url.clone(),
+            maker: product.maker.clone(),
+            tags: product.tags.clone(),
+            note: product.note.clone(),
+            price: last.price,
+            point: last.point,
+            point_ratio: last.point_ratio,
+            status: last.status,
+            effective_price: last.effective_price(profile),
+            high,
+            low,
+            discount,
+            change,
+            last_change: changed_at.map(|i| product.history[i].datetime),
+            score: score.as_ref().map(|v| v.score),
+            buy_score: score,
+        })
+    }
+
+    /// 表示用の名称を返す。
+    pub fn display_name(&self) -> &str {
+        self.custom_name.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl Record for ListRow {
+    const FIELDS: &'static [&'static str] = &[
+        "index",
+        "id",
+        "name",
+        "custom_name",
+        "url",
+        "maker",
+        "tags",
+        "note",
+        "price",
+        "point",
+        "point_ratio",
+        "status",
+        "effective_price",
+        "high",
+        "low",
+        "discount",
+        "change",
+        "last_change",
+        "score",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.index.to_string(),
+            self.id.clone(),
+            self.name.clone(),
+            optional(&self.custom_name),
+            self.url.clone(),
+            self.maker.clone(),
+            self.tags.join(";"),
+            optional(&self.note),
+            self.price.to_string(),
+            self.point.to_string(),
+            self.point_ratio.to_string(),
+            self.status.key().to_string(),
+            self.effective_price.to_string(),
+            self.high.to_string(),
+            self.low.to_string(),
+            self.discount.to_string(),
+            self.change.to_string(),
+            optional(&self.last_change.as_ref().map(datetime_text)),
+            optional(&self.score),
+        ]
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 価格履歴の1件分のデータ
+#[derive(Debug, Serialize)]
+pub struct HistoryRow {
+    pub datetime: DateTime<Utc>,
+    pub price: u64,
+    pub point: u64,
+    pub point_ratio: u64,
+    pub status: StockStatus,
+    /// 使用中の支払い方法での実質価格（円）
+    pub effective_price: u64,
+    /// 直前の価格からの変化（円）
+    pub change: i64,
+}
+
+impl HistoryRow {
+    /// 価格履歴の全件分のデータを生成する。
+    pub fn from_history(history: &[OnePrice], profile: &PaymentProfile) -> Vec<Self> {
+        let mut prev: Option<u64> = None;
+        history
+            .iter()
+            .map(|p| {
+                let change = prev.map_or(0, |x| p.price as i64 - x as i64);
+                prev = Some(p.price);
+                Self {
+                    datetime: p.datetime,
+                    price: p.price,
+                    point: p.point,
+                    point_ratio: p.point_ratio,
+                    status: p.status,
+                    effective_price: p.effective_price(profile),
+                    change,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Record for HistoryRow {
+    const FIELDS: &'static [&'static str] = &[
+        "datetime",
+        "price",
+        "point",
+        "point_ratio",
+        "status",
+        "effective_price",
+        "change",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            datetime_text(&self.datetime),
+            self.price.to_string(),
+            self.point.to_string(),
+            self.point_ratio.to_string(),
+            self.status.key().to_string(),
+            self.effective_price.to_string(),
+            self.change.to_string(),
+        ]
+    }
+}
+
+/// 製品の詳細と全ての価格履歴 (`show` の JSON 出力)
+#[derive(Debug, Serialize)]
+pub struct ProductDetail {
+    pub id: String,
+    pub name: String,
+    pub custom_name: Option<String>,
+    pub url: String,
+    pub maker: String,
+    pub sku: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub archived: bool,
+    pub registered_at: Option<DateTime<Utc>>,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub history: Vec<HistoryRow>,
+}
+
+impl ProductDetail {
+    pub fn new(product: &ProductHistory, profile: &PaymentProfile) -> Self {
+        Self {
+            id: product.id.clone(),
+            name: product.name.clone(),
+            custom_name: product.custom_name.clone(),
+            url: product.url.clone(),
+            maker: product.maker.clone(),
+            sku: product.sku.clone(),
+            category: product.category.clone(),
+            tags: product.tags.clone(),
+            note: product.note.clone(),
+            archived: product.archived,
+            registered_at: product.registered_at,
+            last_checked: product.last_checked,
+            history: HistoryRow::from_history(&product.history, profile),
+        }
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 価格更新の結果の1件分のデータ
+#[derive(Debug, Serialize)]
+pub struct UpdateRow {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    /// `changed`（価格が変化した）、`unchanged`、`error`（取得に失敗した）のいずれか
+    pub result: &'static str,
+    /// 更新前の価格（円）
+    pub old_price: Option<u64>,
+    /// 更新後の価格（円）
+    pub price: Option<u64>,
+    /// 価格の変化（円）
+    pub change: Option<i64>,
+    /// 価格の変化率（%）
+    pub change_ratio: Option<f64>,
+    /// 最安値を更新した
+    pub new_low: bool,
+    /// 最高値を更新した
+    pub new_high: bool,
+    pub old_status: Option<StockStatus>,
+    pub status: Option<StockStatus>,
+    pub old_point_ratio: Option<u64>,
+    pub point_ratio: Option<u64>,
+    /// 今回の更新で条件を満たしたアラートの説明
+    pub alerts: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl UpdateRow {
+    pub fn new(item: &UpdateItem) -> Self {
+        let result = if item.error.is_some() {
+            "error"
+        } else if item.is_changed() {
+            "changed"
+        } else {
+            "unchanged"
+        };
+        Self {
+            id: item.id.clone(),
+            name: item.name.clone(),
+            url: item.url.clone(),
+            result,
+            old_price: item.old_price(),
+            price: item.price(),
+            change: item.change(),
+            change_ratio: item.change_ratio().map(|v| (v * 100.0).round() / 100.0),
+            new_low: item.is_new_low(),
+            new_high: item.is_new_high(),
+            old_status: item.before.as_ref().map(|v| v.status),
+            status: item.after.as_ref().map(|v| v.status),
+            old_point_ratio: item.before.as_ref().map(|v| v.point_ratio),
+            point_ratio: item.after.as_ref().map(|v| v.point_ratio),
+            alerts: item.alerts.iter().map(|v| v.rule.clone()).collect(),
+            error: item.error.clone(),
+        }
+    }
+}
+
+impl Record for UpdateRow {
+    const FIELDS: &'static [&'static str] = &[
+        "id",
+        "name",
+        "url",
+        "result",
+        "old_price",
+        "price",
+        "change",
+        "change_ratio",
+        "new_low",
+        "new_high",
+        "old_status",
+        "status",
+        "old_point_ratio",
+        "point_ratio",
+        "alerts",
+        "error",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.name.clone(),
+            self.url.clone(),
+            self.result.to_string(),
+            optional(&self.old_price),
+            optional(&self.price),
+            optional(&self.change),
+            optional(&self.change_ratio),
+            self.new_low.to_string(),
+            self.new_high.to_string(),
+            optional(&self.old_status.map(|v| v.key())),
+            optional(&self.status.map(|v| v.key())),
+            optional(&self.old_point_ratio),
+            optional(&self.point_ratio),
+            self.alerts.join(";"),
+            optional(&self.error),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price};
+
+    fn row() -> ListRow {
+        ListRow {
+            index: 1,
+            id: "100".to_string(),
+            name: "WH-1000XM5, Black".to_string(),
+            maker: "Sony".to_string(),
+            tags: vec!["audio".to_string(), "gift".to_string()],
+            note: Some("say \"hi\"".to_string()),
+            price: 49500,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_test() {
+        let rows = vec![row()];
+
+        let csv = render(&rows, OutputFormat::Csv);
+        let lines: Vec<_> = csv.lines().collect();
+        assert!(lines[0].starts_with("index,id,name,custom_name,url,maker,tags,note,price,"));
+        assert!(lines[1]
+            .starts_with(r#"1,100,"WH-1000XM5, Black",,,Sony,audio;gift,"say ""hi""",49500,"#));
+
+        let tsv = render(&rows, OutputFormat::Tsv);
+        assert_eq!(
+            ListRow::FIELDS.len(),
+            tsv.lines().nth(1).unwrap().split('\t').count()
+        );
+
+        let md = render(&rows, OutputFormat::Markdown);
+        let lines: Vec<_> = md.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[1].starts_with("|---|---|"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&rows, OutputFormat::Json)).unwrap();
+        assert_eq!(49500, json[0]["price"]);
+    }
+
+    #[test]
+    fn fields_test() {
+        // 列の名称と値の数が一致していること
+        assert_eq!(ListRow::FIELDS.len(), row().values().len());
+        let history = vec![one_price(1000, 1)];
+        let rows = HistoryRow::from_history(&history, &PaymentProfile::default());
+        assert_eq!(HistoryRow::FIELDS.len(), rows[0].values().len());
+        let row = UpdateRow::new(&UpdateItem::default());
+        assert_eq!(UpdateRow::FIELDS.len(), row.values().len());
+    }
+
+    #[test]
+    fn list_row_change_test() {
+        let mut product = ProductHistory {
+            history: vec![one_price(1000, 1), one_price(800, 2)],
+            last_checked: Some(day(2)),
+            ..Default::default()
+        };
+        let profile = PaymentProfile::default();
+
+        // 最後の取得で値下がりした。
+        let row = ListRow::new(1, &product, &profile, day(3)).unwrap();
+        assert_eq!(-200, row.change);
+        assert_eq!(row.score, row.buy_score.as_ref().map(|v| v.score));
+
+        // その後の取得では価格が変わっていない。
+        product.last_checked = Some(day(3));
+        let row = ListRow::new(1, &product, &profile, day(3)).unwrap();
+        assert_eq!(0, row.change);
+        assert_eq!(Some(day(2)), row.last_change);
+    }
+}
@@ -0,0 +1,556 @@
+//! 一覧の並べ替えと絞り込み。
+//!
+//! 絞り込みの条件は `maker = Sony and price < 30000 and status = in_stock` のような式で指定する。
+//!
+//! - 比較：`=`, `!=`, `<`, `<=`, `>`, `>=`, `~`（文字列を含む）
+//! - 論理演算：`and`, `or`, `not`, 括弧
+//! - 空白を含む値は引用符で囲む。
+
+use serde::{Deserialize, Serialize};
+
+use crate::command::suggest;
+use crate::output::ListRow;
+use crate::types::StockStatus;
+
+/// 一覧の表示条件。保存した表示条件（ビュー）にも使う。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ListQuery {
+    /// 絞り込みの条件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// 並べ替えの基準
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// 逆順に並べる
+    #[serde(default)]
+    pub reverse: bool,
+    /// 表示するタグ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+impl ListQuery {
+    /// 保存した表示条件に、コマンドで指定された条件を重ねる。
+    /// 絞り込みの条件は両方を満たすものとし、それ以外はコマンドでの指定を優先する。
+    pub fn merge(&self, other: &ListQuery) -> ListQuery {
+        let filter = match (&self.filter, &other.filter) {
+            (Some(a), Some(b)) => Some(format!("({}) and ({})", a, b)),
+            (a, b) => b.clone().or(a.clone()),
+        };
+        ListQuery {
+            filter,
+            sort: other.sort.clone().or(self.sort.clone()),
+            reverse: self.reverse || other.reverse,
+            tag: other.tag.clone().or(self.tag.clone()),
+        }
+    }
+
+    /// 条件を `list` コマンドの引数の形で返す。
+    pub fn describe(&self) -> String {
+        let mut parts = vec![];
+        if let Some(x) = &self.sort {
+            parts.push(format!("--sort {}", x));
+        }
+        if self.reverse {
+            parts.push("--reverse".to_string());
+        }
+        if let Some(x) = &self.tag {
+            parts.push(format!("--tag {}", x));
+        }
+        if let Some(x) = &self.filter {
+            parts.push(format!("'{}'", x));
+        }
+        parts.join(" ")
+    }
+}
+
+/// 並べ替えの基準として指定できる名称
+pub const SORT_KEYS: &[&str] = &[
+    "name", "maker", "price", "discount", "change", "changed", "score",
+];
+
+/// 並べ替えの基準
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// 名称順
+    Name,
+    /// メーカ順
+    Maker,
+    /// 現在価格が安い順
+    Price,
+    /// 最高値からの値下がり率が大きい順
+    Discount,
+    /// 前回の価格の取得での値下がり幅が大きい順
+    Change,
+    /// 最後に価格が変化した日時が新しい順
+    Changed,
+    /// 買い時の点数が高い順
+    Score,
+}
+
+impl SortKey {
+    pub fn from_string(string: &str) -> Option<SortKey> {
+        let key = match string {
+            "name" => SortKey::Name,
+            "maker" => SortKey::Maker,
+            "price" => SortKey::Price,
+            "discount" => SortKey::Discount,
+            "change" => SortKey::Change,
+            "changed" => SortKey::Changed,
+            "score" => SortKey::Score,
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+/// 一覧を並べ替える。`reverse` のときは逆順にする。
+/// 同じ順位のものは、一覧での番号順にする。
+pub fn sort_rows<T>(rows: &mut [(ListRow, T)], key: SortKey, reverse: bool) {
+    rows.sort_by(|(a, _), (b, _)| {
+        let ordering = match key {
+            SortKey::Name => a.display_name().cmp(b.display_name()),
+            SortKey::Maker => a.maker.cmp(&b.maker),
+            SortKey::Price => a.price.cmp(&b.price),
+            SortKey::Discount => b.discount.total_cmp(&a.discount),
+            SortKey::Change => a.change.cmp(&b.change),
+            SortKey::Changed => b.last_change.cmp(&a.last_change),
+            SortKey::Score => b.score.cmp(&a.score),
+        };
+        let ordering = if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        ordering.then(a.index.cmp(&b.index))
+    });
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 絞り込みに使える項目
+const FIELDS: &[&str] = &[
+    "name",
+    "maker",
+    "id",
+    "tag",
+    "status",
+    "price",
+    "effective",
+    "point",
+    "point_ratio",
+    "high",
+    "low",
+    "discount",
+    "change",
+    "score",
+];
+
+/// 比較演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+/// 絞り込みの式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare {
+        field: &'static str,
+        op: Op,
+        value: String,
+    },
+}
+
+/// 式の解析時のエラー
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+}
+
+impl std::error::Error for QueryError {}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "絞り込みの条件が正しくありません：{}", self.message)
+    }
+}
+
+fn error<T>(message: String) -> Result<T, QueryError> {
+    Err(QueryError { message })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    LParen,
+    RParen,
+}
+
+/// 式を単語に分割する。
+fn lex(input: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let eq = chars.peek() == Some(&'=');
+                let op = match (c, eq) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', true) => Op::Le,
+                    ('<', false) => Op::Lt,
+                    ('>', true) => Op::Ge,
+                    ('>', false) => Op::Gt,
+                    ('~', _) => Op::Contains,
+                    _ => return error("`!` の後には `=` が必要です".to_string()),
+                };
+                // `==` も `=` として扱う。
+                if eq && c != '~' {
+                    chars.next();
+                }
+                tokens.push(Token::Op(op));
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(x) if x == c => break,
+                        Some(x) => s.push(x),
+                        None => return error("引用符が閉じられていません".to_string()),
+                    }
+                }
+                tokens.push(Token::Word(s));
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&x) = chars.peek() {
+                    if x.is_whitespace() || "()=!<>~\"'".contains(x) {
+                        break;
+                    }
+                    s.push(x);
+                    chars.next();
+                }
+                tokens.push(Token::Word(s));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// 絞り込みの式を解析する。
+pub fn parse_query(input: &str) -> Result<Expr, QueryError> {
+    let tokens = lex(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    if let Some(t) = parser.tokens.get(parser.pos) {
+        return error(format!("`{}` の位置が正しくありません", token_text(t)));
+    }
+    Ok(expr)
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Word(s) => s.clone(),
+        Token::Op(_) => "比較演算子".to_string(),
+        Token::LParen => "(".to_string(),
+        Token::RParen => ")".to_string(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            let right = self.and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.unary()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            let right = self.unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.tokens.get(self.pos) == Some(&Token::LParen) {
+            self.pos += 1;
+            let expr = self.or()?;
+            if self.tokens.get(self.pos) != Some(&Token::RParen) {
+                return error("括弧が閉じられていません".to_string());
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr, QueryError> {
+        let field = match self.tokens.get(self.pos) {
+            Some(Token::Word(w)) => w.to_lowercase(),
+            Some(t) => return error(format!("`{}` の前に項目名が必要です", token_text(t))),
+            None => return error("条件が途中で終わっています".to_string()),
+        };
+        let Some(&field) = FIELDS.iter().find(|&&v| v == field) else {
+            let mut message = format!("不明な項目 `{}` です", field);
+            if let Some(s) = suggest(&field, FIELDS.iter().copied()) {
+                message = format!("{}。もしかして: `{}`", message, s);
+            }
+            return error(message);
+        };
+        let op = match self.tokens.get(self.pos + 1) {
+            Some(Token::Op(op)) => *op,
+            _ => return error(format!("`{}` の後に比較演算子が必要です", field)),
+        };
+        let value = match self.tokens.get(self.pos + 2) {
+            Some(Token::Word(w)) => w.clone(),
+            _ => return error(format!("`{}` と比較する値が必要です", field)),
+        };
+        self.pos += 3;
+
+        if is_numeric(field) {
+            if value.parse::<f64>().is_err() {
+                return error(format!("`{}` は数値と比較してください", field));
+            }
+            if op == Op::Contains {
+                return error(format!("`{}` には `~` を使えません", field));
+            }
+        } else {
+            if !matches!(op, Op::Eq | Op::Ne | Op::Contains) {
+                return error(format!("`{}` には `=`, `!=`, `~` のみ使えます", field));
+            }
+            if field == "status" && parse_status(&value).is_none() {
+                return error(format!(
+                    "在庫状況 `{}` は不明です（in_stock, sufficient, limited, back_order のいずれか）",
+                    value
+                ));
+            }
+        }
+        Ok(Expr::Compare { field, op, value })
+    }
+}
+
+fn is_numeric(field: &str) -> bool {
+    !matches!(field, "name" | "maker" | "id" | "tag" | "status")
+}
+
+/// 在庫状況の指定を、該当する在庫状況の一覧に変換する。
+fn parse_status(value: &str) -> Option<Vec<StockStatus>> {
+    let array = match value.to_lowercase().as_str() {
+        "in_stock" | "available" => vec![StockStatus::Sufficient, StockStatus::Limited],
+        "sufficient" | "在庫あり" => vec![StockStatus::Sufficient],
+        "limited" | "在庫僅少" => vec![StockStatus::Limited],
+        "back_order" | "backorder" | "out_of_stock" | "お取り寄せ" => {
+            vec![StockStatus::BackOrder]
+        }
+        "unknown" | "不明" => vec![StockStatus::Unknown],
+        _ => return None,
+    };
+    Some(array)
+}
+
+impl Expr {
+    /// 一覧の1行分のデータが条件に合うかどうかを返す。
+    pub fn matches(&self, row: &ListRow) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(row) && b.matches(row),
+            Expr::Or(a, b) => a.matches(row) || b.matches(row),
+            Expr::Not(a) => !a.matches(row),
+            Expr::Compare { field, op, value } => compare(row, field, *op, value),
+        }
+    }
+}
+
+fn compare(row: &ListRow, field: &str, op: Op, value: &str) -> bool {
+    let text_match = |target: &str| {
+        let target = target.to_lowercase();
+        let value = value.to_lowercase();
+        match op {
+            Op::Contains => target.contains(&value),
+            _ => target == value,
+        }
+    };
+    let positive = match field {
+        "name" => text_match(row.display_name()) || text_match(&row.name),
+        "maker" => text_match(&row.maker),
+        "id" => text_match(&row.id),
+        "tag" => row.tags.iter().any(|v| text_match(v)),
+        "status" => parse_status(value)
+            .unwrap_or_default()
+            .contains(&row.status),
+        _ => {
+            let target = match field {
+                "price" => row.price as f64,
+                "effective" => row.effective_price as f64,
+                "point" => row.point as f64,
+                "point_ratio" => row.point_ratio as f64,
+                "high" => row.high as f64,
+                "low" => row.low as f64,
+                "discount" => row.discount,
+                "change" => row.change as f64,
+                "score" => row.score.unwrap_or(0) as f64,
+                _ => unreachable!(),
+            };
+            let value = value.parse::<f64>().unwrap();
+            return match op {
+                Op::Eq => target == value,
+                Op::Ne => target != value,
+                Op::Lt => target < value,
+                Op::Le => target <= value,
+                Op::Gt => target > value,
+                Op::Ge => target >= value,
+                Op::Contains => false,
+            };
+        }
+    };
+    if op == Op::Ne {
+        !positive
+    } else {
+        positive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(index: usize, name: &str, maker: &str, price: u64, status: StockStatus) -> ListRow {
+        ListRow {
+            index,
+            name: name.to_string(),
+            maker: maker.to_string(),
+            price,
+            high: price,
+            low: price,
+            status,
+            tags: vec!["audio".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_query_test() {
+        let expr = parse_query("maker = Sony and price < 30000 or not status = in_stock").unwrap();
+        assert!(matches!(expr, Expr::Or(..)));
+        assert!(parse_query("(price >= 100 and price<=200)").is_ok());
+        assert!(parse_query(r#"name ~ "WH-1000""#).is_ok());
+
+        let e = parse_query("prise < 100").unwrap_err();
+        assert!(e.message.contains("`price`"));
+        assert!(parse_query("price < cheap").is_err());
+        assert!(parse_query("maker < Sony").is_err());
+        assert!(parse_query("status = soon").is_err());
+        assert!(parse_query("(price < 1").is_err());
+        assert!(parse_query("price < 1 maker").is_err());
+    }
+
+    #[test]
+    fn matches_test() {
+        let sony = row(1, "WH-1000XM5", "Sony", 49500, StockStatus::Sufficient);
+        let ogk = row(2, "SN-13L", "OGK KABUTO", 4860, StockStatus::BackOrder);
+
+        let expr = parse_query("maker = sony and price < 50000 and status = in_stock").unwrap();
+        assert!(expr.matches(&sony));
+        assert!(!expr.matches(&ogk));
+
+        let expr = parse_query("maker ~ ogk or price > 100000").unwrap();
+        assert!(!expr.matches(&sony));
+        assert!(expr.matches(&ogk));
+
+        let expr = parse_query("not status = in_stock").unwrap();
+        assert!(expr.matches(&ogk));
+
+        let expr = parse_query("tag = audio and maker != Sony").unwrap();
+        assert!(expr.matches(&ogk));
+        assert!(!expr.matches(&sony));
+    }
+
+    #[test]
+    fn merge_test() {
+        let view = ListQuery {
+            filter: Some("maker = Sony".to_string()),
+            sort: Some("price".to_string()),
+            ..Default::default()
+        };
+        let merged = view.merge(&ListQuery {
+            filter: Some("price < 100".to_string()),
+            reverse: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            Some("(maker = Sony) and (price < 100)".to_string()),
+            merged.filter
+        );
+        assert_eq!(Some("price".to_string()), merged.sort);
+        assert!(merged.reverse);
+        assert_eq!(view, view.merge(&ListQuery::default()));
+    }
+
+    #[test]
+    fn sort_rows_test() {
+        let mut rows = vec![
+            (row(1, "b", "Sony", 300, StockStatus::Sufficient), ()),
+            (row(2, "a", "Canon", 100, StockStatus::Sufficient), ()),
+            (row(3, "c", "Apple", 200, StockStatus::Sufficient), ()),
+        ];
+        sort_rows(&mut rows, SortKey::Price, false);
+        assert_eq!(
+            vec![2, 3, 1],
+            rows.iter().map(|v| v.0.index).collect::<Vec<_>>()
+        );
+        sort_rows(&mut rows, SortKey::Maker, true);
+        assert_eq!(
+            vec![1, 2, 3],
+            rows.iter().map(|v| v.0.index).collect::<Vec<_>>()
+        );
+        sort_rows(&mut rows, SortKey::Name, false);
+        assert_eq!(
+            vec![2, 1, 3],
+            rows.iter().map(|v| v.0.index).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! 対話モードの行入力。
+//!
+//! 矢印キーでの編集、履歴（`history.txt` に保存）、Tab キーでの補完に対応する。
+//! 補完の候補は、コマンド名・オプション・製品（番号・ID・表示名）・タグ等。
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+
+use crate::alert::RULE_KINDS;
+use crate::command::{
+    find_command, find_option, ArgKind, ArgSpec, CommandSpec, ValueKind, COMMANDS,
+};
+use crate::config::CONFIG;
+use crate::data::APP_STATE;
+use crate::util::set_current_dir_to_exe;
+
+/// 履歴のファイル名
+const HISTORY_FILE_NAME: &str = "history.txt";
+/// 履歴として保存する最大の件数
+const MAX_HISTORY: usize = 1000;
+/// 期間の補完候補
+const WINDOWS: &[&str] = &["7d", "30d", "90d", "365d", "all"];
+
+/// 対話モードの行エディタを生成し、保存されている履歴を読み込む。
+pub fn editor() -> rustyline::Result<Editor<ReplHelper, FileHistory>> {
+    let config = Config::builder()
+        .max_history_size(MAX_HISTORY)?
+        .history_ignore_dups(true)?
+        .history_ignore_space(true)
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor = Editor::with_config(config)?;
+    editor.set_helper(Some(ReplHelper));
+    set_current_dir_to_exe();
+    // 初回起動時は履歴ファイルがないため、エラーは無視する。
+    let _ = editor.load_history(HISTORY_FILE_NAME);
+    Ok(editor)
+}
+
+/// 入力された行を履歴に追加し、ファイルに保存する。
+pub fn add_history(editor: &mut Editor<ReplHelper, FileHistory>, line: &str) {
+    let _ = editor.add_history_entry(line);
+    set_current_dir_to_exe();
+    if let Err(e) = editor.save_history(HISTORY_FILE_NAME) {
+        eprintln!("履歴の保存時にエラーが発生しました({})", e);
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 補完に使うデータ
+#[derive(Debug, Default)]
+pub struct CompletionData {
+    /// 製品の一覧での番号・ID・表示名
+    pub products: Vec<(usize, String, String)>,
+    pub tags: Vec<String>,
+    pub profiles: Vec<String>,
+    pub views: Vec<String>,
+    pub rules: Vec<String>,
+    pub webhooks: Vec<String>,
+    pub hooks: Vec<String>,
+    pub feeds: Vec<String>,
+}
+
+impl CompletionData {
+    /// 現在の登録内容と設定から、補完に使うデータを生成する。
+    fn current() -> Self {
+        let config = CONFIG.lock().unwrap();
+        let app_state = APP_STATE.lock().unwrap();
+        let mut tags: Vec<String> = app_state
+            .histories
+            .iter()
+            .flat_map(|v| v.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        Self {
+            products: (1..)
+                .zip(app_state.histories.iter())
+                .map(|(i, v)| (i, v.id.clone(), v.display_name().to_string()))
+                .collect(),
+            tags,
+            profiles: config.profiles().into_iter().map(|v| v.name).collect(),
+            views: config.views.iter().map(|v| v.name.clone()).collect(),
+            rules: config.rules.iter().map(|v| v.name.clone()).collect(),
+            webhooks: config.webhooks.iter().map(|v| v.name.clone()).collect(),
+            hooks: config.hooks.iter().map(|v| v.name.clone()).collect(),
+            feeds: config.feeds.iter().map(|v| v.name.clone()).collect(),
+        }
+    }
+}
+
+/// 入力中の行の補完候補を返す。返り値は、置き換える単語の開始位置と候補の一覧。
+pub fn complete(line: &str, pos: usize, data: &CompletionData) -> (usize, Vec<Pair>) {
+    let line = &line[..pos];
+    // 全角空白等の複数バイトの空白の後ろから始める。
+    let start = line
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let word = &line[start..];
+    let words: Vec<&str> = line[..start].split_whitespace().collect();
+
+    let Some(&name) = words.first() else {
+        let names = COMMANDS.iter().map(|v| v.name.to_string());
+        return (start, plain(names, word));
+    };
+    let Some(spec) = find_command(name) else {
+        return (start, vec![]);
+    };
+
+    // オプションの名称
+    if word.starts_with('-') {
+        let options = spec
+            .args
+            .iter()
+            .filter(|v| !v.kind.is_positional())
+            .map(|v| format!("--{}", v.name))
+            .chain(std::iter::once("--help".to_string()));
+        return (start, plain(options, word));
+    }
+
+    // 直前の単語がオプションのときは、その値。それ以外は位置引数。
+    let option_value = words
+        .last()
+        .filter(|v| v.starts_with('-') && !v.contains('='))
+        .and_then(|v| find_option(spec, v))
+        .and_then(|v| match v.kind {
+            ArgKind::Option { value } => Some((v.name, value)),
+            _ => None,
+        });
+    let (arg_name, value) = match option_value {
+        Some(x) => x,
+        None => {
+            let Some(arg) = positional_at(spec, &words[1..]) else {
+                return (start, vec![]);
+            };
+            match arg.kind {
+                ArgKind::Positional { value, .. } | ArgKind::Rest { value, .. } => {
+                    (arg.name, value)
+                }
+                _ => return (start, vec![]),
+            }
+        }
+    };
+
+    let candidates = match (spec.name, arg_name, value) {
+        (_, _, ValueKind::Product) => products(data, word),
+        (_, _, ValueKind::Choice(choices)) => plain(choices.iter().map(|v| v.to_string()), word),
+        (_, _, ValueKind::Window) => plain(WINDOWS.iter().map(|v| v.to_string()), word),
+        ("help", "command", _) => plain(COMMANDS.iter().map(|v| v.name.to_string()), word),
+        ("rules", "name", _) | ("backtest", "rule", _) => plain(data.rules.iter().cloned(), word),
+        ("webhooks", "name", _) => plain(data.webhooks.iter().cloned(), word),
+        ("hooks", "name", _) => plain(data.hooks.iter().cloned(), word),
+        ("feeds", "name", _) => plain(data.feeds.iter().cloned(), word),
+        ("alert", "rule", _) => plain(RULE_KINDS.iter().map(|v| v.to_string()), word),
+        ("profile", "name", _) => plain(data.profiles.iter().cloned(), word),
+        (_, "tag", _) => plain(data.tags.iter().cloned(), word),
+        (_, "view", _) | ("views", "delete", _) => plain(data.views.iter().cloned(), word),
+        _ => vec![],
+    };
+    (start, candidates)
+}
+
+/// 入力済みの単語から、次に入力する位置引数の定義を返す。
+fn positional_at(spec: &'static CommandSpec, words: &[&str]) -> Option<&'static ArgSpec> {
+    let mut count = 0;
+    let mut iter = words.iter();
+    while let Some(word) = iter.next() {
+        if word.starts_with('-') && word.len() > 1 {
+            // 値を取るオプションは、次の単語も読み飛ばす。
+            let takes_value = find_option(spec, word)
+                .is_some_and(|v| matches!(v.kind, ArgKind::Option { .. }) && !word.contains('='));
+            if takes_value {
+                iter.next();
+            }
+            continue;
+        }
+        count += 1;
+    }
+    let positionals: Vec<_> = spec
+        .args
+        .iter()
+        .filter(|v| v.kind.is_positional())
+        .collect();
+    match positionals.get(count) {
+        Some(x) => Some(x),
+        // 最後が Rest のときは、以降の全ての単語が該当する。
+        None => positionals
+            .last()
+            .filter(|v| matches!(v.kind, ArgKind::Rest { .. }))
+            .copied(),
+    }
+}
+
+/// 前方一致する候補を返す。
+fn plain<I: Iterator<Item = String>>(candidates: I, word: &str) -> Vec<Pair> {
+    candidates
+        .filter(|v| v.starts_with(word))
+        .map(|v| Pair {
+            display: v.clone(),
+            replacement: quote(&v),
+        })
+        .collect()
+}
+
+/// 製品の候補を返す。番号・IDは前方一致、表示名は部分一致（大文字小文字を区別しない）で探す。
+/// 表示名で一致したときは、一覧での番号に置き換える。
+fn products(data: &CompletionData, word: &str) -> Vec<Pair> {
+    let lower = word.to_lowercase();
+    data.products
+        .iter()
+        .filter_map(|(index, id, name)| {
+            let replacement = if index.to_string().starts_with(word) {
+                index.to_string()
+            } else if id.starts_with(word) {
+                id.clone()
+            } else if !word.is_empty() && name.to_lowercase().contains(&lower) {
+                index.to_string()
+            } else {
+                return None;
+            };
+            Some(Pair {
+                display: format!("{}: {} ({})", index, name, id),
+                replacement,
+            })
+        })
+        .collect()
+}
+
+/// 空白を含むときは引用符で囲む。
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 行エディタの補完等の設定
+pub struct ReplHelper;
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(complete(line, pos, &CompletionData::current()))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> CompletionData {
+        CompletionData {
+            products: vec![
+                (
+                    1,
+                    "100000001007414874".to_string(),
+                    "OGK ヘルメット".to_string(),
+                ),
+                (
+                    2,
+                    "100000001007000001".to_string(),
+                    "Sony WH-1000XM5".to_string(),
+                ),
+            ],
+            tags: vec![
+                "audio".to_string(),
+                "bike".to_string(),
+                "gift idea".to_string(),
+            ],
+            profiles: vec!["default".to_string(), "gold".to_string()],
+            views: vec!["cheap".to_string()],
+            rules: vec!["sale".to_string()],
+            webhooks: vec!["ops".to_string()],
+            hooks: vec!["log".to_string()],
+            feeds: vec!["all".to_string()],
+        }
+    }
+
+    fn replacements(line: &str) -> (usize, Vec<String>) {
+        let (start, pairs) = complete(line, line.len(), &data());
+        (start, pairs.into_iter().map(|v| v.replacement).collect())
+    }
+
+    #[test]
+    fn complete_command_test() {
+        assert_eq!(
+            (0, vec!["stats".to_string(), "stock".to_string()]),
+            replacements("st")
+        );
+        assert!(replacements("").1.contains(&"list".to_string()));
+        assert_eq!(vec!["--sort", "--save"], replacements("list --s").1);
+        assert_eq!(vec!["show"], replacements("help sh").1);
+    }
+
+    #[test]
+    fn complete_value_test() {
+        // 製品は番号・ID・表示名で補完する。
+        assert_eq!((5, vec!["2".to_string()]), replacements("show 2"));
+        assert_eq!(vec!["2"], replacements("chart sony").1);
+        assert_eq!(
+            vec!["100000001007000001"],
+            replacements("stats 1000000010070000").1
+        );
+        // 期間
+        assert_eq!(vec!["30d"], replacements("stats 1 30").1);
+        // タグ（空白を含むときは引用符で囲む）
+        assert_eq!(vec!["\"gift idea\""], replacements("tag 1 audio g").1);
+        assert_eq!(vec!["audio"], replacements("list --tag a").1);
+        // 選択肢
+        assert_eq!(vec!["csv"], replacements("list -o c").1);
+        assert_eq!(vec!["gold"], replacements("profile g").1);
+        assert_eq!(vec!["cheap"], replacements("list -v ").1);
+        assert_eq!(vec!["sale"], replacements("rules test s").1);
+        assert_eq!(vec!["ops"], replacements("webhooks test o").1);
+        assert_eq!(vec!["log"], replacements("hooks remove l").1);
+        assert_eq!(vec!["all"], replacements("feeds remove a").1);
+        assert_eq!(
+            vec!["below-high", "back-in-stock"],
+            replacements("alert 1 b").1
+        );
+        // 値を取らない位置
+        assert!(replacements("add ht").1.is_empty());
+    }
+
+    #[test]
+    fn complete_after_wide_space_test() {
+        // 全角空白の後ろでも、文字の境界から補完する。
+        let (start, candidates) = replacements("show　");
+        assert_eq!("show　".len(), start);
+        assert_eq!(vec!["1", "2"], candidates);
+    }
+}
@@ -0,0 +1,193 @@
+//! 価格更新の結果の報告。
+//!
+//! 端末に出力するときは、値下がりを緑、値上がりと失敗を赤で色付けする。
+
+use std::io::IsTerminal;
+
+use crate::data::{UpdateItem, UpdateResult};
+use crate::util::{omitted_string, signed_yen, yen};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// 標準出力に色を付けるかどうかを返す。
+/// 端末でないとき、または環境変数 `NO_COLOR` が設定されているときは色を付けない。
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+fn paint(text: &str, style: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// 価格更新の結果を、人が読むための行の配列に変換する。
+/// 先頭の行は件数の要約で、その後に変化があった製品ごとに1行ずつ続く。
+pub fn render_report(result: &UpdateResult, color: bool) -> Vec<String> {
+    let down = result
+        .items
+        .iter()
+        .filter(|v| v.change().is_some_and(|x| x < 0))
+        .count();
+    let up = result
+        .items
+        .iter()
+        .filter(|v| v.change().is_some_and(|x| x > 0))
+        .count();
+    let errors = result.errors().count();
+    let alerts: usize = result.items.iter().map(|v| v.alerts.len()).sum();
+
+    let mut summary = format!(
+        "{}件を更新しました：値下がり {}件、値上がり {}件、失敗 {}件",
+        result.items.len(),
+        paint(&down.to_string(), GREEN, color && down > 0),
+        paint(&up.to_string(), RED, color && up > 0),
+        paint(&errors.to_string(), RED, color && errors > 0),
+    );
+    if alerts > 0 {
+        summary += &format!("、アラート {}件", paint(&alerts.to_string(), BOLD, color));
+    }
+    let mut lines = vec![summary];
+    lines.extend(
+        result
+            .items
+            .iter()
+            .filter(|v| v.is_notable())
+            .map(|v| render_item(v, color)),
+    );
+    if lines.len() == 1 {
+        lines.push("価格・在庫状況・還元率に変化はありませんでした。".to_string());
+    }
+    lines
+}
+
+/// 1つの製品の結果を1行の文字列に変換する。
+pub fn render_item(item: &UpdateItem, color: bool) -> String {
+    let name = omitted_string(&item.name);
+    if let Some(e) = &item.error {
+        return format!("{} {}  {}", paint("×", RED, color), name, e);
+    }
+
+    let mut parts = vec![];
+    let mark = match item.change() {
+        Some(x) if x < 0 => paint("↓", GREEN, color),
+        Some(x) if x > 0 => paint("↑", RED, color),
+        _ => "・".to_string(),
+    };
+    if let (true, Some(old), Some(new), Some(change)) = (
+        item.is_changed(),
+        item.old_price(),
+        item.price(),
+        item.change(),
+    ) {
+        let ratio = item.change_ratio().unwrap_or(0.0);
+        let style = if change < 0 { GREEN } else { RED };
+        parts.push(format!(
+            "{} → {} ({}, {})",
+            yen(old),
+            yen(new),
+            paint(&signed_yen(change), style, color),
+            paint(&format!("{:+.1}%", ratio), style, color)
+        ));
+    }
+    if item.is_new_low() {
+        parts.push(paint("最安値を更新", &format!("{}{}", BOLD, GREEN), color));
+    }
+    if item.is_new_high() {
+        parts.push(paint("最高値を更新", &format!("{}{}", BOLD, RED), color));
+    }
+    if let Some((before, after)) = item.status_change() {
+        parts.push(paint(
+            &format!("在庫: {} → {}", before, after),
+            YELLOW,
+            color,
+        ));
+    }
+    if let Some((before, after)) = item.point_ratio_change() {
+        parts.push(paint(
+            &format!("還元率: {}% → {}%", before, after),
+            YELLOW,
+            color,
+        ));
+    }
+    for alert in item.alerts.iter() {
+        parts.push(paint(
+            &format!("アラート: {}", alert.rule),
+            &format!("{}{}", BOLD, YELLOW),
+            color,
+        ));
+    }
+    format!("{} {}  {}", mark, name, parts.join("  "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::FiredAlert;
+    use crate::fixtures::{one_price_with, update_item};
+    use crate::types::StockStatus;
+
+    #[test]
+    fn render_report_test() {
+        let mut result = UpdateResult {
+            items: vec![
+                update_item(
+                    "A",
+                    one_price_with(5000, 10, StockStatus::Sufficient, 1),
+                    Some(one_price_with(4500, 10, StockStatus::Sufficient, 1)),
+                ),
+                update_item(
+                    "B",
+                    one_price_with(1000, 10, StockStatus::BackOrder, 1),
+                    Some(one_price_with(1000, 5, StockStatus::Sufficient, 1)),
+                ),
+                update_item(
+                    "C",
+                    one_price_with(1000, 10, StockStatus::Sufficient, 1),
+                    None,
+                ),
+                update_item(
+                    "D",
+                    one_price_with(1000, 10, StockStatus::Sufficient, 1),
+                    Some(one_price_with(1000, 10, StockStatus::Sufficient, 1)),
+                ),
+            ],
+        };
+        result.items[2].error = Some("データを取得できませんでした".to_string());
+
+        let lines = render_report(&result, false);
+        assert_eq!(4, lines.len());
+        assert_eq!(
+            "4件を更新しました：値下がり 1件、値上がり 0件、失敗 1件",
+            lines[0]
+        );
+        assert_eq!(
+            "↓ A  ￥5,000 → ￥4,500 (-￥500, -10.0%)  最安値を更新",
+            lines[1]
+        );
+        assert_eq!(
+            "・ B  在庫: お取り寄せ → 在庫あり  還元率: 10% → 5%",
+            lines[2]
+        );
+        assert_eq!("× C  データを取得できませんでした", lines[3]);
+
+        // 色を付けるときは、エスケープシーケンスを含む。
+        let lines = render_report(&result, true);
+        assert!(lines[1].contains(GREEN));
+
+        // アラートは件数を要約に加え、変化がなくても報告する。
+        result.items[3].alerts = vec![FiredAlert {
+            rule: "在庫あり".to_string(),
+            price: 1000,
+        }];
+        let lines = render_report(&result, false);
+        assert!(lines[0].ends_with("、アラート 1件"));
+        assert_eq!("・ D  アラート: 在庫あり", lines[4]);
+    }
+}
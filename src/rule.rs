@@ -0,0 +1,1110 @@
+//! アラートのルール。
+//!
+//! ルールの条件は `effective < 0.85 * median(effective, 90d) and in_stock` のような式で指定し、
+//! 製品の価格履歴と最新の価格データに対して評価する。
+//!
+//! - 数値の項目：`price`, `effective`, `point`, `point_ratio`, `high`, `low`, `discount`
+//! - 真偽値の項目：`in_stock`, `new_low`, `new_high`
+//! - 関数：`prev(項目)`（前回の値）, `delta(項目)`（前回からの変化）,
+//!   `median` / `avg` / `min` / `max(項目, 期間)`（期間内の時間加重の集計）
+//! - 演算：`+`, `-`, `*`（`×`）, `/`、比較：`=`, `!=`, `<`, `<=`, `>`, `>=`（`≠`, `≤`, `≥`）、
+//!   論理演算：`and`, `or`, `not`, 括弧
+//!
+//! ルールはタグまたは製品を指定して適用する（どちらも指定しないときは全製品）。
+//! アラートと同じく、条件を満たさない状態から満たす状態に変わったときに知らせる。
+//! 前回知らせてからクールダウンの期間が過ぎていないとき、または知らせない時間帯のときは、
+//! 条件を満たしたままその期間が過ぎるまで知らせるのを保留する。
+
+use chrono::serde::ts_seconds_option;
+use chrono::{DateTime, Duration, FixedOffset, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::alert::FiredAlert;
+use crate::command::suggest;
+use crate::config::PaymentProfile;
+use crate::data::{OnePrice, ProductHistory};
+use crate::stats::{segments_by, weighted_average, weighted_median, Window};
+
+/// 名前を付けて保存したルール
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    /// 条件式
+    pub expr: String,
+    /// 適用するタグ。タグと製品がどちらも空のときは全製品に適用する。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// 適用する製品のID
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub products: Vec<String>,
+    /// 一度知らせてから、再び知らせるまでの最短の間隔（時間）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_hours: Option<i64>,
+    /// 知らせない時間帯（日本時間）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Rule {
+    /// 保存せずにその場で使う、全製品に適用するルールを生成する。
+    pub fn ad_hoc(expr: &str) -> Self {
+        Self {
+            name: "test".to_string(),
+            expr: expr.to_string(),
+            tags: vec![],
+            products: vec![],
+            cooldown_hours: None,
+            quiet_hours: None,
+        }
+    }
+
+    /// 製品にこのルールを適用するかどうかを返す。
+    pub fn applies_to(&self, product: &ProductHistory) -> bool {
+        (self.tags.is_empty() && self.products.is_empty())
+            || self.tags.iter().any(|v| product.has_tag(v))
+            || self.products.contains(&product.id)
+    }
+
+    /// 適用範囲と通知の設定を `rules add` のオプションの形で返す。
+    pub fn describe_options(&self) -> String {
+        let mut parts = vec![];
+        for tag in self.tags.iter() {
+            parts.push(format!("--tag {}", tag));
+        }
+        for id in self.products.iter() {
+            parts.push(format!("--product {}", id));
+        }
+        if let Some(x) = self.cooldown_hours {
+            parts.push(format!("--cooldown {}h", x));
+        }
+        if let Some(x) = self.quiet_hours {
+            parts.push(format!("--quiet {}", x));
+        }
+        parts.join(" ")
+    }
+
+    /// 前回知らせた日時から、クールダウンの期間中かどうかを返す。
+    fn in_cooldown(&self, fired_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        match (self.cooldown_hours, fired_at) {
+            (Some(hours), Some(at)) => now < at + Duration::hours(hours),
+            _ => false,
+        }
+    }
+
+    /// クールダウンと知らせない時間帯による保留が解ける日時を返す。保留していないときは `now` を返す。
+    pub fn release_time(
+        &self,
+        fired_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        let mut time = now;
+        if let (Some(hours), Some(at)) = (self.cooldown_hours, fired_at) {
+            time = time.max(at + Duration::hours(hours));
+        }
+        match self.quiet_hours {
+            Some(quiet) if quiet.contains(time) => quiet.end_after(time),
+            _ => time,
+        }
+    }
+
+    /// 知らせない時間帯かどうかを返す。
+    fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        self.quiet_hours.is_some_and(|v| v.contains(now))
+    }
+}
+
+/// クールダウンとして指定できる最長の期間（時間）
+const MAX_COOLDOWN_HOURS: i64 = 365 * 24;
+
+/// `12h` や `3d` のような表記のクールダウンの期間を、時間数に変換する。
+/// 1年より長い期間は受け付けない。
+pub fn parse_cooldown(string: &str) -> Option<i64> {
+    let (number, hours) = if let Some(x) = string.strip_suffix('h') {
+        (x, 1)
+    } else if let Some(x) = string.strip_suffix('d') {
+        (x, 24)
+    } else {
+        return None;
+    };
+    let n = number.parse::<i64>().ok().filter(|v| *v > 0)?;
+    n.checked_mul(hours).filter(|v| *v <= MAX_COOLDOWN_HOURS)
+}
+
+/// 知らせない時間帯（日本時間の時）。`start` 時から `end` 時の前までで、日付をまたいでもよい。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl QuietHours {
+    /// `22-7` のような表記から生成する。
+    pub fn from_string(string: &str) -> Option<QuietHours> {
+        let (start, end) = string.split_once('-')?;
+        let start = start.trim().parse::<u32>().ok()?;
+        let end = end.trim().parse::<u32>().ok()?;
+        let quiet = QuietHours { start, end };
+        quiet.is_valid().then_some(quiet)
+    }
+
+    /// 開始と終了が0〜23時の範囲で、異なる時かどうかを返す。
+    pub fn is_valid(&self) -> bool {
+        self.start < 24 && self.end < 24 && self.start != self.end
+    }
+
+    /// 指定した日時が、知らせない時間帯に含まれるかどうかを返す。
+    pub fn contains(&self, datetime: DateTime<Utc>) -> bool {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let hour = datetime.with_timezone(&jst).hour();
+        if self.start < self.end {
+            self.start <= hour && hour < self.end
+        } else {
+            self.start <= hour || hour < self.end
+        }
+    }
+    /// 指定した日時の後で、知らせない時間帯が終わる日時を返す。
+    pub fn end_after(&self, datetime: DateTime<Utc>) -> DateTime<Utc> {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let local = datetime.with_timezone(&jst);
+        let mut end = local
+            .date_naive()
+            .and_hms_opt(self.end, 0, 0)
+            .unwrap()
+            .and_local_timezone(jst)
+            .unwrap();
+        if end <= local {
+            end += Duration::days(1);
+        }
+        end.with_timezone(&Utc)
+    }
+}
+
+impl std::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 製品ごとのルールの通知状態
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleState {
+    /// ルールの名称
+    pub rule: String,
+    /// 通知済み（条件を満たしている間は再度通知しない）
+    #[serde(default)]
+    pub triggered: bool,
+    /// 最後に通知した日時
+    #[serde(default, with = "ts_seconds_option")]
+    pub fired_at: Option<DateTime<Utc>>,
+}
+
+/// ルールを評価した結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// 条件を満たさない
+    Idle,
+    /// 新たに条件を満たしたため知らせる
+    Fired,
+    /// 通知済みで、条件を満たし続けている
+    Held,
+    /// 条件を満たしたが、クールダウンの期間中のため保留している
+    Cooldown,
+    /// 条件を満たしたが、知らせない時間帯のため保留している
+    Quiet,
+}
+
+impl Outcome {
+    /// 状態の説明を返す。
+    pub fn label(&self) -> &'static str {
+        match self {
+            Outcome::Idle => "",
+            Outcome::Fired => "未通知",
+            Outcome::Held => "通知済み",
+            Outcome::Cooldown => "クールダウン中",
+            Outcome::Quiet => "通知しない時間帯",
+        }
+    }
+}
+
+impl RuleState {
+    pub fn new(rule: &str) -> Self {
+        Self {
+            rule: rule.to_string(),
+            triggered: false,
+            fired_at: None,
+        }
+    }
+
+    /// 状態を変えずに、条件の評価結果がどう扱われるかを返す。
+    pub fn outcome(&self, rule: &Rule, holds: bool, now: DateTime<Utc>) -> Outcome {
+        if !holds {
+            Outcome::Idle
+        } else if self.triggered {
+            Outcome::Held
+        } else if rule.in_cooldown(self.fired_at, now) {
+            Outcome::Cooldown
+        } else if rule.is_quiet(now) {
+            Outcome::Quiet
+        } else {
+            Outcome::Fired
+        }
+    }
+
+    /// 条件の評価結果を反映し、その結果を返す。
+    pub fn step(&mut self, rule: &Rule, holds: bool, now: DateTime<Utc>) -> Outcome {
+        let outcome = self.outcome(rule, holds, now);
+        match outcome {
+            Outcome::Idle => self.triggered = false,
+            Outcome::Fired => {
+                self.triggered = true;
+                self.fired_at = Some(now);
+            }
+            _ => {}
+        }
+        outcome
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 条件式を解析済みのルール
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub rule: Rule,
+    pub expr: RuleExpr,
+}
+
+impl CompiledRule {
+    /// ルールを検査して、条件式を解析する。
+    /// 設定ファイルを手で編集したときも、範囲外のクールダウンや時間帯はここでエラーにする。
+    pub fn new(rule: Rule) -> Result<Self, RuleError> {
+        if let Some(x) = rule
+            .cooldown_hours
+            .filter(|v| !(1..=MAX_COOLDOWN_HOURS).contains(v))
+        {
+            return error(format!(
+                "クールダウンの期間は1時間から1年までです（{}時間）",
+                x
+            ));
+        }
+        if let Some(x) = rule.quiet_hours.filter(|v| !v.is_valid()) {
+            return error(format!(
+                "知らせない時間帯は0〜23時の範囲で、開始と終了を異なる時にしてください（{}）",
+                x
+            ));
+        }
+        let expr = parse_rule(&rule.expr)?;
+        Ok(Self { rule, expr })
+    }
+
+    /// 製品の現在の状態（`now` の時点）が条件を満たすかどうかを返す。
+    pub fn holds(
+        &self,
+        product: &ProductHistory,
+        profile: &PaymentProfile,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.expr.holds(&product.history, profile, now)
+    }
+}
+
+/// 製品に適用するルールを評価し、新たに知らせるものを返す。通知状態も更新する。
+pub fn evaluate_rules(
+    product: &mut ProductHistory,
+    rules: &[CompiledRule],
+    profile: &PaymentProfile,
+) -> Vec<FiredAlert> {
+    let Some(last) = product.history.last() else {
+        return vec![];
+    };
+    let price = last.price;
+    let now = product.last_checked.unwrap_or(last.datetime);
+
+    let mut fired = vec![];
+    for r in rules.iter() {
+        if !r.rule.applies_to(product) {
+            continue;
+        }
+        let holds = r.holds(product, profile, now);
+        let state = product.rule_state_mut(&r.rule.name);
+        if state.step(&r.rule, holds, now) == Outcome::Fired {
+            fired.push(FiredAlert {
+                rule: format!("ルール「{}」", r.rule.name),
+                price,
+            });
+        }
+    }
+    fired
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 条件式で使える項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Var {
+    Price,
+    Effective,
+    Point,
+    PointRatio,
+    High,
+    Low,
+    Discount,
+    InStock,
+    NewLow,
+    NewHigh,
+}
+
+const VARS: &[(&str, Var)] = &[
+    ("price", Var::Price),
+    ("effective", Var::Effective),
+    ("point", Var::Point),
+    ("point_ratio", Var::PointRatio),
+    ("high", Var::High),
+    ("low", Var::Low),
+    ("discount", Var::Discount),
+    ("in_stock", Var::InStock),
+    ("new_low", Var::NewLow),
+    ("new_high", Var::NewHigh),
+];
+
+impl Var {
+    fn is_bool(self) -> bool {
+        matches!(self, Var::InStock | Var::NewLow | Var::NewHigh)
+    }
+
+    /// 期間で集計できる（価格データごとに値が決まる）項目かどうかを返す。
+    fn is_per_price(self) -> bool {
+        matches!(
+            self,
+            Var::Price | Var::Effective | Var::Point | Var::PointRatio
+        )
+    }
+}
+
+/// 期間内の集計の方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Median,
+    Avg,
+    Min,
+    Max,
+}
+
+/// 関数の名称
+const FUNCTIONS: &[&str] = &["prev", "delta", "median", "avg", "min", "max"];
+
+/// 算術演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// 比較演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// ルールの条件式
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleExpr {
+    Number(f64),
+    Var(Var),
+    /// 前回の価格データでの値
+    Prev(Var),
+    /// 前回の価格データからの変化
+    Delta(Var),
+    Aggregate {
+        func: Aggregate,
+        var: Var,
+        window: Window,
+    },
+    Neg(Box<RuleExpr>),
+    Arith(ArithOp, Box<RuleExpr>, Box<RuleExpr>),
+    Compare(CmpOp, Box<RuleExpr>, Box<RuleExpr>),
+    And(Box<RuleExpr>, Box<RuleExpr>),
+    Or(Box<RuleExpr>, Box<RuleExpr>),
+    Not(Box<RuleExpr>),
+}
+
+/// 条件式の解析時や、ルールの設定の検査時のエラー
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleError {
+    pub message: String,
+}
+
+impl std::error::Error for RuleError {}
+
+impl std::fmt::Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ルールが正しくありません：{}", self.message)
+    }
+}
+
+fn error<T>(message: String) -> Result<T, RuleError> {
+    Err(RuleError { message })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Arith(ArithOp),
+    Cmp(CmpOp),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// 条件式を単語に分割する。
+fn lex(input: &str) -> Result<Vec<Token>, RuleError> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        chars.next();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '+' => Token::Arith(ArithOp::Add),
+            '-' => Token::Arith(ArithOp::Sub),
+            '*' | '×' => Token::Arith(ArithOp::Mul),
+            '/' | '÷' => Token::Arith(ArithOp::Div),
+            '≠' => Token::Cmp(CmpOp::Ne),
+            '≤' => Token::Cmp(CmpOp::Le),
+            '≥' => Token::Cmp(CmpOp::Ge),
+            '=' | '!' | '<' | '>' => {
+                let eq = chars.peek() == Some(&'=');
+                if eq {
+                    chars.next();
+                }
+                let op = match (c, eq) {
+                    ('=', _) => CmpOp::Eq,
+                    ('!', true) => CmpOp::Ne,
+                    ('<', true) => CmpOp::Le,
+                    ('<', false) => CmpOp::Lt,
+                    ('>', true) => CmpOp::Ge,
+                    ('>', false) => CmpOp::Gt,
+                    _ => return error("`!` の後には `=` が必要です".to_string()),
+                };
+                Token::Cmp(op)
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' => {
+                let mut s = c.to_string();
+                while let Some(&x) = chars.peek() {
+                    if !(x.is_alphanumeric() || x == '_' || x == '.') {
+                        break;
+                    }
+                    s.push(x);
+                    chars.next();
+                }
+                // `90d` のような期間は名称として扱う。
+                let number = s
+                    .starts_with(|x: char| x.is_ascii_digit() || x == '.')
+                    .then(|| s.parse::<f64>().ok())
+                    .flatten();
+                match number {
+                    Some(n) => Token::Number(n),
+                    None => Token::Ident(s.to_lowercase()),
+                }
+            }
+            _ => return error(format!("`{}` は使えない文字です", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// ルールの条件式を解析する。
+pub fn parse_rule(input: &str) -> Result<RuleExpr, RuleError> {
+    let tokens = lex(input)?;
+    if tokens.is_empty() {
+        return error("条件を指定してください".to_string());
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    if let Some(t) = parser.tokens.get(parser.pos) {
+        return error(format!("`{}` の位置が正しくありません", token_text(t)));
+    }
+    if !expr.is_bool() {
+        return error(
+            "条件は比較（`price < 30000` 等）または真偽値の項目にしてください".to_string(),
+        );
+    }
+    Ok(expr)
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Ident(s) => s.clone(),
+        Token::Arith(_) => "算術演算子".to_string(),
+        Token::Cmp(_) => "比較演算子".to_string(),
+        Token::LParen => "(".to_string(),
+        Token::RParen => ")".to_string(),
+        Token::Comma => ",".to_string(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(w)) if w == keyword)
+    }
+
+    fn expect(&mut self, token: Token, message: &str) -> Result<(), RuleError> {
+        if self.peek() != Some(&token) {
+            return error(message.to_string());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn boolean(expr: RuleExpr, keyword: &str) -> Result<Box<RuleExpr>, RuleError> {
+        if !expr.is_bool() {
+            return error(format!("`{}` の対象は条件にしてください", keyword));
+        }
+        Ok(Box::new(expr))
+    }
+
+    fn numeric(expr: RuleExpr) -> Result<Box<RuleExpr>, RuleError> {
+        if expr.is_bool() {
+            return error("真偽値は計算や比較に使えません".to_string());
+        }
+        Ok(Box::new(expr))
+    }
+
+    fn or(&mut self) -> Result<RuleExpr, RuleError> {
+        let mut left = self.and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            let right = self.and()?;
+            left = RuleExpr::Or(Self::boolean(left, "or")?, Self::boolean(right, "or")?);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<RuleExpr, RuleError> {
+        let mut left = self.not()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            let right = self.not()?;
+            left = RuleExpr::And(Self::boolean(left, "and")?, Self::boolean(right, "and")?);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<RuleExpr, RuleError> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            let expr = self.not()?;
+            return Ok(RuleExpr::Not(Self::boolean(expr, "not")?));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<RuleExpr, RuleError> {
+        let left = self.additive()?;
+        let Some(&Token::Cmp(op)) = self.peek() else {
+            return Ok(left);
+        };
+        self.pos += 1;
+        let right = self.additive()?;
+        Ok(RuleExpr::Compare(
+            op,
+            Self::numeric(left)?,
+            Self::numeric(right)?,
+        ))
+    }
+
+    fn additive(&mut self) -> Result<RuleExpr, RuleError> {
+        let mut left = self.multiplicative()?;
+        while let Some(&Token::Arith(op @ (ArithOp::Add | ArithOp::Sub))) = self.peek() {
+            self.pos += 1;
+            let right = self.multiplicative()?;
+            left = RuleExpr::Arith(op, Self::numeric(left)?, Self::numeric(right)?);
+        }
+        Ok(left)
+    }
+
+    fn multiplicative(&mut self) -> Result<RuleExpr, RuleError> {
+        let mut left = self.unary()?;
+        while let Some(&Token::Arith(op @ (ArithOp::Mul | ArithOp::Div))) = self.peek() {
+            self.pos += 1;
+            let right = self.unary()?;
+            left = RuleExpr::Arith(op, Self::numeric(left)?, Self::numeric(right)?);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<RuleExpr, RuleError> {
+        if self.peek() == Some(&Token::Arith(ArithOp::Sub)) {
+            self.pos += 1;
+            let expr = self.unary()?;
+            return Ok(RuleExpr::Neg(Self::numeric(expr)?));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<RuleExpr, RuleError> {
+        let token = match self.peek() {
+            Some(x) => x.clone(),
+            None => return error("条件が途中で終わっています".to_string()),
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(RuleExpr::Number(n)),
+            Token::LParen => {
+                let expr = self.or()?;
+                self.expect(Token::RParen, "括弧が閉じられていません")?;
+                Ok(expr)
+            }
+            Token::Ident(name) if self.peek() == Some(&Token::LParen) => self.function(&name),
+            Token::Ident(name) => Ok(RuleExpr::Var(variable(&name)?)),
+            t => error(format!(
+                "`{}` の前に項目名または数値が必要です",
+                token_text(&t)
+            )),
+        }
+    }
+
+    fn function(&mut self, name: &str) -> Result<RuleExpr, RuleError> {
+        if !FUNCTIONS.contains(&name) {
+            let mut message = format!("不明な関数 `{}` です", name);
+            if let Some(s) = suggest(name, FUNCTIONS.iter().copied()) {
+                message = format!("{}。もしかして: `{}`", message, s);
+            }
+            return error(message);
+        }
+        self.pos += 1;
+        let var = match self.peek() {
+            Some(Token::Ident(x)) => variable(&x.clone())?,
+            _ => return error(format!("`{}` には項目名を指定してください", name)),
+        };
+        self.pos += 1;
+
+        let aggregate = match name {
+            "median" => Aggregate::Median,
+            "avg" => Aggregate::Avg,
+            "min" => Aggregate::Min,
+            "max" => Aggregate::Max,
+            _ => {
+                if var.is_bool() {
+                    return error(format!("`{}` には数値の項目を指定してください", name));
+                }
+                self.expect(Token::RParen, "括弧が閉じられていません")?;
+                return Ok(if name == "prev" {
+                    RuleExpr::Prev(var)
+                } else {
+                    RuleExpr::Delta(var)
+                });
+            }
+        };
+        if !var.is_per_price() {
+            return error(format!(
+                "`{}` には price, effective, point, point_ratio のいずれかを指定してください",
+                name
+            ));
+        }
+        self.expect(
+            Token::Comma,
+            &format!("`{}` には期間（30d, 90d, all 等）も指定してください", name),
+        )?;
+        let window = match self.peek() {
+            Some(Token::Ident(x)) => Window::from_string(x),
+            _ => None,
+        };
+        let Some(window) = window else {
+            return error("期間は 30d, 90d, all のように指定してください".to_string());
+        };
+        self.pos += 1;
+        self.expect(Token::RParen, "括弧が閉じられていません")?;
+        Ok(RuleExpr::Aggregate {
+            func: aggregate,
+            var,
+            window,
+        })
+    }
+}
+
+fn variable(name: &str) -> Result<Var, RuleError> {
+    if let Some((_, v)) = VARS.iter().find(|(n, _)| *n == name) {
+        return Ok(*v);
+    }
+    let mut message = format!("不明な項目 `{}` です", name);
+    let names = VARS
+        .iter()
+        .map(|(n, _)| *n)
+        .chain(FUNCTIONS.iter().copied());
+    if let Some(s) = suggest(name, names) {
+        message = format!("{}。もしかして: `{}`", message, s);
+    }
+    error(message)
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 条件式を評価するときのデータ
+struct Context<'a> {
+    /// 評価する時点までの価格履歴
+    history: &'a [OnePrice],
+    profile: &'a PaymentProfile,
+    now: DateTime<Utc>,
+}
+
+impl RuleExpr {
+    fn is_bool(&self) -> bool {
+        match self {
+            RuleExpr::Var(v) => v.is_bool(),
+            RuleExpr::Compare(..) | RuleExpr::And(..) | RuleExpr::Or(..) | RuleExpr::Not(..) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 価格履歴（最後が最新の価格データ）が `now` の時点で条件を満たすかどうかを返す。
+    pub fn holds(
+        &self,
+        history: &[OnePrice],
+        profile: &PaymentProfile,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let context = Context {
+            history,
+            profile,
+            now,
+        };
+        self.truth(&context)
+    }
+
+    fn truth(&self, c: &Context) -> bool {
+        match self {
+            RuleExpr::Var(v) => truth_of(*v, c.history),
+            RuleExpr::And(a, b) => a.truth(c) && b.truth(c),
+            RuleExpr::Or(a, b) => a.truth(c) || b.truth(c),
+            RuleExpr::Not(a) => !a.truth(c),
+            RuleExpr::Compare(op, a, b) => {
+                let (Some(a), Some(b)) = (a.number(c), b.number(c)) else {
+                    return false;
+                };
+                match op {
+                    CmpOp::Eq => a == b,
+                    CmpOp::Ne => a != b,
+                    CmpOp::Lt => a < b,
+                    CmpOp::Le => a <= b,
+                    CmpOp::Gt => a > b,
+                    CmpOp::Ge => a >= b,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// 数値を返す。前回の値がない等で計算できないときは None を返す。
+    fn number(&self, c: &Context) -> Option<f64> {
+        match self {
+            RuleExpr::Number(n) => Some(*n),
+            RuleExpr::Var(v) => value_of(*v, c.history, c.profile),
+            RuleExpr::Prev(v) => value_of(*v, previous(c.history)?, c.profile),
+            RuleExpr::Delta(v) => Some(
+                value_of(*v, c.history, c.profile)?
+                    - value_of(*v, previous(c.history)?, c.profile)?,
+            ),
+            RuleExpr::Aggregate { func, var, window } => {
+                let segs =
+                    segments_by(c.history, *window, c.now, |p| per_price(*var, p, c.profile));
+                match func {
+                    Aggregate::Median => weighted_median(&segs).map(|v| v as f64),
+                    Aggregate::Avg => weighted_average(&segs),
+                    Aggregate::Min => segs.iter().map(|v| v.price).min().map(|v| v as f64),
+                    Aggregate::Max => segs.iter().map(|v| v.price).max().map(|v| v as f64),
+                }
+            }
+            RuleExpr::Neg(a) => Some(-a.number(c)?),
+            RuleExpr::Arith(op, a, b) => {
+                let (a, b) = (a.number(c)?, b.number(c)?);
+                match op {
+                    ArithOp::Add => Some(a + b),
+                    ArithOp::Sub => Some(a - b),
+                    ArithOp::Mul => Some(a * b),
+                    ArithOp::Div => (b != 0.0).then(|| a / b),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 最新の価格データを除いた価格履歴を返す。前回の価格データがないときは None を返す。
+fn previous(history: &[OnePrice]) -> Option<&[OnePrice]> {
+    (history.len() >= 2).then(|| &history[..history.len() - 1])
+}
+
+/// 1回分の価格データで決まる項目の値を返す。
+fn per_price(var: Var, p: &OnePrice, profile: &PaymentProfile) -> u64 {
+    match var {
+        Var::Effective => p.effective_price(profile),
+        Var::Point => p.point_with(profile),
+        Var::PointRatio => p.point_ratio_with(profile),
+        _ => p.price,
+    }
+}
+
+fn value_of(var: Var, history: &[OnePrice], profile: &PaymentProfile) -> Option<f64> {
+    let last = history.last()?;
+    let high = history.iter().map(|v| v.price).max()?;
+    let value = match var {
+        Var::High => high as f64,
+        Var::Low => history.iter().map(|v| v.price).min()? as f64,
+        Var::Discount if high == 0 => 0.0,
+        Var::Discount => (high - last.price) as f64 * 100.0 / high as f64,
+        Var::InStock | Var::NewLow | Var::NewHigh => truth_of(var, history) as u8 as f64,
+        _ => per_price(var, last, profile) as f64,
+    };
+    Some(value)
+}
+
+fn truth_of(var: Var, history: &[OnePrice]) -> bool {
+    let Some(last) = history.last() else {
+        return false;
+    };
+    // 現在の価格になる前の価格と比べる。
+    let start = history
+        .windows(2)
+        .rposition(|v| v[0].price != v[1].price)
+        .map_or(0, |i| i + 1);
+    let before = history[..start].iter().map(|v| v.price);
+    match var {
+        Var::InStock => last.status.is_in_stock(),
+        Var::NewLow => before.min().is_some_and(|v| last.price < v),
+        Var::NewHigh => before.max().is_some_and(|v| last.price > v),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::fixtures::{day, one_price_with};
+    use crate::types::StockStatus;
+
+    fn holds(expr: &str, history: &[OnePrice], now: u32) -> bool {
+        parse_rule(expr)
+            .unwrap()
+            .holds(history, &PaymentProfile::default(), day(now))
+    }
+
+    fn rule(name: &str) -> Rule {
+        Rule {
+            name: name.to_string(),
+            expr: "price < 1000".to_string(),
+            tags: vec![],
+            products: vec![],
+            cooldown_hours: None,
+            quiet_hours: None,
+        }
+    }
+
+    #[test]
+    fn parse_rule_test() {
+        assert!(parse_rule("effective < 0.85 × median(effective, 90d) AND in_stock").is_ok());
+        assert!(parse_rule("delta(point_ratio) ≥ 3").is_ok());
+        assert!(parse_rule("not (new_low or price - prev(price) <= -1000)").is_ok());
+        assert_eq!(
+            Ok(RuleExpr::Compare(
+                CmpOp::Lt,
+                Box::new(RuleExpr::Var(Var::Price)),
+                Box::new(RuleExpr::Arith(
+                    ArithOp::Add,
+                    Box::new(RuleExpr::Number(1.0)),
+                    Box::new(RuleExpr::Arith(
+                        ArithOp::Mul,
+                        Box::new(RuleExpr::Number(2.0)),
+                        Box::new(RuleExpr::Number(3.0)),
+                    )),
+                )),
+            )),
+            parse_rule("price < 1 + 2 * 3")
+        );
+
+        let e = parse_rule("prise < 100").unwrap_err();
+        assert!(e.message.contains("`price`"));
+        assert!(parse_rule("price").is_err());
+        assert!(parse_rule("price < in_stock").is_err());
+        assert!(parse_rule("median(price) < 100").is_err());
+        assert!(parse_rule("median(discount, 30d) < 100").is_err());
+        assert!(parse_rule("median(price, 3x) < 100").is_err());
+        assert!(parse_rule("delta(in_stock) > 0").is_err());
+        assert!(parse_rule("(price < 1").is_err());
+        assert!(parse_rule("price < 1 price").is_err());
+        assert!(parse_rule("price < ￥1").is_err());
+        assert!(parse_rule("").is_err());
+    }
+
+    #[test]
+    fn holds_test() {
+        // 1000円が8日間、800円が1日間（在庫あり、還元率 10% → 13%）
+        let history = vec![
+            one_price_with(1000, 10, StockStatus::BackOrder, 1),
+            one_price_with(800, 13, StockStatus::Sufficient, 9),
+        ];
+        assert!(holds(
+            "price <= 0.85 * median(price, all) and in_stock",
+            &history,
+            10
+        ));
+        assert!(!holds("price <= 0.75 * median(price, all)", &history, 10));
+        assert!(holds("delta(point_ratio) >= 3", &history, 10));
+        assert!(holds(
+            "prev(price) = 1000 and delta(price) = -200",
+            &history,
+            10
+        ));
+        assert!(holds(
+            "discount = 20 and new_low and not new_high",
+            &history,
+            10
+        ));
+        assert!(holds(
+            "effective = 696 and high = 1000 and low = 800",
+            &history,
+            10
+        ));
+        assert!(holds(
+            "avg(price, 2d) = 800 and max(price, all) = 1000",
+            &history,
+            11
+        ));
+
+        // 前回の価格データがないときは、前回との比較は満たさない。
+        let first = &history[..1];
+        assert!(!holds("delta(price) < 0", first, 2));
+        assert!(!holds("new_low", first, 2));
+        assert!(!holds("price / 0 > 1", first, 2));
+    }
+
+    #[test]
+    fn invalid_settings_test() {
+        // 設定ファイルを手で編集した、範囲外のクールダウンや時間帯はエラーにする。
+        let mut long = Rule::ad_hoc("in_stock");
+        long.cooldown_hours = Some(i64::MAX);
+        let mut quiet = Rule::ad_hoc("in_stock");
+        quiet.quiet_hours = Some(QuietHours { start: 22, end: 24 });
+        assert!(CompiledRule::new(long.clone()).is_err());
+        assert!(CompiledRule::new(quiet.clone()).is_err());
+
+        let mut ok = Rule::ad_hoc("in_stock");
+        ok.cooldown_hours = Some(24);
+        ok.quiet_hours = Some(QuietHours { start: 22, end: 7 });
+        assert!(CompiledRule::new(ok.clone()).is_ok());
+
+        let config = crate::config::Config {
+            rules: vec![long, quiet, ok],
+            ..Default::default()
+        };
+        let (context, errors) = crate::alert::AlertContext::from_config(&config);
+        assert_eq!(1, context.rules.len());
+        assert_eq!(2, errors.len());
+    }
+
+    #[test]
+    fn quiet_hours_test() {
+        let quiet = QuietHours::from_string("22-7").unwrap();
+        // 日本時間 23:00, 6:00, 7:00
+        assert!(quiet.contains(Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap()));
+        assert!(quiet.contains(Utc.with_ymd_and_hms(2024, 1, 1, 21, 0, 0).unwrap()));
+        assert!(!quiet.contains(Utc.with_ymd_and_hms(2024, 1, 1, 22, 0, 0).unwrap()));
+        assert!(QuietHours::from_string("9-9").is_none());
+        assert!(QuietHours::from_string("24-7").is_none());
+        assert_eq!(Some(12), parse_cooldown("12h"));
+        assert_eq!(Some(72), parse_cooldown("3d"));
+        assert_eq!(None, parse_cooldown("3w"));
+        assert_eq!(None, parse_cooldown("3日"));
+        assert_eq!(None, parse_cooldown("h"));
+        assert_eq!(Some(365 * 24), parse_cooldown("365d"));
+        assert_eq!(None, parse_cooldown("366d"));
+        assert_eq!(None, parse_cooldown("9223372036854775807d"));
+
+        // 日本時間 23:00 の後、7:00 に終わる。
+        let night = Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap();
+        assert_eq!(
+            Utc.with_ymd_and_hms(2024, 1, 1, 22, 0, 0).unwrap(),
+            quiet.end_after(night)
+        );
+    }
+
+    #[test]
+    fn step_test() {
+        let mut r = rule("cheap");
+        r.cooldown_hours = Some(48);
+        let mut state = RuleState::new("cheap");
+
+        assert_eq!(Outcome::Fired, state.step(&r, true, day(1)));
+        assert_eq!(Outcome::Held, state.step(&r, true, day(2)));
+        assert_eq!(Outcome::Idle, state.step(&r, false, day(2)));
+        // クールダウンの期間中は保留し、期間が過ぎてから知らせる。
+        assert_eq!(Outcome::Cooldown, state.step(&r, true, day(2)));
+        assert_eq!(day(3), r.release_time(state.fired_at, day(2)));
+        assert_eq!(Outcome::Fired, state.step(&r, true, day(3)));
+
+        r.quiet_hours = QuietHours::from_string("9-12");
+        let mut state = RuleState::new("cheap");
+        assert_eq!(Outcome::Quiet, state.step(&r, true, day(1)));
+        assert_eq!(
+            Outcome::Fired,
+            state.step(&r, true, day(1) + Duration::hours(3))
+        );
+    }
+
+    #[test]
+    fn evaluate_rules_test() {
+        let mut product = ProductHistory {
+            id: "1".to_string(),
+            tags: vec!["audio".to_string()],
+            history: vec![one_price_with(1200, 10, StockStatus::Sufficient, 1)],
+            ..Default::default()
+        };
+        let mut tagged = rule("audio");
+        tagged.tags = vec!["audio".to_string()];
+        let mut other = rule("camera");
+        other.tags = vec!["camera".to_string()];
+        let rules: Vec<_> = [rule("all"), tagged, other]
+            .into_iter()
+            .map(|v| CompiledRule::new(v).unwrap())
+            .collect();
+        let profile = PaymentProfile::default();
+
+        assert!(evaluate_rules(&mut product, &rules, &profile).is_empty());
+        product
+            .history
+            .push(one_price_with(900, 10, StockStatus::Sufficient, 2));
+        let fired = evaluate_rules(&mut product, &rules, &profile);
+        let names: Vec<_> = fired.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(vec!["ルール「all」", "ルール「audio」"], names);
+        assert_eq!(2, product.rule_states.len());
+        assert!(evaluate_rules(&mut product, &rules, &profile).is_empty());
+    }
+}
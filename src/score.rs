@@ -0,0 +1,187 @@
+//! 買い時の評価。
+//!
+//! 製品自身の価格履歴（実質価格）から、今が買い時かどうかを 0〜100 の点数で評価する。
+
+use chrono::{DateTime, Utc};
+
+use crate::config::PaymentProfile;
+use crate::data::{OnePrice, ProductHistory};
+use crate::stats::{percentile_below, segments_by, weighted_average, Window};
+use crate::util::commafy;
+
+/// 直近の傾向を判断する期間
+const TREND_WINDOW: Window = Window::Days(30);
+
+/// 買い時の評価
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rating {
+    /// 待った方がよい
+    Wait,
+    /// 様子見
+    Hold,
+    /// まずまず
+    Fair,
+    /// 買い時
+    Buy,
+}
+
+impl Rating {
+    fn from_score(score: u64) -> Rating {
+        match score {
+            80.. => Rating::Buy,
+            60..=79 => Rating::Fair,
+            40..=59 => Rating::Hold,
+            _ => Rating::Wait,
+        }
+    }
+}
+
+impl std::fmt::Display for Rating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Rating::Buy => "買い時",
+            Rating::Fair => "まずまず",
+            Rating::Hold => "様子見",
+            Rating::Wait => "待つべき",
+        };
+        f.write_str(s)
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 買い時の点数と、その根拠
+#[derive(Debug, Clone)]
+pub struct BuyScore {
+    /// 点数 (0〜100)。高いほど買い時。
+    pub score: u64,
+    /// 点数に対応する評価
+    pub rating: Rating,
+    /// 評価の根拠の説明
+    pub reasons: Vec<String>,
+}
+
+/// 製品の価格履歴から買い時の点数を計算する。
+///
+/// 以下の要素を重み付けして合計する。
+/// - 現在の実質価格のパーセンタイル (40点)
+/// - 最安値との差 (30点)
+/// - 直近30日の平均との比較 (15点)
+/// - 過去の値下がりの頻度 (15点)。頻繁に値下がりする製品で、今が最安値でなければ待った方がよい。
+///
+/// 価格履歴が空のときは None を返す。
+pub fn buy_score(
+    product: &ProductHistory,
+    profile: &PaymentProfile,
+    now: DateTime<Utc>,
+) -> Option<BuyScore> {
+    let effective = |v: &OnePrice| v.effective_price(profile);
+    let all = segments_by(&product.history, Window::All, now, effective);
+    let recent = segments_by(&product.history, TREND_WINDOW, now, effective);
+    let current = all.last()?.price;
+    let low = all.iter().map(|v| v.price).min()?;
+    let mut reasons = vec![];
+
+    // パーセンタイル
+    let percentile = percentile_below(&all, current).unwrap_or(0.0);
+    let c_percentile = 1.0 - percentile / 100.0;
+    reasons.push(format!(
+        "これまでの{:.0}%の期間は今より安かった",
+        percentile
+    ));
+
+    // 最安値との差。20%以上高いときは0点。
+    let distance = (current - low) as f64 / low.max(1) as f64;
+    let c_low = (1.0 - distance / 0.2).clamp(0.0, 1.0);
+    if current == low {
+        reasons.push("過去の最安値と同じ".to_string());
+    } else {
+        reasons.push(format!(
+            "最安値(￥{})より{:.1}%高い",
+            commafy(low),
+            distance * 100.0
+        ));
+    }
+
+    // 直近の傾向。平均より10%安ければ満点、10%高ければ0点。
+    let average = weighted_average(&recent).unwrap_or(current as f64);
+    let trend = (average - current as f64) / average.max(1.0);
+    let c_trend = (0.5 + trend * 5.0).clamp(0.0, 1.0);
+    if trend.abs() >= 0.005 {
+        let word = if trend > 0.0 { "安い" } else { "高い" };
+        reasons.push(format!(
+            "直近30日の平均より{:.1}%{}",
+            trend.abs() * 100.0,
+            word
+        ));
+    }
+
+    // 値下がりの頻度（30日あたりの回数）
+    let drops = all.windows(2).filter(|v| v[1].price < v[0].price).count();
+    let days = (now - all[0].start).num_seconds() as f64 / 86400.0;
+    let rate = if days < 1.0 {
+        0.0
+    } else {
+        drops as f64 * 30.0 / days
+    };
+    let c_dips = if current == low {
+        1.0
+    } else {
+        1.0 / (1.0 + rate)
+    };
+    if drops > 0 {
+        reasons.push(format!("値下がりは30日あたり{:.1}回", rate));
+    }
+
+    let score =
+        (c_percentile * 40.0 + c_low * 30.0 + c_trend * 15.0 + c_dips * 15.0).round() as u64;
+    Some(BuyScore {
+        score,
+        rating: Rating::from_score(score),
+        reasons,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price};
+
+    fn product(prices: &[(u64, u32)]) -> ProductHistory {
+        ProductHistory {
+            id: "1".to_string(),
+            name: "test".to_string(),
+            history: prices
+                .iter()
+                .map(|&(price, d)| one_price(price, d))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn buy_score_at_low_test() {
+        let p = product(&[(1000, 1), (800, 20)]);
+        let score = buy_score(&p, &PaymentProfile::default(), day(21)).unwrap();
+        assert_eq!(Rating::Buy, score.rating);
+        assert!(score.reasons.iter().any(|v| v == "過去の最安値と同じ"));
+    }
+
+    #[test]
+    fn buy_score_after_dip_test() {
+        let p = product(&[(800, 1), (1000, 3), (800, 10), (1000, 12)]);
+        let score = buy_score(&p, &PaymentProfile::default(), day(30)).unwrap();
+        assert!(score.rating <= Rating::Hold);
+        assert!(score
+            .reasons
+            .iter()
+            .any(|v| v.starts_with("最安値(￥720)より25.0%高い")));
+    }
+
+    #[test]
+    fn rating_order_test() {
+        assert!(Rating::Buy > Rating::Fair);
+        assert_eq!(Rating::Hold, Rating::from_score(40));
+        assert_eq!(Rating::Wait, Rating::from_score(39));
+    }
+}
@@ -0,0 +1,308 @@
+//! 価格履歴の統計。
+//!
+//! 価格履歴には価格が変化した時点のデータのみが記録されているため、
+//! 各データの価格は次のデータの日時（最後のデータは現時刻）まで続いていたものとして（階段関数として）扱う。
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::data::OnePrice;
+
+/// 統計の対象とする期間
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// 直近の指定日数
+    Days(i64),
+    /// 全期間
+    All,
+}
+
+impl Window {
+    /// `30d` や `all` のような表記から期間を生成する。
+    pub fn from_string(string: &str) -> Option<Window> {
+        if string == "all" {
+            return Some(Window::All);
+        }
+        let days = string.strip_suffix('d')?.parse::<i64>().ok()?;
+        if days <= 0 {
+            return None;
+        }
+        Some(Window::Days(days))
+    }
+
+    /// 期間の開始日時を返す。全期間のときは None を返す。
+    fn start(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Window::Days(days) => Some(now - Duration::days(*days)),
+            Window::All => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Window::Days(days) => write!(f, "{}d", days),
+            Window::All => f.write_str("all"),
+        }
+    }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// ある価格が続いていた期間
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub price: u64,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Segment {
+    fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// 価格履歴を、指定期間内の価格が続いていた期間の列に変換する。
+/// 期間の開始前から続いていた価格は、期間の開始日時で切り詰める。
+/// 同じ価格が連続するときは1つにまとめる。
+pub fn segments(history: &[OnePrice], window: Window, now: DateTime<Utc>) -> Vec<Segment> {
+    segments_by(history, window, now, |v| v.price)
+}
+
+/// 価格履歴を、指定期間内の値（実質価格等）が続いていた期間の列に変換する。
+/// 値は `value` で1回分の価格データから求める。
+pub fn segments_by<F>(
+    history: &[OnePrice],
+    window: Window,
+    now: DateTime<Utc>,
+    value: F,
+) -> Vec<Segment>
+where
+    F: Fn(&OnePrice) -> u64,
+{
+    let start = window.start(now);
+    let mut array: Vec<Segment> = vec![];
+
+    for (i, p) in history.iter().enumerate() {
+        let price = value(p);
+        let mut seg_start = p.datetime;
+        let seg_end = history.get(i + 1).map_or(now, |v| v.datetime);
+        if let Some(s) = start {
+            if seg_end <= s {
+                continue;
+            }
+            seg_start = std::cmp::max(seg_start, s);
+        }
+
+        match array.last_mut() {
+            Some(last) if last.price == price => last.end = seg_end,
+            _ => array.push(Segment {
+                price,
+                start: seg_start,
+                end: seg_end,
+            }),
+        }
+    }
+    array
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 価格履歴の統計値
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceStats {
+    /// 対象期間
+    pub window: Window,
+    /// 時間加重平均価格
+    pub average: f64,
+    /// 時間加重の中央値
+    pub median: u64,
+    /// 現在価格のパーセンタイル。期間中で現在価格より安かった時間の割合(%)。
+    pub percentile: f64,
+    /// 価格の変化回数
+    pub changes: usize,
+    /// 典型的な変化幅（変化幅の絶対値の中央値）
+    pub typical_change: u64,
+    /// 最後に価格が変化してからの日数
+    pub days_since_change: i64,
+    /// 最安値が続いた最長の期間
+    pub longest_at_low: Duration,
+}
+
+/// 価格履歴の統計値を計算する。
+/// 対象期間内にデータがないときは None を返す。
+pub fn price_stats(history: &[OnePrice], window: Window, now: DateTime<Utc>) -> Option<PriceStats> {
+    let segs = segments(history, window, now);
+    let now_price = segs.last()?.price;
+    let average = weighted_average(&segs)?;
+    let median = weighted_median(&segs)?;
+    let percentile = percentile_below(&segs, now_price)?;
+
+    let mut deltas: Vec<u64> = segs
+        .windows(2)
+        .map(|v| v[0].price.abs_diff(v[1].price))
+        .collect();
+    let changes = deltas.len();
+    deltas.sort();
+    let typical_change = if deltas.is_empty() {
+        0
+    } else {
+        deltas[(deltas.len() - 1) / 2]
+    };
+
+    let last_change = last_change_datetime(history).unwrap_or(history[0].datetime);
+    let days_since_change = (now - last_change).num_days();
+
+    let low = segs.iter().map(|v| v.price).min()?;
+    let longest_at_low = segs
+        .iter()
+        .filter(|v| v.price == low)
+        .map(|v| v.duration())
+        .max()
+        .unwrap_or_else(Duration::zero);
+
+    Some(PriceStats {
+        window,
+        average,
+        median,
+        percentile,
+        changes,
+        typical_change,
+        days_since_change,
+        longest_at_low,
+    })
+}
+
+/// 各期間の重み（秒数）を返す。
+/// 期間の長さの合計が0のとき（登録直後等）は、各価格を同じ重みとして扱う。
+fn weights(segs: &[Segment]) -> Vec<i64> {
+    let total: i64 = segs.iter().map(|v| v.duration().num_seconds()).sum();
+    segs.iter()
+        .map(|v| {
+            if total == 0 {
+                1
+            } else {
+                v.duration().num_seconds()
+            }
+        })
+        .collect()
+}
+
+/// 時間加重平均を返す。期間がないときは None を返す。
+pub fn weighted_average(segs: &[Segment]) -> Option<f64> {
+    let weights = weights(segs);
+    let total: i64 = weights.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let sum: f64 = segs
+        .iter()
+        .zip(weights.iter())
+        .map(|(v, w)| v.price as f64 * *w as f64)
+        .sum();
+    Some(sum / total as f64)
+}
+
+/// 指定した価格より安かった時間の割合(%)を返す。期間がないときは None を返す。
+pub fn percentile_below(segs: &[Segment], price: u64) -> Option<f64> {
+    let weights = weights(segs);
+    let total: i64 = weights.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let below: i64 = segs
+        .iter()
+        .zip(weights.iter())
+        .filter(|(v, _)| v.price < price)
+        .map(|(_, w)| w)
+        .sum();
+    Some(below as f64 * 100.0 / total as f64)
+}
+
+/// 時間加重の中央値を返す。期間がないときは None を返す。
+pub fn weighted_median(segs: &[Segment]) -> Option<u64> {
+    let weights = weights(segs);
+    let total: i64 = weights.iter().sum();
+    let mut sorted: Vec<_> = segs.iter().zip(weights.iter()).collect();
+    sorted.sort_by_key(|(v, _)| v.price);
+    let mut acc = 0;
+    for (s, w) in sorted {
+        acc += w;
+        if acc * 2 >= total {
+            return Some(s.price);
+        }
+    }
+    None
+}
+
+/// 最後に価格が変化した日時を返す。
+/// 登録時から変化していないときは None を返す。
+fn last_change_datetime(history: &[OnePrice]) -> Option<DateTime<Utc>> {
+    history
+        .windows(2)
+        .rfind(|v| v[0].price != v[1].price)
+        .map(|v| v[1].datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price};
+
+    #[test]
+    fn window_from_string_test() {
+        assert_eq!(Some(Window::Days(30)), Window::from_string("30d"));
+        assert_eq!(Some(Window::All), Window::from_string("all"));
+        assert_eq!(None, Window::from_string("0d"));
+        assert_eq!(None, Window::from_string("30"));
+    }
+
+    #[test]
+    fn segments_test() {
+        let history = vec![one_price(1000, 1), one_price(800, 5), one_price(1000, 7)];
+        let segs = segments(&history, Window::All, day(11));
+        assert_eq!(3, segs.len());
+        assert_eq!(day(5), segs[1].start);
+        assert_eq!(day(11), segs[2].end);
+
+        // 期間の開始前から続いていた価格は切り詰める。
+        let segs = segments(&history, Window::Days(3), day(11));
+        assert_eq!(1, segs.len());
+        assert_eq!(day(8), segs[0].start);
+        let segs = segments(&history, Window::Days(5), day(11));
+        assert_eq!(2, segs.len());
+        assert_eq!(800, segs[0].price);
+        assert_eq!(day(6), segs[0].start);
+    }
+
+    #[test]
+    fn price_stats_test() {
+        // 1000円が4日間、800円が2日間、1000円が4日間
+        let history = vec![one_price(1000, 1), one_price(800, 5), one_price(1000, 7)];
+        let stats = price_stats(&history, Window::All, day(11)).unwrap();
+        assert_eq!(960.0, stats.average);
+        assert_eq!(1000, stats.median);
+        assert_eq!(20.0, stats.percentile);
+        assert_eq!(2, stats.changes);
+        assert_eq!(200, stats.typical_change);
+        assert_eq!(4, stats.days_since_change);
+        assert_eq!(Duration::days(2), stats.longest_at_low);
+
+        let stats = price_stats(&history, Window::Days(3), day(11)).unwrap();
+        assert_eq!(1000.0, stats.average);
+        assert_eq!(0, stats.changes);
+        assert_eq!(0.0, stats.percentile);
+        assert_eq!(Duration::days(3), stats.longest_at_low);
+    }
+
+    #[test]
+    fn price_stats_registered_now_test() {
+        let history = vec![one_price(1000, 1)];
+        let stats = price_stats(&history, Window::All, day(1)).unwrap();
+        assert_eq!(1000.0, stats.average);
+        assert_eq!(1000, stats.median);
+        assert_eq!(0, stats.days_since_change);
+        assert!(price_stats(&[], Window::All, day(1)).is_none());
+    }
+}
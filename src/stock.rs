@@ -0,0 +1,168 @@
+//! 在庫状況の分析。
+//!
+//! 価格履歴と同様に、各データの在庫状況は次のデータの日時（最後のデータは現時刻）まで続いていたものとして扱う。
+//! 在庫状況が不明な期間（在庫状況を記録する前の価格データ）は、集計に含めない。
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::data::OnePrice;
+use crate::types::StockStatus;
+
+/// ある在庫状況が続いていた期間
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusPeriod {
+    pub status: StockStatus,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl StatusPeriod {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// 在庫状況の推移を返す。同じ在庫状況が連続するときは1つにまとめる。
+pub fn timeline(history: &[OnePrice], now: DateTime<Utc>) -> Vec<StatusPeriod> {
+    let mut array: Vec<StatusPeriod> = vec![];
+    for (i, p) in history.iter().enumerate() {
+        let end = history.get(i + 1).map_or(now, |v| v.datetime);
+        match array.last_mut() {
+            Some(last) if last.status == p.status => last.end = end,
+            _ => array.push(StatusPeriod {
+                status: p.status,
+                start: p.datetime,
+                end,
+            }),
+        }
+    }
+    array
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// 在庫状況の集計
+#[derive(Debug, Clone, PartialEq)]
+pub struct StockSummary {
+    /// 在庫があった時間の割合(%)
+    pub in_stock_ratio: f64,
+    /// 在庫切れ（お取り寄せ）になった回数
+    pub out_of_stock_count: usize,
+    /// お取り寄せになってから在庫が復活するまでの平均期間。復活したことがないときは None。
+    pub mean_restock: Option<Duration>,
+}
+
+/// 在庫状況を集計する。在庫状況が分かる価格データがないときは None を返す。
+pub fn stock_summary(history: &[OnePrice], now: DateTime<Utc>) -> Option<StockSummary> {
+    let periods: Vec<_> = timeline(history, now)
+        .into_iter()
+        .filter(|v| v.status != StockStatus::Unknown)
+        .collect();
+    if periods.is_empty() {
+        return None;
+    }
+
+    let total: i64 = periods.iter().map(|v| v.duration().num_seconds()).sum();
+    let in_stock_ratio = if total == 0 {
+        // 登録直後は、現在の在庫状況のみで判断する。
+        if periods.last().unwrap().status.is_in_stock() {
+            100.0
+        } else {
+            0.0
+        }
+    } else {
+        let in_stock: i64 = periods
+            .iter()
+            .filter(|v| v.status.is_in_stock())
+            .map(|v| v.duration().num_seconds())
+            .sum();
+        in_stock as f64 * 100.0 / total as f64
+    };
+
+    // 在庫がある状態からお取り寄せになった回数と、在庫が復活するまでの期間
+    let mut out_of_stock_count = 0;
+    let mut restocks = vec![];
+    for (i, p) in periods.iter().enumerate() {
+        if p.status.is_in_stock() || i == 0 {
+            continue;
+        }
+        out_of_stock_count += 1;
+        if i + 1 < periods.len() {
+            restocks.push(p.duration());
+        }
+    }
+    let mean_restock = if restocks.is_empty() {
+        None
+    } else {
+        let sum: i64 = restocks.iter().map(|v| v.num_seconds()).sum();
+        Some(Duration::seconds(sum / restocks.len() as i64))
+    };
+
+    Some(StockSummary {
+        in_stock_ratio,
+        out_of_stock_count,
+        mean_restock,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{day, one_price_with};
+
+    #[test]
+    fn timeline_test() {
+        let history = vec![
+            one_price_with(1000, 10, StockStatus::Sufficient, 1),
+            one_price_with(1000, 10, StockStatus::Sufficient, 2),
+            one_price_with(1000, 10, StockStatus::BackOrder, 3),
+            one_price_with(1000, 10, StockStatus::Limited, 5),
+        ];
+        let periods = timeline(&history, day(6));
+        assert_eq!(3, periods.len());
+        assert_eq!(Duration::days(2), periods[0].duration());
+        assert_eq!(StockStatus::BackOrder, periods[1].status);
+        assert_eq!(day(6), periods[2].end);
+    }
+
+    #[test]
+    fn stock_summary_test() {
+        // 在庫あり2日、お取り寄せ2日、在庫あり2日、お取り寄せ4日、在庫あり0日
+        let history = vec![
+            one_price_with(1000, 10, StockStatus::Sufficient, 1),
+            one_price_with(1000, 10, StockStatus::BackOrder, 3),
+            one_price_with(1000, 10, StockStatus::Sufficient, 5),
+            one_price_with(1000, 10, StockStatus::BackOrder, 7),
+            one_price_with(1000, 10, StockStatus::Sufficient, 11),
+        ];
+        let summary = stock_summary(&history, day(11)).unwrap();
+        assert_eq!(40.0, summary.in_stock_ratio);
+        assert_eq!(2, summary.out_of_stock_count);
+        assert_eq!(Some(Duration::days(3)), summary.mean_restock);
+
+        // お取り寄せのまま
+        let summary = stock_summary(&history[..4], day(9)).unwrap();
+        assert_eq!(2, summary.out_of_stock_count);
+        assert_eq!(Some(Duration::days(2)), summary.mean_restock);
+
+        // 登録時からお取り寄せのときは、在庫切れの回数に含めない。
+        let history = vec![one_price_with(1000, 10, StockStatus::BackOrder, 1)];
+        let summary = stock_summary(&history, day(1)).unwrap();
+        assert_eq!(0.0, summary.in_stock_ratio);
+        assert_eq!(0, summary.out_of_stock_count);
+        assert_eq!(None, summary.mean_restock);
+
+        // 在庫状況が不明な期間は含めない。
+        let history = vec![
+            one_price_with(1000, 10, StockStatus::Unknown, 1),
+            one_price_with(1000, 10, StockStatus::Sufficient, 3),
+            one_price_with(1000, 10, StockStatus::BackOrder, 4),
+            one_price_with(1000, 10, StockStatus::Sufficient, 5),
+        ];
+        let summary = stock_summary(&history, day(7)).unwrap();
+        assert_eq!(75.0, summary.in_stock_ratio);
+        assert_eq!(1, summary.out_of_stock_count);
+        assert_eq!(Some(Duration::days(1)), summary.mean_restock);
+        assert_eq!(None, stock_summary(&history[..1], day(7)));
+    }
+}
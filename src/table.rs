@@ -0,0 +1,194 @@
+//! 端末に表示する表。
+//!
+//! 各列の幅は、全角文字を2として数えた表示幅で揃える。
+//! 端末の幅に収まらないときは、縮められる列を縮め、それでも収まらないときは優先度の低い列から省く。
+
+use crate::util::{display_width, pad_left, pad_right, truncate_width};
+
+/// 列の間の空白
+const GAP: &str = "  ";
+
+/// 文字の揃え方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// 列の定義
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub header: &'static str,
+    pub align: Align,
+    /// 幅が足りないときに、この幅まで縮める。None のときは縮めない。
+    pub min_width: Option<usize>,
+    /// 幅が足りないときに省く順序。値が大きい列から省く。0 の列は省かない。
+    pub drop_order: u8,
+}
+
+impl Column {
+    /// 縮めず、省かない列を返す。
+    pub fn new(header: &'static str, align: Align) -> Self {
+        Self {
+            header,
+            align,
+            min_width: None,
+            drop_order: 0,
+        }
+    }
+
+    /// 幅が足りないときに `width` まで縮める列にする。
+    pub fn shrink(mut self, width: usize) -> Self {
+        self.min_width = Some(width);
+        self
+    }
+
+    /// 幅が足りないときに省く列にする。
+    pub fn optional(mut self, order: u8) -> Self {
+        self.drop_order = order;
+        self
+    }
+}
+
+/// 表
+#[derive(Debug, Default)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self {
+            columns,
+            rows: vec![],
+        }
+    }
+
+    /// 行を追加する。値の数は列の数と同じにすること。
+    pub fn push(&mut self, row: Vec<String>) {
+        debug_assert_eq!(self.columns.len(), row.len());
+        self.rows.push(row);
+    }
+
+    /// 表を `width` 以内の幅の文字列に変換する。
+    /// 返り値の先頭は見出しの行で、その後に追加した順に各行が続く。
+    pub fn render(&self, width: usize) -> Vec<String> {
+        let mut widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                self.rows
+                    .iter()
+                    .map(|r| display_width(&r[i]))
+                    .chain(std::iter::once(display_width(c.header)))
+                    .max()
+                    .unwrap()
+            })
+            .collect();
+        let mut visible = vec![true; self.columns.len()];
+
+        loop {
+            let total: usize = (0..widths.len())
+                .filter(|&i| visible[i])
+                .map(|i| widths[i] + GAP.len())
+                .sum::<usize>()
+                .saturating_sub(GAP.len());
+            if total <= width {
+                break;
+            }
+            let excess = total - width;
+
+            // 縮められる列を縮める。
+            let shrinkable = (0..widths.len())
+                .find(|&i| visible[i] && self.columns[i].min_width.is_some_and(|m| widths[i] > m));
+            if let Some(i) = shrinkable {
+                let min = self.columns[i].min_width.unwrap();
+                widths[i] = widths[i].saturating_sub(excess).max(min);
+                continue;
+            }
+
+            // 優先度の低い列を省く。
+            let droppable = (0..widths.len())
+                .filter(|&i| visible[i] && self.columns[i].drop_order > 0)
+                .max_by_key(|&i| self.columns[i].drop_order);
+            match droppable {
+                Some(i) => visible[i] = false,
+                None => break,
+            }
+        }
+
+        let format_row = |cells: Vec<&str>| -> String {
+            let parts: Vec<_> = cells
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| visible[*i])
+                .map(|(i, cell)| {
+                    let text = truncate_width(cell, widths[i]);
+                    match self.columns[i].align {
+                        Align::Left => pad_right(&text, widths[i]),
+                        Align::Right => pad_left(&text, widths[i]),
+                    }
+                })
+                .collect();
+            parts.join(GAP).trim_end().to_string()
+        };
+
+        let mut lines = vec![format_row(self.columns.iter().map(|c| c.header).collect())];
+        for row in self.rows.iter() {
+            lines.push(format_row(row.iter().map(|v| v.as_str()).collect()));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new(vec![
+            Column::new("#", Align::Right),
+            Column::new("名称", Align::Left).shrink(8),
+            Column::new("価格", Align::Right),
+            Column::new("推移", Align::Left).optional(1),
+        ]);
+        table.push(vec![
+            "1".to_string(),
+            "ソニー WH-1000XM5".to_string(),
+            "￥49,500".to_string(),
+            "▁▂▃".to_string(),
+        ]);
+        table.push(vec![
+            "10".to_string(),
+            "OGK".to_string(),
+            "￥4,860".to_string(),
+            "▃▂▁".to_string(),
+        ]);
+        table
+    }
+
+    #[test]
+    fn render_test() {
+        let lines = table().render(80);
+        assert_eq!(3, lines.len());
+        assert_eq!(" #  名称                   価格  推移", lines[0]);
+        assert_eq!(" 1  ソニー WH-1000XM5  ￥49,500  ▁▂▃", lines[1]);
+        assert_eq!("10  OGK                 ￥4,860  ▃▂▁", lines[2]);
+    }
+
+    #[test]
+    fn render_narrow_test() {
+        // 名称を縮めて収める。
+        let lines = table().render(30);
+        assert!(lines.iter().all(|v| display_width(v) <= 30));
+        assert!(lines[1].contains("..."));
+        assert!(lines[1].contains("▁▂▃"));
+
+        // 名称を最小幅まで縮めても収まらないときは、推移を省く。
+        let lines = table().render(24);
+        assert!(lines.iter().all(|v| display_width(v) <= 24));
+        assert!(!lines[1].contains("▁▂▃"));
+    }
+}
@@ -0,0 +1,793 @@
+//! 全画面の端末UI（ダッシュボード）。
+//!
+//! 製品の一覧・選択中の製品の詳細とグラフ・更新のログを1画面に表示する。
+//! 価格の取得は別スレッドで行い、取得したデータはメインスレッドで `AppData` に反映する。
+//! そのため、取得中も画面の操作を続けられる。
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind,
+};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::Frame;
+
+use crate::access::get_data;
+use crate::alert::AlertContext;
+use crate::chart::render_chart;
+use crate::config::CONFIG;
+use crate::data::{AppData, UpdateResult, APP_STATE};
+use crate::notify::Notifiers;
+use crate::output::ListRow;
+use crate::report::render_item;
+use crate::types::WebData;
+use crate::util::{commafy, signed_yen, yen};
+
+/// ログとして保持する最大の行数
+const MAX_LOG: usize = 200;
+/// キー入力を待つ時間
+const TICK: Duration = Duration::from_millis(100);
+
+/// 取得用のスレッドからの通知
+#[derive(Debug)]
+enum Message {
+    /// 登録済みの製品のデータを取得した
+    Fetched {
+        url: String,
+        result: Result<WebData, String>,
+        fetched_at: DateTime<Utc>,
+    },
+    /// 追加する製品のデータを取得した
+    Added {
+        url: String,
+        result: Result<WebData, String>,
+        fetched_at: DateTime<Utc>,
+    },
+    /// 一連の取得が終わった
+    Finished,
+}
+
+/// 入力の状態
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    Normal,
+    /// 追加する製品のURLを入力中
+    Add(String),
+    /// 表示名を入力中
+    Rename(String),
+    /// 削除の確認中
+    ConfirmRemove,
+}
+
+/// ダッシュボードの状態
+struct Dashboard {
+    /// 選択中の行（表示中の製品の中での位置）
+    table: TableState,
+    /// アーカイブした製品も表示する
+    show_archived: bool,
+    mode: Mode,
+    log: Vec<String>,
+    /// 取得の進捗（完了した件数, 全件数）
+    progress: Option<(usize, usize)>,
+    tx: Sender<Message>,
+    rx: Receiver<Message>,
+    /// 実質価格の表示と、アラートの評価に使う設定
+    context: AlertContext,
+    /// 更新が終わったときに知らせる先
+    notifiers: Notifiers,
+    /// 実行中の一連の更新の結果。終わったときにまとめて知らせる。
+    pending: UpdateResult,
+    /// 変更のたびにファイルへ保存する
+    autosave: bool,
+    quit: bool,
+}
+
+/// ダッシュボードを表示する。終了するまで戻らない。
+pub fn run_dashboard() -> std::io::Result<()> {
+    let (context, errors, notifiers) = {
+        let config = CONFIG.lock().unwrap();
+        let (context, errors) = AlertContext::from_config(&config);
+        (context, errors, Notifiers::from_config(&config))
+    };
+    let mut app = Dashboard::new(context, notifiers);
+    for e in errors {
+        app.log(e);
+    }
+    app.log("q で終了します。? でキー操作を表示します。".to_string());
+
+    let mut terminal = ratatui::init();
+    execute!(std::io::stdout(), EnableBracketedPaste)?;
+    let result = (|| -> std::io::Result<()> {
+        while !app.quit {
+            {
+                let data = APP_STATE.lock().unwrap();
+                terminal.draw(|frame| app.draw(frame, &data))?;
+            }
+            if event::poll(TICK)? {
+                match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        let mut data = APP_STATE.lock().unwrap();
+                        app.handle_key(key, &mut data);
+                    }
+                    Event::Paste(text) => app.handle_paste(&text),
+                    _ => {}
+                }
+            }
+            while let Ok(message) = app.rx.try_recv() {
+                let mut data = APP_STATE.lock().unwrap();
+                app.handle_message(message, &mut data);
+            }
+        }
+        Ok(())
+    })();
+    let _ = execute!(std::io::stdout(), DisableBracketedPaste);
+    ratatui::restore();
+    // 更新の途中で終了したときも、反映済みの価格やアラートの状態を失わないようにする。
+    for e in app.shutdown(&mut APP_STATE.lock().unwrap()) {
+        eprintln!("{}", e);
+    }
+    result
+}
+
+impl Dashboard {
+    fn new(context: AlertContext, notifiers: Notifiers) -> Self {
+        let (tx, rx) = channel();
+        Self {
+            table: TableState::default().with_selected(Some(0)),
+            show_archived: false,
+            mode: Mode::Normal,
+            log: vec![],
+            progress: None,
+            tx,
+            rx,
+            context,
+            notifiers,
+            pending: UpdateResult::default(),
+            autosave: true,
+            quit: false,
+        }
+    }
+
+    fn log(&mut self, line: String) {
+        self.log.push(line);
+        if self.log.len() > MAX_LOG {
+            self.log.remove(0);
+        }
+    }
+
+    fn save(&mut self, data: &AppData) {
+        if !self.autosave {
+            return;
+        }
+        if let Err(e) = data.write_file() {
+            self.log(format!("データの保存時にエラーが発生しました({})", e));
+        }
+    }
+
+    /// 表示中の製品の、`histories` 内での位置の一覧を返す。
+    fn visible(&self, data: &AppData) -> Vec<usize> {
+        (0..data.histories.len())
+            .filter(|&i| self.show_archived || !data.histories[i].archived)
+            .collect()
+    }
+
+    /// 選択中の製品の、`histories` 内での位置を返す。
+    fn selected(&self, data: &AppData) -> Option<usize> {
+        self.visible(data).get(self.table.selected()?).copied()
+    }
+
+    /// 選択中の行が表示中の製品の範囲に収まるようにする。
+    fn clamp_selection(&mut self, data: &AppData) {
+        let count = self.visible(data).len();
+        let selected = self.table.selected().unwrap_or(0);
+        self.table
+            .select(Some(selected.min(count.saturating_sub(1))));
+    }
+
+    // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+    fn handle_key(&mut self, key: KeyEvent, data: &mut AppData) {
+        match self.mode.clone() {
+            Mode::Normal => self.handle_normal_key(key, data),
+            Mode::Add(buf) | Mode::Rename(buf) => {
+                let mut buf = buf;
+                match key.code {
+                    KeyCode::Esc => self.mode = Mode::Normal,
+                    KeyCode::Enter => {
+                        let mode = std::mem::replace(&mut self.mode, Mode::Normal);
+                        match mode {
+                            Mode::Add(_) => self.add(buf.trim()),
+                            Mode::Rename(_) => self.rename(buf.trim(), data),
+                            _ => unreachable!(),
+                        }
+                        return;
+                    }
+                    KeyCode::Backspace => {
+                        buf.pop();
+                    }
+                    KeyCode::Char(c) => buf.push(c),
+                    _ => {}
+                }
+                self.set_input(buf);
+            }
+            Mode::ConfirmRemove => {
+                self.mode = Mode::Normal;
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    self.remove(data);
+                } else {
+                    self.log("削除を中止しました。".to_string());
+                }
+            }
+        }
+    }
+
+    /// 入力中の文字列を更新する。
+    fn set_input(&mut self, buf: String) {
+        self.mode = match self.mode {
+            Mode::Add(_) => Mode::Add(buf),
+            Mode::Rename(_) => Mode::Rename(buf),
+            _ => return,
+        };
+    }
+
+    fn handle_paste(&mut self, text: &str) {
+        // 改行を含む貼り付けは1行目のみを使う。
+        let line = text.lines().next().unwrap_or_default();
+        match &self.mode {
+            Mode::Add(buf) | Mode::Rename(buf) => {
+                let buf = format!("{}{}", buf, line);
+                self.set_input(buf);
+            }
+            // 一覧の表示中にURLを貼り付けたときは、そのまま追加する。
+            Mode::Normal if line.starts_with("http") => self.add(line.trim()),
+            _ => {}
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent, data: &mut AppData) {
+        let count = self.visible(data).len();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::PageDown => self.table.scroll_down_by(10),
+            KeyCode::PageUp => self.table.scroll_up_by(10),
+            KeyCode::Home | KeyCode::Char('g') => self.table.select_first(),
+            KeyCode::End | KeyCode::Char('G') => self.table.select(Some(count.saturating_sub(1))),
+            KeyCode::Char('u') => {
+                if let Some(i) = self.selected(data) {
+                    let url = data.histories[i].url.clone();
+                    self.fetch(vec![url]);
+                }
+            }
+            KeyCode::Char('U') => {
+                let urls = data
+                    .histories
+                    .iter()
+                    .filter(|v| !v.archived)
+                    .map(|v| v.url.clone())
+                    .collect();
+                self.fetch(urls);
+            }
+            KeyCode::Char('a') => self.mode = Mode::Add(String::new()),
+            KeyCode::Char('r') => {
+                if let Some(i) = self.selected(data) {
+                    let name = data.histories[i].custom_name.clone().unwrap_or_default();
+                    self.mode = Mode::Rename(name);
+                }
+            }
+            KeyCode::Char('x') => {
+                if let Some(i) = self.selected(data) {
+                    let product = &mut data.histories[i];
+                    product.archived = !product.archived;
+                    let line = if product.archived {
+                        format!("{} をアーカイブしました。", product.display_name())
+                    } else {
+                        format!("{} のアーカイブを解除しました。", product.display_name())
+                    };
+                    self.log(line);
+                    self.save(data);
+                }
+            }
+            KeyCode::Char('d') if self.selected(data).is_some() => {
+                self.mode = Mode::ConfirmRemove;
+            }
+            KeyCode::Char('A') => self.show_archived = !self.show_archived,
+            KeyCode::Char('?') => {
+                self.log("↑↓/jk: 選択  u: 選択中の製品を更新  U: 全製品を更新  a: 追加（URLの貼り付けでも追加）".to_string());
+                self.log("r: 表示名を変更  x: アーカイブ/解除  d: 削除  A: アーカイブした製品の表示切替  q: 終了".to_string());
+            }
+            _ => {}
+        }
+        self.clamp_selection(data);
+    }
+
+    // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+    /// 別スレッドで製品のデータを取得する。
+    fn fetch(&mut self, urls: Vec<String>) {
+        if self.progress.is_some() {
+            self.log("更新中です。完了してから実行してください。".to_string());
+            return;
+        }
+        if urls.is_empty() {
+            return;
+        }
+        self.log(format!("{}件の製品を更新します。", urls.len()));
+        self.progress = Some((0, urls.len()));
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            for url in urls {
+                let result = get_data(&url).map_err(|e| e.to_string());
+                let message = Message::Fetched {
+                    url,
+                    result,
+                    fetched_at: Utc::now(),
+                };
+                if tx.send(message).is_err() {
+                    return;
+                }
+            }
+            let _ = tx.send(Message::Finished);
+        });
+    }
+
+    /// 別スレッドで追加する製品のデータを取得する。
+    fn add(&mut self, url: &str) {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            self.log(format!("URLが正しくありません: {}", url));
+            return;
+        }
+        self.log(format!("{} を取得しています...", url));
+        let url = url.to_string();
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            let result = get_data(&url).map_err(|e| e.to_string());
+            let _ = tx.send(Message::Added {
+                url,
+                result,
+                fetched_at: Utc::now(),
+            });
+        });
+    }
+
+    fn rename(&mut self, name: &str, data: &mut AppData) {
+        let Some(i) = self.selected(data) else {
+            return;
+        };
+        let product = &mut data.histories[i];
+        if name.is_empty() {
+            product.custom_name = None;
+            self.log(format!("{} の表示名の設定を解除しました。", product.name));
+        } else {
+            product.custom_name = Some(name.to_string());
+            self.log(format!(
+                "{} の表示名を `{}` に設定しました。",
+                product.name, name
+            ));
+        }
+        self.save(data);
+    }
+
+    fn remove(&mut self, data: &mut AppData) {
+        let Some(i) = self.selected(data) else {
+            return;
+        };
+        let product = data.remove_product(i);
+        self.log(format!("{} を削除しました。", product.name));
+        self.save(data);
+        self.clamp_selection(data);
+    }
+
+    fn handle_message(&mut self, message: Message, data: &mut AppData) {
+        match message {
+            Message::Fetched {
+                url,
+                result,
+                fetched_at,
+            } => {
+                if let Some((done, total)) = self.progress {
+                    self.progress = Some((done + 1, total));
+                }
+                let Some(item) = data.apply_update(&url, result, fetched_at, &self.context) else {
+                    return;
+                };
+                let line = if item.is_notable() {
+                    render_item(&item, false)
+                } else {
+                    format!("・ {}  変化なし", item.name)
+                };
+                self.log(line);
+                self.pending.items.push(item);
+            }
+            Message::Added {
+                url,
+                result,
+                fetched_at,
+            } => {
+                let result = result.and_then(|v| {
+                    data.add_from_web_data(&url, v, fetched_at)
+                        .map_err(|e| e.to_string())
+                });
+                match result {
+                    Ok(_) => {
+                        let name = data.histories.last().unwrap().display_name().to_string();
+                        self.log(format!("{} を追加しました。", name));
+                        self.save(data);
+                    }
+                    Err(e) => self.log(format!("追加できませんでした({})", e)),
+                }
+            }
+            Message::Finished => {
+                self.log("更新が完了しました。".to_string());
+                for e in self.finish_update(data) {
+                    self.log(e);
+                }
+            }
+        }
+    }
+
+    /// 一連の更新を終える。`update` と同じく、反映済みの結果を保存してから知らせる。
+    /// 知らせるのに失敗したもののメッセージを返す。
+    fn finish_update(&mut self, data: &mut AppData) -> Vec<String> {
+        self.progress = None;
+        self.save(data);
+        let result = std::mem::take(&mut self.pending);
+        let profile = &self.context.profile;
+        self.notifiers
+            .dispatch(&result, &data.histories, profile, Utc::now())
+    }
+
+    /// 終了する前に、受け取り済みの取得結果を反映する。
+    /// 更新の途中だったときは、そこまでの結果を保存して知らせ、失敗したもののメッセージを返す。
+    fn shutdown(&mut self, data: &mut AppData) -> Vec<String> {
+        while let Ok(message) = self.rx.try_recv() {
+            self.handle_message(message, data);
+        }
+        if self.progress.is_none() {
+            return vec![];
+        }
+        self.finish_update(data)
+    }
+
+    // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+    fn draw(&mut self, frame: &mut Frame, data: &AppData) {
+        let [main, log, footer] = Layout::vertical([
+            Constraint::Min(10),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(main);
+
+        self.draw_list(frame, list, data);
+        self.draw_detail(frame, detail, data);
+        self.draw_log(frame, log);
+        self.draw_footer(frame, footer, data);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect, data: &AppData) {
+        let now = Utc::now();
+        let rows: Vec<Row> = self
+            .visible(data)
+            .into_iter()
+            .map(|i| {
+                let product = &data.histories[i];
+                let (high, low, current) = product.high_low_now();
+                // 一覧 (`list`) の変化と同じく、最後の価格の取得での変化を表示する。
+                let change = ListRow::new(i + 1, product, &self.context.profile, now)
+                    .map_or(0, |v| v.change);
+                let style = if product.archived {
+                    Style::default().add_modifier(Modifier::DIM)
+                } else if current == low && low < high {
+                    Style::default().fg(Color::Green)
+                } else {
+                    Style::default()
+                };
+                let status = product
+                    .history
+                    .last()
+                    .map(|v| v.status.to_string())
+                    .unwrap_or_default();
+                Row::new(vec![
+                    Cell::from((i + 1).to_string()),
+                    Cell::from(product.display_name().to_string()),
+                    Cell::from(Line::from(yen(current)).right_aligned()),
+                    Cell::from(Line::from(yen(low)).right_aligned()),
+                    Cell::from(Line::from(signed_yen(change)).right_aligned()),
+                    Cell::from(status),
+                ])
+                .style(style)
+            })
+            .collect();
+        let header = Row::new(vec!["#", "名称", "現在", "最安", "変化", "在庫"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let title = if self.show_archived {
+            " 製品（アーカイブを含む） "
+        } else {
+            " 製品 "
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Min(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(9),
+                Constraint::Length(10),
+            ],
+        )
+        .header(header)
+        .block(Block::bordered().title(title))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect, data: &AppData) {
+        let profile = &self.context.profile;
+        let block = Block::bordered().title(" 詳細 ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let Some(product) = self.selected(data).map(|i| &data.histories[i]) else {
+            return;
+        };
+
+        let mut lines = vec![
+            Line::from(product.display_name().to_string())
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+            Line::from(format!("メーカ: {}  ID: {}", product.maker, product.id)),
+        ];
+        if let Some(last) = product.history.last() {
+            let (high, low, _) = product.high_low_now();
+            lines.push(Line::from(format!(
+                "現在 {}  最高 {}  最安 {}  実質 {} ({}pt)",
+                yen(last.price),
+                yen(high),
+                yen(low),
+                yen(last.effective_price(profile)),
+                commafy(last.point_with(profile))
+            )));
+            lines.push(Line::from(format!("在庫: {}", last.status)));
+        }
+        if !product.tags.is_empty() {
+            lines.push(Line::from(format!("タグ: {}", product.tags.join(", "))));
+        }
+        if let Some(x) = &product.note {
+            lines.push(Line::from(format!("メモ: {}", x)));
+        }
+        lines.push(Line::default());
+
+        // グラフの下の4行（横軸・最高値/最安値・在庫・期間）を除いた高さをグラフに使う。
+        let height = (inner.height as usize).saturating_sub(lines.len() + 4);
+        if height >= 3 {
+            let chart = render_chart(
+                &product.history,
+                |v| v.effective_price(profile),
+                inner.width as usize,
+                height,
+                chrono::Utc::now(),
+            );
+            lines.extend(chart.into_iter().map(Line::from));
+        }
+        frame.render_widget(Paragraph::new(lines), inner);
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let title = match self.progress {
+            Some((done, total)) => format!(" ログ（更新中 {}/{}） ", done, total),
+            None => " ログ ".to_string(),
+        };
+        let height = area.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self
+            .log
+            .iter()
+            .skip(self.log.len().saturating_sub(height))
+            .map(|v| Line::from(v.as_str()))
+            .collect();
+        let paragraph = Paragraph::new(lines)
+            .block(Block::bordered().title(title))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(paragraph, area);
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect, data: &AppData) {
+        let text = match &self.mode {
+            Mode::Normal => {
+                "u:更新 U:全て更新 a:追加 r:表示名 x:アーカイブ d:削除 A:アーカイブ表示 ?:ヘルプ q:終了"
+                    .to_string()
+            }
+            Mode::Add(buf) => format!("追加するURL (Enterで確定、Escで中止): {}", buf),
+            Mode::Rename(buf) => format!("表示名（空にすると解除、Escで中止): {}", buf),
+            Mode::ConfirmRemove => {
+                let name = self
+                    .selected(data)
+                    .map(|i| data.histories[i].display_name().to_string())
+                    .unwrap_or_default();
+                format!("{} を削除します。よろしいですか？ [y/N]", name)
+            }
+        };
+        let style = match self.mode {
+            Mode::Normal => Style::default().add_modifier(Modifier::DIM),
+            _ => Style::default().fg(Color::Yellow),
+        };
+        frame.render_widget(Paragraph::new(text).style(style), area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::crossterm::event::KeyModifiers;
+    use ratatui::Terminal;
+
+    use super::*;
+    use crate::data::ProductHistory;
+    use crate::feed::Feed;
+    use crate::fixtures::one_price;
+    use crate::types::StockStatus;
+
+    fn data() -> AppData {
+        let product = |id: &str, name: &str, price: u64| ProductHistory {
+            id: id.to_string(),
+            name: name.to_string(),
+            url: format!("https://www.yodobashi.com/product/{}/", id),
+            history: vec![one_price(price, 1)],
+            ..Default::default()
+        };
+        AppData {
+            histories: vec![
+                product("1", "OGK ヘルメット", 4860),
+                product("2", "Sony WH-1000XM5", 49500),
+            ],
+        }
+    }
+
+    fn dashboard() -> Dashboard {
+        let mut app = Dashboard::new(AlertContext::default(), Notifiers::default());
+        app.autosave = false;
+        app
+    }
+
+    fn press(app: &mut Dashboard, data: &mut AppData, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE), data);
+    }
+
+    fn type_text(app: &mut Dashboard, data: &mut AppData, text: &str) {
+        for c in text.chars() {
+            press(app, data, KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn draw_test() {
+        let data = data();
+        let mut app = dashboard();
+        let mut terminal = Terminal::new(TestBackend::new(160, 40)).unwrap();
+        terminal.draw(|frame| app.draw(frame, &data)).unwrap();
+        // 全角文字の次のセルは空白になるため、ASCIIの部分で確認する。
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|v| v.symbol())
+            .collect();
+        assert!(screen.contains("Sony WH-1000XM5"));
+        assert!(screen.contains("4,860"));
+        assert!(screen.contains("U:"));
+    }
+
+    #[test]
+    fn handle_key_test() {
+        let mut data = data();
+        let mut app = dashboard();
+
+        // 2番目の製品の表示名を変更する。
+        press(&mut app, &mut data, KeyCode::Down);
+        press(&mut app, &mut data, KeyCode::Char('r'));
+        type_text(&mut app, &mut data, "ヘッドホン");
+        press(&mut app, &mut data, KeyCode::Enter);
+        assert_eq!(
+            Some("ヘッドホン".to_string()),
+            data.histories[1].custom_name
+        );
+        assert_eq!(Mode::Normal, app.mode);
+
+        // アーカイブすると一覧から消え、選択位置が範囲内に収まる。
+        press(&mut app, &mut data, KeyCode::Char('x'));
+        assert!(data.histories[1].archived);
+        assert_eq!(Some(0), app.table.selected());
+        press(&mut app, &mut data, KeyCode::Char('A'));
+        assert_eq!(2, app.visible(&data).len());
+
+        // 削除は確認してから行う。
+        press(&mut app, &mut data, KeyCode::Char('d'));
+        press(&mut app, &mut data, KeyCode::Char('n'));
+        assert_eq!(2, data.histories.len());
+        press(&mut app, &mut data, KeyCode::Char('d'));
+        press(&mut app, &mut data, KeyCode::Char('y'));
+        assert_eq!(1, data.histories.len());
+        assert_eq!("2", data.histories[0].id);
+    }
+
+    #[test]
+    fn handle_message_test() {
+        let mut data = data();
+        let mut app = dashboard();
+        app.progress = Some((0, 1));
+        let url = data.histories[0].url.clone();
+        let web_data = WebData {
+            price: 4500,
+            point: 450,
+            point_ratio: 10,
+            status: StockStatus::Sufficient,
+            ..Default::default()
+        };
+        app.handle_message(
+            Message::Fetched {
+                url,
+                result: Ok(web_data),
+                fetched_at: Utc::now(),
+            },
+            &mut data,
+        );
+        assert_eq!(Some((1, 1)), app.progress);
+        assert_eq!(4500, data.histories[0].history.last().unwrap().price);
+        assert!(app.log.last().unwrap().contains("最安値を更新"));
+
+        // 更新が終わると、`update` と同じくフィードを書き出す。
+        let path = std::env::temp_dir().join(format!("yodoprice-tui-{}.xml", std::process::id()));
+        app.notifiers.feeds = vec![Feed::new("all", path.to_str().unwrap())];
+        app.handle_message(Message::Finished, &mut data);
+        assert_eq!(None, app.progress);
+        assert!(app.pending.items.is_empty());
+        let feed = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(feed.contains("OGK ヘルメット"));
+    }
+
+    #[test]
+    fn shutdown_during_update_test() {
+        let mut data = data();
+        let mut app = dashboard();
+        app.progress = Some((0, 2));
+        let path = std::env::temp_dir().join(format!("yodoprice-quit-{}.xml", std::process::id()));
+        app.notifiers.feeds = vec![Feed::new("all", path.to_str().unwrap())];
+
+        // 受け取り済みの結果は、終了前に反映して知らせる。
+        let web_data = WebData {
+            price: 4500,
+            point: 450,
+            point_ratio: 10,
+            status: StockStatus::Sufficient,
+            ..Default::default()
+        };
+        app.tx
+            .send(Message::Fetched {
+                url: data.histories[0].url.clone(),
+                result: Ok(web_data),
+                fetched_at: Utc::now(),
+            })
+            .unwrap();
+        assert!(app.shutdown(&mut data).is_empty());
+        assert_eq!(None, app.progress);
+        assert!(app.pending.items.is_empty());
+        assert_eq!(4500, data.histories[0].history.last().unwrap().price);
+        let feed = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(feed.contains("OGK ヘルメット"));
+
+        // 更新中でなければ何もしない。
+        assert!(app.shutdown(&mut data).is_empty());
+    }
+}
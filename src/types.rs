@@ -17,10 +17,14 @@ pub struct WebData {
     pub status: StockStatus,
     /// 製造メーカ
     pub maker: String,
+    /// SKU（ヨドバシ.comの製品番号）
+    pub sku: String,
+    /// カテゴリのコード
+    pub category: String,
 }
 
 // TODO: 各種ステータスに対する処理を実装する
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StockStatus {
     /// 在庫あり
     Sufficient,
@@ -29,6 +33,8 @@ pub enum StockStatus {
     // お取り寄せ
     #[default]
     BackOrder,
+    /// 不明（在庫状況を記録する前の価格データ）
+    Unknown,
     // 販売休止中 (違うところに表示される)
     // 販売終了 (違うところに表示される)
     // 店舗のみ（店頭でのみ販売しています）
@@ -40,9 +46,39 @@ impl StockStatus {
         if string.starts_with("在庫あり") {
             return StockStatus::Sufficient;
         }
+        if string.starts_with("在庫僅少") || string.starts_with("在庫残少") {
+            return StockStatus::Limited;
+        }
 
         StockStatus::BackOrder
     }
+
+    /// 機械可読な出力に使う名称を返す。JSON に出力される名称と同じ。
+    pub fn key(&self) -> &'static str {
+        match self {
+            StockStatus::Sufficient => "Sufficient",
+            StockStatus::Limited => "Limited",
+            StockStatus::BackOrder => "BackOrder",
+            StockStatus::Unknown => "Unknown",
+        }
+    }
+
+    /// 在庫があるかどうかを返す。
+    pub fn is_in_stock(&self) -> bool {
+        matches!(self, StockStatus::Sufficient | StockStatus::Limited)
+    }
+}
+
+impl std::fmt::Display for StockStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            StockStatus::Sufficient => "在庫あり",
+            StockStatus::Limited => "在庫僅少",
+            StockStatus::BackOrder => "お取り寄せ",
+            StockStatus::Unknown => "不明",
+        };
+        f.write_str(s)
+    }
 }
 
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
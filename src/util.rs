@@ -1,10 +1,62 @@
-/// 省略された文字列を返す。
+use chrono::{DateTime, FixedOffset, Utc};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// 実行ファイルがある場所をカレントディレクトリに設定する。
+/// データファイルや設定ファイルは、実行ファイルと同じフォルダに置く。
+pub fn set_current_dir_to_exe() {
+    let exe_path = std::env::current_exe().unwrap();
+    let exe_dir = exe_path.parent().unwrap();
+    std::env::set_current_dir(exe_dir).unwrap();
+}
+
+/// 日時を日本時間(JST)の文字列に変換する。
+pub fn format_jst(datetime: &DateTime<Utc>, format: &str) -> String {
+    let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+    datetime.with_timezone(&jst).format(format).to_string()
+}
+
+/// 一覧等で名称を省略するときの幅（半角の文字数）
+const OMIT_WIDTH: usize = 50;
+
+/// 省略された文字列を返す。端末での表示幅が全角25文字分を超えるときは省略する。
 pub fn omitted_string(input: &str) -> String {
-    let end = input.char_indices().nth(25);
-    match end {
-        Some(x) => format!("{}...", &input[0..x.0]),
-        None => input[0..].to_string(),
+    truncate_width(input, OMIT_WIDTH)
+}
+
+/// 文字列の端末での表示幅を返す。全角文字は2、半角文字は1として数える。
+pub fn display_width(input: &str) -> usize {
+    UnicodeWidthStr::width(input)
+}
+
+/// 表示幅が `width` を超えるときは、末尾を `...` にして `width` 以内に収めた文字列を返す。
+pub fn truncate_width(input: &str, width: usize) -> String {
+    if display_width(input) <= width {
+        return input.to_string();
+    }
+    let limit = width.saturating_sub(3);
+    let mut s = String::new();
+    let mut used = 0;
+    for c in input.chars() {
+        let w = UnicodeWidthChar::width(c).unwrap_or(0);
+        if used + w > limit {
+            break;
+        }
+        s.push(c);
+        used += w;
     }
+    s + &".".repeat(width.min(3))
+}
+
+/// 表示幅が `width` になるように、右側を空白で埋めた文字列を返す。
+pub fn pad_right(input: &str, width: usize) -> String {
+    let pad = width.saturating_sub(display_width(input));
+    format!("{}{}", input, " ".repeat(pad))
+}
+
+/// 表示幅が `width` になるように、左側を空白で埋めた文字列を返す。
+pub fn pad_left(input: &str, width: usize) -> String {
+    let pad = width.saturating_sub(display_width(input));
+    format!("{}{}", " ".repeat(pad), input)
 }
 
 /// 渡された数値にカンマを入れた文字列を返す。
@@ -27,6 +79,28 @@ pub fn commafy<T: Into<i128>>(value: T) -> String {
     num
 }
 
+/// 金額を `￥1,234` の形式の文字列に変換する。
+pub fn yen(value: u64) -> String {
+    format!("￥{}", commafy(value))
+}
+
+/// 金額の変化を `+￥1,234` の形式の文字列に変換する。変化がないときは `-` にする。
+pub fn signed_yen(value: i64) -> String {
+    match value {
+        0 => "-".to_string(),
+        x if x > 0 => format!("+￥{}", commafy(x)),
+        x => format!("-￥{}", commafy(-x)),
+    }
+}
+
+/// HTML・XML の特殊文字をエスケープする。
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 #[cfg(test)]
 mod test {
     use crate::util::*;
@@ -35,11 +109,11 @@ mod test {
     fn omitted_string_test() {
         assert_eq!("あいうえお", omitted_string("あいうえお"));
         assert_eq!(
-            "あいうえおかきくけこさしすせそたちつてと12345...",
+            "あいうえおかきくけこさしすせそたちつてと123456789",
             omitted_string("あいうえおかきくけこさしすせそたちつてと123456789")
         );
         assert_eq!(
-            "あいうえおかきくけこさしすせそたちつてとなにぬねの...",
+            "あいうえおかきくけこさしすせそたちつてとなにぬ...",
             omitted_string("あいうえおかきくけこさしすせそたちつてとなにぬねのまみむめも")
         );
         assert_eq!(
@@ -48,6 +122,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn display_width_test() {
+        assert_eq!(9, display_width("ソニー WH"));
+        assert_eq!("ソニ...", truncate_width("ソニー WH-1000XM5", 7));
+        assert_eq!("ソ...", truncate_width("ソニー WH-1000XM5", 6));
+        assert_eq!("ソニー  ", pad_right("ソニー", 8));
+        assert_eq!("  ￥100", pad_left("￥100", 7));
+    }
+
+    #[test]
+    fn format_jst_test() {
+        use chrono::TimeZone;
+        let datetime = Utc.with_ymd_and_hms(2024, 1, 1, 15, 30, 0).unwrap();
+        assert_eq!("2024/01/02 00:30", format_jst(&datetime, "%Y/%m/%d %H:%M"));
+    }
+
     #[test]
     fn name() {
         assert_eq!(commafy(0_u32), "0");
@@ -0,0 +1,470 @@
+//! Webhook による通知。
+//!
+//! 更新で生じたイベントを、登録した URL へ POST で送る。
+//! 本文は既定ではイベントごとの JSON で、テンプレートを指定したときは `{{name}}` のような項目を置き換えたもの。
+//! チャット向けの形式（Slack, Discord, Mattermost）のときは、1回の更新のイベントを1通のメッセージにまとめる。
+//! 秘密鍵を指定したときは、本文の HMAC-SHA256 を `X-Yodoprice-Signature` ヘッダに付ける。
+//! 接続できなかったときと、サーバのエラー（5xx, 429）のときは、間隔を倍にしながら再送する。
+
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::chat::{chat_messages, WebhookFormat};
+use crate::notify::{Event, EventKind};
+
+/// 本文の署名を付けるヘッダ
+pub const SIGNATURE_HEADER: &str = "X-Yodoprice-Signature";
+/// イベントの種類を付けるヘッダ
+pub const EVENT_HEADER: &str = "X-Yodoprice-Event";
+
+/// 通知先の Webhook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    /// 名称
+    pub name: String,
+    /// 送信先の URL
+    pub url: String,
+    /// 本文の形式
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// 署名に使う秘密鍵。None のときは署名しない。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// 本文のテンプレート。None のときはイベントの JSON をそのまま送る。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// 送るイベントの種類。空のときは全ての種類を送る。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventKind>,
+    /// 失敗したときに再送する回数
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+impl Webhook {
+    pub fn new(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            format: WebhookFormat::Json,
+            secret: None,
+            template: None,
+            events: vec![],
+            retries: default_retries(),
+        }
+    }
+
+    /// 指定した種類のイベントを送るかどうかを返す。
+    pub fn accepts(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+
+    /// 送るイベントから、送信するメッセージの一覧を作る。
+    pub fn messages(&self, events: &[Event]) -> Result<Vec<Message>, WebhookError> {
+        let events = events
+            .iter()
+            .filter(|v| self.accepts(v.event))
+            .cloned()
+            .collect::<Vec<_>>();
+        if self.format.is_chat() {
+            let messages = chat_messages(self.format, &events);
+            return Ok(messages
+                .into_iter()
+                .map(|body| Message { event: None, body })
+                .collect());
+        }
+        events
+            .iter()
+            .map(|v| {
+                Ok(Message {
+                    event: Some(v.event),
+                    body: self.body(v)?,
+                })
+            })
+            .collect()
+    }
+
+    /// 1件のイベントを JSON の形式で送るときの本文を返す。
+    pub fn body(&self, event: &Event) -> Result<String, WebhookError> {
+        match &self.template {
+            Some(template) => render_template(template, &serde_json::to_value(event).unwrap()),
+            None => Ok(serde_json::to_string(event).unwrap()),
+        }
+    }
+
+    /// URL 以外の設定の説明を返す。
+    pub fn describe_options(&self) -> String {
+        let mut parts = vec![format!("形式: {}", self.format.key())];
+        if !self.events.is_empty() {
+            let events = self.events.iter().map(|v| v.key()).collect::<Vec<_>>();
+            parts.push(format!("イベント: {}", events.join(",")));
+        }
+        if self.secret.is_some() {
+            parts.push("署名あり".to_string());
+        }
+        if self.template.is_some() {
+            parts.push("テンプレートあり".to_string());
+        }
+        parts.push(format!("再送: {}回", self.retries));
+        parts.join("、")
+    }
+}
+
+/// 1回の送信の内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// イベントの種類。複数のイベントをまとめたときは None
+    pub event: Option<EventKind>,
+    pub body: String,
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// Webhook の送信時のエラー
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookError {
+    /// テンプレートに不明な項目がある
+    UnknownField(String),
+    /// サーバがエラーを返した
+    Status(u16),
+    /// 接続できなかった等
+    Request(String),
+}
+
+impl std::error::Error for WebhookError {}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookError::UnknownField(name) => {
+                write!(f, "テンプレートの項目 `{}` はありません", name)
+            }
+            WebhookError::Status(code) => write!(f, "サーバがエラー {} を返しました", code),
+            WebhookError::Request(message) => write!(f, "送信できませんでした: {}", message),
+        }
+    }
+}
+
+/// テンプレートの `{{name}}` をイベントの項目の値に置き換える。`{{payload}}` はイベントの JSON 全体にする。
+/// 文字列は JSON の文字列の中に書けるようにエスケープし、値がないときは空にする。
+pub fn render_template(template: &str, value: &serde_json::Value) -> Result<String, WebhookError> {
+    let re = Regex::new(r"\{\{\s*([a-z_]+)\s*\}\}").unwrap();
+    let mut result = String::new();
+    let mut last = 0;
+    for caps in re.captures_iter(template) {
+        let whole = caps.get(0).unwrap();
+        let name = &caps[1];
+        let replaced = match (name, value.get(name)) {
+            ("payload", _) => value.to_string(),
+            (_, Some(serde_json::Value::String(s))) => {
+                let quoted = serde_json::to_string(s).unwrap();
+                quoted[1..quoted.len() - 1].to_string()
+            }
+            (_, Some(serde_json::Value::Null)) => String::new(),
+            (_, Some(v)) => v.to_string(),
+            (_, None) => return Err(WebhookError::UnknownField(name.to_string())),
+        };
+        result.push_str(&template[last..whole.start()]);
+        result.push_str(&replaced);
+        last = whole.end();
+    }
+    result.push_str(&template[last..]);
+    Ok(result)
+}
+
+/// 本文の署名（`sha256=` に続けて HMAC-SHA256 を16進数で表したもの）を返す。
+pub fn signature(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    let hex = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|v| format!("{:02x}", v))
+        .collect::<String>();
+    format!("sha256={}", hex)
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// Webhook の送信を行う。
+pub struct Sender {
+    client: reqwest::blocking::Client,
+    /// 最初に再送するまでの待ち時間。再送するたびに倍にする。
+    backoff: Duration,
+}
+
+impl Sender {
+    pub fn new() -> Self {
+        Self::with_backoff(Duration::from_secs(1))
+    }
+
+    pub fn with_backoff(backoff: Duration) -> Self {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        Self { client, backoff }
+    }
+
+    /// メッセージを Webhook へ送る。失敗したときは、設定された回数まで再送する。
+    pub fn send(&self, hook: &Webhook, message: &Message) -> Result<(), WebhookError> {
+        let mut wait = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match self.post(hook, message) {
+                Err(e) if attempt < hook.retries && is_retryable(&e) => {
+                    std::thread::sleep(wait);
+                    wait *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn post(&self, hook: &Webhook, message: &Message) -> Result<(), WebhookError> {
+        let mut request = self
+            .client
+            .post(&hook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(message.body.clone());
+        if let Some(kind) = message.event {
+            request = request.header(EVENT_HEADER, kind.key());
+        }
+        if let Some(secret) = &hook.secret {
+            request = request.header(SIGNATURE_HEADER, signature(secret, &message.body));
+        }
+        let response = request
+            .send()
+            .map_err(|e| WebhookError::Request(e.to_string()))?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(WebhookError::Status(response.status().as_u16()))
+        }
+    }
+}
+
+/// 再送すれば成功する見込みのあるエラーかどうかを返す。
+fn is_retryable(error: &WebhookError) -> bool {
+    match error {
+        WebhookError::Status(code) => *code >= 500 || *code == 429,
+        WebhookError::Request(_) => true,
+        WebhookError::UnknownField(_) => false,
+    }
+}
+
+/// 全ての Webhook へ、それぞれが受け付けるイベントを送る。送信に失敗したもののメッセージを返す。
+pub fn notify_all(hooks: &[Webhook], events: &[Event]) -> Vec<String> {
+    if hooks.is_empty() || events.is_empty() {
+        return vec![];
+    }
+    let sender = Sender::new();
+    let mut errors = vec![];
+    for hook in hooks.iter() {
+        let messages = match hook.messages(events) {
+            Ok(x) => x,
+            Err(e) => {
+                errors.push(format!(
+                    "Webhook「{}」へ通知を送れませんでした（{}）",
+                    hook.name, e
+                ));
+                continue;
+            }
+        };
+        let failed = messages
+            .iter()
+            .filter_map(|v| sender.send(hook, v).err())
+            .collect::<Vec<_>>();
+        if let Some(e) = failed.last() {
+            errors.push(format!(
+                "Webhook「{}」へ {}件中{}件の通知を送れませんでした（{}）",
+                hook.name,
+                messages.len(),
+                failed.len(),
+                e
+            ));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use super::*;
+
+    /// 受け取った HTTP リクエスト
+    #[derive(Debug)]
+    pub struct Received {
+        pub headers: Vec<(String, String)>,
+        pub body: String,
+    }
+
+    impl Received {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// ローカルで HTTP サーバを起動し、リクエストを受けるたびに `statuses` の順にステータスを返す。
+    /// URL と、全て受け終えたときに受け取ったリクエストを返すスレッドを返す。
+    pub fn serve(statuses: Vec<u16>) -> (String, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut received = vec![];
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((k, v)) = line.split_once(':') {
+                        headers.push((k.trim().to_string(), v.trim().to_string()));
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+                    .map_or(0, |(_, v)| v.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                received.push(Received {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+            }
+            received
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn render_template_test() {
+        let mut event = Event::sample(EventKind::Alert);
+        event.name = "say \"hi\"".to_string();
+        event.old_status = None;
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            Ok(r#"{"text": "say \"hi\": 27800 (status: Limited, old: )"}"#.to_string()),
+            render_template(
+                r#"{"text": "{{name}}: {{ price }} (status: {{status}}, old: {{old_status}})"}"#,
+                &value
+            )
+        );
+        assert_eq!(
+            Ok(format!(r#"{{"data": {}}}"#, value)),
+            render_template(r#"{"data": {{payload}}}"#, &value)
+        );
+        assert_eq!(
+            Err(WebhookError::UnknownField("prise".to_string())),
+            render_template("{{prise}}", &value)
+        );
+    }
+
+    #[test]
+    fn signature_test() {
+        // RFC 4231 のテストケース 2
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            signature("Jefe", "what do ya want for nothing?")
+        );
+    }
+
+    #[test]
+    fn send_test() {
+        let (url, server) = serve(vec![500, 503, 200]);
+        let mut hook = Webhook::new("test", &url);
+        hook.secret = Some("secret".to_string());
+        let event = Event::sample(EventKind::Alert);
+        let messages = hook.messages(std::slice::from_ref(&event)).unwrap();
+        assert_eq!(1, messages.len());
+        let sender = Sender::with_backoff(Duration::from_millis(10));
+        assert_eq!(Ok(()), sender.send(&hook, &messages[0]));
+
+        // 5xx のときは再送する。全て同じ本文・署名で送る。
+        let received = server.join().unwrap();
+        assert_eq!(3, received.len());
+        let body = hook.body(&event).unwrap();
+        for r in received.iter() {
+            assert_eq!(body, r.body);
+            assert_eq!(Some("alert"), r.header(EVENT_HEADER));
+            let expected = signature("secret", &body);
+            assert_eq!(Some(expected.as_str()), r.header(SIGNATURE_HEADER));
+        }
+        let value: serde_json::Value = serde_json::from_str(&received[0].body).unwrap();
+        assert_eq!("sample", value["product_id"]);
+        assert_eq!(30000, value["old_price"]);
+        assert_eq!("price < 28000", value["rule"]);
+    }
+
+    #[test]
+    fn send_failure_test() {
+        // 4xx のときは再送しない。
+        let (url, server) = serve(vec![404]);
+        let hook = Webhook::new("test", &url);
+        let sender = Sender::with_backoff(Duration::from_millis(10));
+        let message = &hook.messages(&[Event::sample(EventKind::Change)]).unwrap()[0];
+        assert_eq!(Err(WebhookError::Status(404)), sender.send(&hook, message));
+        assert_eq!(1, server.join().unwrap().len());
+
+        // 再送の回数を超えたときは、最後のエラーを返す。
+        let (url, server) = serve(vec![500, 500]);
+        let mut hook = Webhook::new("test", &url);
+        hook.retries = 1;
+        assert_eq!(Err(WebhookError::Status(500)), sender.send(&hook, message));
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn chat_format_test() {
+        // チャット向けの形式では、受け付ける種類のイベントを1通にまとめて送る。
+        let (url, server) = serve(vec![200]);
+        let mut hook = Webhook::new("chat", &url);
+        hook.format = WebhookFormat::Slack;
+        hook.events = vec![EventKind::Change];
+        let mut other = Event::sample(EventKind::Change);
+        other.product_id = "other".to_string();
+        let events = [
+            Event::sample(EventKind::Change),
+            Event::sample(EventKind::Alert),
+            other,
+        ];
+        assert!(notify_all(&[hook], &events).is_empty());
+
+        let received = server.join().unwrap();
+        assert_eq!(1, received.len());
+        assert_eq!(None, received[0].header(EVENT_HEADER));
+        let value: serde_json::Value = serde_json::from_str(&received[0].body).unwrap();
+        let text = value["text"].as_str().unwrap();
+        assert_eq!(3, text.lines().count());
+        assert!(text.starts_with("*価格の更新：変化 2件*"));
+        assert!(!text.contains("🔔"));
+    }
+}